@@ -1,12 +1,16 @@
-use std::time::{Duration, Instant};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 
 use crate::{
+    character_edit::CharacterEditState,
     character_select::CharacterSelectState,
     chat::ChatState,
     chatlog::ChatLog,
-    config::{CharacterFileYaml, ConfigurationFile},
+    config::{CharacterFileYaml, ConfigurationFile, ConfiguredParameters, ParameterOverrides},
     llm_engine,
     log_select::LogSelectState,
     main_menu::MainMenuState,
@@ -18,8 +22,14 @@ use crate::{
 pub enum ApplicationState {
     MainMenu,
     CharacterSelect,
+
+    // edits the character file at the path, or makes a new character if there isn't one
+    CharacterEdit(Option<PathBuf>),
     CharacterLogSelect(CharacterFileYaml),
     Chat(CharacterFileYaml, ChatLog),
+
+    // switches the chat over to the chatlog that was open before the current one
+    PreviousChat,
 }
 
 // This is the main application state object for the app.
@@ -31,6 +41,19 @@ pub struct Application<'a> {
     // our active configuration file for the app, loaded
     config: ConfigurationFile,
 
+    // the configuration file and profile paths given on the command line, used again when
+    // the configuration gets reloaded
+    config_filepath: Option<String>,
+    profile_filepath: Option<String>,
+
+    // the inference parameters new chats start with; built from the configuration
+    // and any overrides passed on the command line.
+    startup_parameters: ConfiguredParameters,
+
+    // the parameter set and sampler settings given on the command line, which take
+    // precedence over the parameter set a chat would otherwise pick
+    parameter_overrides: ParameterOverrides,
+
     // the LLM engine worker thread controller
     pub engine: llm_engine::LlmEngine,
 
@@ -43,28 +66,55 @@ pub struct Application<'a> {
     // contains the character select scene's state
     character_select_state: Option<CharacterSelectState>,
 
+    // optionally contains the character editor scene's state
+    character_edit_state: Option<CharacterEditState>,
+
     // optionally contains the log selector scene's state
     log_select_state: Option<LogSelectState>,
 
     // optionally contains the chat scene's state
     chat_state: Option<ChatState>,
+
+    // the name of the model configuration the engine has loaded, carried over between chats
+    active_model_name: String,
+
+    // the character and chatlog file of the chat that's open, or was open last
+    current_chat_log: Option<(CharacterFileYaml, PathBuf)>,
+
+    // the character and chatlog file of the chat open before `current_chat_log`, which
+    // is where the chat goes when asked to switch to the previous log
+    previous_chat_log: Option<(CharacterFileYaml, PathBuf)>,
 }
 impl<'a> Application<'a> {
     // Creates a new Application object.
     pub fn new(
         terminal: &'a mut Tui,
         config: ConfigurationFile,
+        config_filepath: Option<String>,
+        profile_filepath: Option<String>,
+        startup_parameters: ConfiguredParameters,
+        parameter_overrides: ParameterOverrides,
         engine: llm_engine::LlmEngine,
     ) -> Application<'a> {
+        let active_model_name = engine.startup_model_name.clone();
+        let mainmenu_state = MainMenuState::new(config.confirm_on_quit.unwrap_or(true));
         Application {
             terminal,
             config,
+            config_filepath,
+            profile_filepath,
+            startup_parameters,
+            parameter_overrides,
             engine,
             current_state: ApplicationState::MainMenu,
-            mainmenu_state: MainMenuState::default(),
+            mainmenu_state,
             character_select_state: None,
+            character_edit_state: None,
             log_select_state: None,
             chat_state: None,
+            active_model_name,
+            current_chat_log: None,
+            previous_chat_log: None,
         }
     }
 
@@ -96,6 +146,16 @@ impl<'a> Application<'a> {
                         proc_result = self.terminal.process_input(charselect);
                     }
                 }
+                ApplicationState::CharacterEdit(_) => {
+                    if let Some(charedit) = self.character_edit_state.as_mut() {
+                        if perform_draw {
+                            self.terminal
+                                .draw(charedit)
+                                .expect("failed to draw the character editor UI");
+                        }
+                        proc_result = self.terminal.process_input(charedit);
+                    }
+                }
                 ApplicationState::CharacterLogSelect(_) => {
                     if let Some(logselect) = self.log_select_state.as_mut() {
                         if perform_draw {
@@ -106,6 +166,7 @@ impl<'a> Application<'a> {
                         proc_result = self.terminal.process_input(logselect);
                     }
                 }
+                ApplicationState::PreviousChat => {}
                 ApplicationState::Chat(_, _) => {
                     if let Some(chat_state) = self.chat_state.as_mut() {
                         if perform_draw {
@@ -128,6 +189,30 @@ impl<'a> Application<'a> {
                     return Ok(());
                 }
                 ProcessInputResult::ChangeScene(new_scene) => {
+                    // keep any parameter set edits from the chat for the next chat, and note
+                    // where the chatlog lives now in case it was saved somewhere new
+                    if let ApplicationState::Chat(_, _) = self.current_state {
+                        if let Some(chat_state) = self.chat_state.as_ref() {
+                            self.config.parameters = chat_state.get_parameter_sets().clone();
+                            if let (Some((_, current_fp)), Some(fp)) = (
+                                self.current_chat_log.as_mut(),
+                                chat_state.get_chatlog_filepath(),
+                            ) {
+                                *current_fp = fp.clone();
+                            }
+                        }
+                    }
+
+                    // switching to the previous chat means loading its log back up
+                    let new_scene = if new_scene == ApplicationState::PreviousChat {
+                        match self.load_previous_chat() {
+                            Some(scene) => scene,
+                            None => continue,
+                        }
+                    } else {
+                        new_scene
+                    };
+
                     // mark the new scene as current
                     self.current_state = new_scene;
 
@@ -135,25 +220,54 @@ impl<'a> Application<'a> {
                     match &self.current_state {
                         ApplicationState::MainMenu => {}
                         ApplicationState::CharacterSelect => {
-                            self.character_select_state = Some(CharacterSelectState::new());
+                            self.character_select_state =
+                                Some(CharacterSelectState::new(self.config.clone()));
+                        }
+                        ApplicationState::CharacterEdit(filepath) => {
+                            self.character_edit_state = Some(CharacterEditState::new(
+                                filepath.clone(),
+                                self.config.clone(),
+                            ));
                         }
                         ApplicationState::CharacterLogSelect(chararcter) => {
                             self.log_select_state =
                                 Some(LogSelectState::new(chararcter.clone(), self.config.clone()));
                         }
+                        ApplicationState::PreviousChat => {}
                         ApplicationState::Chat(character, chatlog) => {
-                            let params = self.config.parameters.first();
+                            // remember the last two chatlogs so they can be swapped between
+                            if let Some(fp) = chatlog.get_last_used_filepath() {
+                                let is_same_log = self
+                                    .current_chat_log
+                                    .as_ref()
+                                    .is_some_and(|(_, current_fp)| current_fp == fp);
+                                if !is_same_log {
+                                    self.previous_chat_log = self.current_chat_log.take();
+                                    self.current_chat_log =
+                                        Some((character.to_owned(), fp.to_owned()));
+                                }
+                            }
+
+                            // the old chat may have seen the engine switch models
+                            if let Some(chat_state) = self.chat_state.as_ref() {
+                                self.active_model_name =
+                                    chat_state.get_active_model_name().to_owned();
+                            }
                             self.chat_state = Some(ChatState::new(
                                 character.to_owned(),
                                 chatlog.to_owned(),
-                                params,
+                                Some(&self.startup_parameters),
+                                &self.parameter_overrides,
                                 self.config.clone(),
                                 self.engine.send_to_server.clone(),
+                                self.engine.send_cmd_to_server.clone(),
                                 self.engine.recv_on_client.clone(),
+                                self.active_model_name.clone(),
                             ));
                         }
                     }
                 }
+                ProcessInputResult::ReloadConfig => self.reload_config(),
                 ProcessInputResult::None => {}
             }
 
@@ -161,4 +275,68 @@ impl<'a> Application<'a> {
             std::thread::sleep(Duration::from_millis(2));
         }
     }
+
+    // reads the configuration file again and hands it to the engine and the open scenes. if
+    // it can't be read, the configuration already in use is kept. either way, the result is
+    // shown in the scene that asked for it.
+    fn reload_config(&mut self) {
+        let message = match ConfigurationFile::load_config(
+            self.config_filepath.as_ref(),
+            self.profile_filepath.as_ref(),
+        ) {
+            Ok(new_config) => {
+                if let Err(err) =
+                    self.engine
+                        .send_cmd_to_server
+                        .send(llm_engine::LlmEngineCommand::ReloadConfig(
+                            new_config.clone(),
+                        ))
+                {
+                    log::error!("Error sending the reload config command: {}", err);
+                }
+                self.mainmenu_state
+                    .apply_config(new_config.confirm_on_quit.unwrap_or(true));
+                if let Some(chat_state) = self.chat_state.as_mut() {
+                    chat_state.apply_config(new_config.clone());
+                }
+                self.config = new_config;
+                log::info!("Reloaded the configuration file.");
+                "Reloaded the configuration file.".to_owned()
+            }
+            Err(err) => {
+                log::error!("Failed to reload the configuration: {:#}", err);
+                format!(
+                    "The configuration couldn't be reloaded, so the current one is still used: {:#}",
+                    err
+                )
+            }
+        };
+        match self.current_state {
+            ApplicationState::Chat(_, _) => {
+                if let Some(chat_state) = self.chat_state.as_mut() {
+                    chat_state.show_message("Reload Config", &message);
+                }
+            }
+            _ => self.mainmenu_state.show_status(&message),
+        }
+    }
+
+    // loads the chatlog that was open before the current one and returns the chat scene for
+    // it. if there isn't one, or it can't be loaded, the chat shows why and None is returned.
+    fn load_previous_chat(&mut self) -> Option<ApplicationState> {
+        let message = match self.previous_chat_log.as_ref() {
+            Some((character, fp)) => match ChatLog::new_from_json(fp) {
+                Ok(chatlog) => return Some(ApplicationState::Chat(character.clone(), chatlog)),
+                Err(err) => {
+                    log::error!("Failed to load the previous chatlog ({:?}): {}", fp, err);
+                    format!("The previous chatlog could not be loaded: {}", err)
+                }
+            },
+            None => "There's no other chatlog opened this session to switch to yet.".to_owned(),
+        };
+        if let Some(chat_state) = self.chat_state.as_mut() {
+            chat_state.show_message("Switch Chatlogs", &message);
+        }
+        None
+    }
 }
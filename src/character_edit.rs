@@ -0,0 +1,447 @@
+use anyhow::{anyhow, Context};
+use crossterm::event::{KeyCode, KeyModifiers};
+use ratatui::{
+    prelude::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{List, ListItem, ListState, Paragraph},
+};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    character_select::CHARACTERS_FOLDER_NAME,
+    config::{CharacterFileYaml, ConfigurationFile},
+    tui::{
+        ConfirmModalWidget, Frame, MessageBoxModalWidget, ProcessInputResult, TerminalEvent,
+        TerminalRenderable, TextEditingBlockModalWidget,
+    },
+};
+
+// The fields of the character file that can be changed in the editor, in display order.
+#[derive(Clone, Copy, PartialEq)]
+enum CharacterField {
+    Name,
+    Description,
+    Greeting,
+    Context,
+    NameColor,
+    QuotesColor,
+    TextColor,
+    ProgressColor,
+}
+const CHARACTER_FIELDS: [CharacterField; 8] = [
+    CharacterField::Name,
+    CharacterField::Description,
+    CharacterField::Greeting,
+    CharacterField::Context,
+    CharacterField::NameColor,
+    CharacterField::QuotesColor,
+    CharacterField::TextColor,
+    CharacterField::ProgressColor,
+];
+impl CharacterField {
+    fn label(&self) -> &'static str {
+        match self {
+            CharacterField::Name => "Name",
+            CharacterField::Description => "Description",
+            CharacterField::Greeting => "Greeting",
+            CharacterField::Context => "Context",
+            CharacterField::NameColor => "Name color",
+            CharacterField::QuotesColor => "Quotes color",
+            CharacterField::TextColor => "Text color",
+            CharacterField::ProgressColor => "Progress color",
+        }
+    }
+
+    // the key the field is stored under in the character's yaml file
+    fn yaml_key(&self) -> &'static str {
+        match self {
+            CharacterField::Name => "name",
+            CharacterField::Description => "description",
+            CharacterField::Greeting => "greeting",
+            CharacterField::Context => "context",
+            CharacterField::NameColor => "name_rgb",
+            CharacterField::QuotesColor => "quotes_rgb",
+            CharacterField::TextColor => "text_rgb",
+            CharacterField::ProgressColor => "progress_secondary_rgb",
+        }
+    }
+
+    fn is_color(&self) -> bool {
+        matches!(
+            self,
+            CharacterField::NameColor
+                | CharacterField::QuotesColor
+                | CharacterField::TextColor
+                | CharacterField::ProgressColor
+        )
+    }
+
+    fn get_value(&self, character: &CharacterFileYaml) -> String {
+        let color = match self {
+            CharacterField::Name => return character.name.clone(),
+            CharacterField::Description => return character.description.clone(),
+            CharacterField::Greeting => return character.greeting.clone(),
+            CharacterField::Context => return character.context.clone(),
+            CharacterField::NameColor => character.name_rgb,
+            CharacterField::QuotesColor => character.quotes_rgb,
+            CharacterField::TextColor => character.text_rgb,
+            CharacterField::ProgressColor => character.progress_secondary_rgb,
+        };
+        color.map_or(String::new(), |[r, g, b]| format!("{}, {}, {}", r, g, b))
+    }
+}
+
+// parses a color typed as "r, g, b"; an empty string means the default color is used.
+fn parse_rgb(text: &str) -> Result<Option<[u8; 3]>, String> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let parts: Vec<Option<u8>> = text.split(',').map(|p| p.trim().parse().ok()).collect();
+    match parts.as_slice() {
+        [Some(r), Some(g), Some(b)] => Ok(Some([*r, *g, *b])),
+        _ => Err(format!(
+            "'{}' should be three numbers from 0 to 255, like \"25, 133, 161\".",
+            text
+        )),
+    }
+}
+
+pub struct CharacterEditState {
+    // the character file being edited, or None when making a new character
+    filepath: Option<PathBuf>,
+
+    // the text of each field in `CHARACTER_FIELDS`, in the same order
+    values: Vec<String>,
+
+    // the field that has the focus
+    field_index: usize,
+
+    // set once a field has been changed since the character was loaded or saved
+    has_unsaved_edits: bool,
+
+    // the editor for the field that has the focus, while it's being changed
+    field_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains a modal dialog widget used to show a message or alert to the user
+    modal_messagebox: Option<MessageBoxModalWidget>,
+
+    // asks before leaving the editor with unsaved edits
+    leave_confirm: Option<ConfirmModalWidget>,
+
+    // the application configuration, used for the editor's appearance
+    config: ConfigurationFile,
+}
+impl CharacterEditState {
+    pub fn new(filepath: Option<PathBuf>, config: ConfigurationFile) -> Self {
+        let character = match &filepath {
+            Some(fp) => CharacterFileYaml::load_character(fp),
+            None => CharacterFileYaml::default(),
+        };
+        let values = CHARACTER_FIELDS
+            .iter()
+            .map(|field| field.get_value(&character))
+            .collect();
+
+        Self {
+            filepath,
+            values,
+            field_index: 0,
+            has_unsaved_edits: false,
+            field_editor: None,
+            modal_messagebox: None,
+            leave_confirm: None,
+            config,
+        }
+    }
+
+    fn leave_editor(&self) -> ProcessInputResult {
+        ProcessInputResult::ChangeScene(crate::application::ApplicationState::CharacterSelect)
+    }
+
+    // opens the editor for the field that has the focus. the larger text fields can hold
+    // several lines, which are typed as "\n" since enter finishes the edit.
+    fn edit_focused_field(&mut self) {
+        let field = CHARACTER_FIELDS[self.field_index];
+        let title = if field.is_color() {
+            format!("{} as r, g, b (empty for the default):", field.label())
+        } else if field == CharacterField::Name {
+            format!("{}:", field.label())
+        } else {
+            format!("{} (\\n = new line):", field.label())
+        };
+        let text = self.values[self.field_index].replace('\n', "\\n");
+        self.field_editor = Some(TextEditingBlockModalWidget::new(title, text));
+    }
+
+    // checks the fields and writes them to the character's yaml file, returning where it was
+    // saved. any other settings already in the file are kept, but since the yaml gets
+    // reserialized, comments are lost, so an existing file is first copied next to it with
+    // a '.bak' extension.
+    fn save_character(&self) -> anyhow::Result<PathBuf> {
+        let name = self.values[0].trim();
+        if name.is_empty() {
+            return Err(anyhow!(
+                "The character needs a name before it can be saved."
+            ));
+        }
+        if name.contains(['/', '\\']) {
+            return Err(anyhow!("The character's name can't contain '/' or '\\'."));
+        }
+
+        let (filepath, mut yaml) = match &self.filepath {
+            Some(fp) => {
+                let plain_string = std::fs::read_to_string(fp)
+                    .context("Attempting to read the character file before saving")?;
+                let yaml: serde_yaml::Value = serde_yaml::from_str(plain_string.as_str())
+                    .context("Attempting to parse the character file before saving")?;
+                (fp.clone(), yaml)
+            }
+            None => {
+                let fp = Path::new(CHARACTERS_FOLDER_NAME).join(format!("{}.yaml", name));
+                if fp.exists() {
+                    return Err(anyhow!(
+                        "There's already a character file at {:?}; pick another name or edit that character instead.",
+                        fp
+                    ));
+                }
+                (fp, serde_yaml::Value::Mapping(serde_yaml::Mapping::new()))
+            }
+        };
+        let mapping = yaml
+            .as_mapping_mut()
+            .context("The character file isn't a yaml mapping")?;
+
+        for (field, value) in CHARACTER_FIELDS.iter().zip(self.values.iter()) {
+            let key = serde_yaml::Value::from(field.yaml_key());
+            if field.is_color() {
+                match parse_rgb(value).map_err(|err| anyhow!("{}: {}", field.label(), err))? {
+                    Some(rgb) => {
+                        mapping.insert(key, serde_yaml::Value::from(rgb.to_vec()));
+                    }
+                    None => {
+                        mapping.remove(&key);
+                    }
+                }
+            } else if *field == CharacterField::Name {
+                mapping.insert(key, serde_yaml::Value::from(name));
+            } else {
+                mapping.insert(key, serde_yaml::Value::from(value.as_str()));
+            }
+        }
+        let new_contents =
+            serde_yaml::to_string(&yaml).context("Attempting to serialize the character")?;
+
+        if filepath.exists() {
+            let mut backup_filepath = filepath.as_os_str().to_owned();
+            backup_filepath.push(".bak");
+            std::fs::copy(&filepath, &backup_filepath)
+                .context("Attempting to back up the character file")?;
+        }
+        std::fs::write(&filepath, new_contents)
+            .context("Attempting to write the character file")?;
+
+        Ok(filepath)
+    }
+}
+impl TerminalRenderable for CharacterEditState {
+    fn process_input(&mut self, event: TerminalEvent) -> ProcessInputResult {
+        if let Some(modal) = self.modal_messagebox.as_mut() {
+            modal.process_input(event);
+            if modal.is_finished {
+                self.modal_messagebox = None;
+            }
+        } else if let Some(confirm) = self.leave_confirm.as_mut() {
+            confirm.process_input(event);
+            if confirm.is_finished {
+                let confirmed = confirm.is_confirmed;
+                self.leave_confirm = None;
+                if confirmed {
+                    return self.leave_editor();
+                }
+            }
+        } else if let Some(editor) = self.field_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let text = editor.text.replace("\\n", "\n");
+                let accepted = editor.is_success;
+                self.field_editor = None;
+                if accepted {
+                    let field = CHARACTER_FIELDS[self.field_index];
+                    match parse_rgb(&text) {
+                        Err(err) if field.is_color() => {
+                            self.modal_messagebox =
+                                Some(MessageBoxModalWidget::new(field.label(), &err, 50, 20));
+                        }
+                        _ => {
+                            if self.values[self.field_index] != text {
+                                self.values[self.field_index] = text;
+                                self.has_unsaved_edits = true;
+                            }
+                        }
+                    }
+                }
+            }
+        } else if let TerminalEvent::Key(key) = event {
+            if key.code == KeyCode::Esc {
+                if !self.has_unsaved_edits {
+                    return self.leave_editor();
+                }
+                self.leave_confirm = Some(ConfirmModalWidget::new(
+                    "Unsaved Edits",
+                    "Leave the character editor without saving?",
+                    50,
+                ));
+            } else if key.code == KeyCode::Char('k') || key.code == KeyCode::Up {
+                self.field_index = self.field_index.saturating_sub(1);
+            } else if key.code == KeyCode::Char('j')
+                || key.code == KeyCode::Down
+                || key.code == KeyCode::Tab
+            {
+                self.field_index = (self.field_index + 1).min(CHARACTER_FIELDS.len() - 1);
+            } else if key.code == KeyCode::Enter {
+                self.edit_focused_field();
+            } else if key.code == KeyCode::Char('s')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                match self.save_character() {
+                    Ok(filepath) => {
+                        log::info!("Saved the character file to {:?}", filepath);
+                        return self.leave_editor();
+                    }
+                    Err(err) => {
+                        log::error!("Failed to save the character: {:#}", err);
+                        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                            "Save Failed",
+                            format!("{:#}", err).as_str(),
+                            60,
+                            30,
+                        ));
+                    }
+                }
+            } else if key.code == KeyCode::Char('?') {
+                let help_strings = "j/down = move to the next field\n\
+                                    k/up   = move to the previous field\n\
+                                    enter  = edit the selected field\n\
+                                    ctrl-s = save the character and go back\n\
+                                    esc    = go back to the character select\n\
+                                    \n\
+                                    Colors are typed as r, g, b (e.g. 25, 133, 161) or left empty for the default.\n\
+                                    Chatlogs are kept in a folder named after the character, so renaming one starts a new set of chatlogs.\n";
+                let modal = MessageBoxModalWidget::new("Command Reference:", help_strings, 60, 60);
+                self.modal_messagebox = Some(modal);
+            }
+        }
+
+        ProcessInputResult::None
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let title = if self.filepath.is_some() {
+            "Character Editor"
+        } else {
+            "New Character"
+        };
+        let menu_lines = vec![Line::from(title.bold()), Line::from("----------------")];
+
+        let label_width = CHARACTER_FIELDS
+            .iter()
+            .map(|field| field.label().len())
+            .max()
+            .unwrap_or_default();
+        let items: Vec<ListItem> = CHARACTER_FIELDS
+            .iter()
+            .zip(self.values.iter())
+            .map(|(field, value)| {
+                let mut spans = vec![Span::raw(format!(
+                    "{:width$}  ",
+                    field.label(),
+                    width = label_width
+                ))];
+                if field.is_color() {
+                    match parse_rgb(value) {
+                        Ok(Some([r, g, b])) => {
+                            spans.push(Span::styled(
+                                "\u{25a0}\u{25a0}\u{25a0} ",
+                                Style::default().fg(Color::Rgb(r, g, b)),
+                            ));
+                            spans.push(Span::raw(value.as_str()));
+                        }
+                        _ => spans.push(Span::styled(
+                            "default",
+                            Style::default().fg(Color::Rgb(100, 100, 100)),
+                        )),
+                    }
+                } else {
+                    // only the start of the first line fits next to the label
+                    let first_line = value.lines().next().unwrap_or_default();
+                    let preview: String = first_line.chars().take(60).collect();
+                    if preview.len() < value.len() {
+                        spans.push(Span::raw(format!("{}...", preview)));
+                    } else {
+                        spans.push(Span::raw(preview));
+                    }
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let items = List::new(items)
+            .highlight_style(self.config.get_selection_highlight_style())
+            .highlight_symbol(self.config.get_selection_highlight_symbol());
+
+        let hchunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                [
+                    Constraint::Percentage(10),
+                    Constraint::Percentage(80),
+                    Constraint::Percentage(10),
+                ]
+                .as_ref(),
+            )
+            .split(frame.size());
+
+        let vchunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Percentage(20),
+                    Constraint::Max(2),
+                    Constraint::Length(CHARACTER_FIELDS.len() as u16 + 1),
+                    Constraint::Min(1),
+                ]
+                .as_ref(),
+            )
+            .split(hchunks[1]);
+
+        let title = Paragraph::new(menu_lines).alignment(Alignment::Center);
+        frame.render_widget(title, vchunks[1]);
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(self.field_index));
+        frame.render_stateful_widget(items, vchunks[2], &mut list_state);
+
+        let hint = if self.has_unsaved_edits {
+            "enter = edit, ctrl-s = save, esc = back (unsaved edits), ? = help"
+        } else {
+            "enter = edit, ctrl-s = save, esc = back, ? = help"
+        };
+        let hint = Paragraph::new(Line::from(Span::styled(
+            hint,
+            Style::default().fg(Color::Rgb(100, 100, 100)),
+        )))
+        .alignment(Alignment::Center);
+        frame.render_widget(hint, vchunks[3]);
+
+        // Now render any modal boxes over the form, only selecting one of them to draw.
+        // This *should* mimic the same order that input processing gets called so that
+        // there's no confusion.
+        if let Some(modal) = &self.modal_messagebox {
+            modal.render(frame);
+        } else if let Some(confirm) = &self.leave_confirm {
+            confirm.render(frame);
+        } else if let Some(editor) = &self.field_editor {
+            editor.render(frame);
+        }
+    }
+}
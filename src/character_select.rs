@@ -1,21 +1,21 @@
-use crossterm::event::KeyCode;
+use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
     prelude::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Modifier, Style, Stylize},
+    style::{Style, Stylize},
     text::Line,
     widgets::{List, ListItem, Paragraph},
 };
 use std::path::{Path, PathBuf};
 
 use crate::{
-    config::CharacterFileYaml,
+    config::{CharacterFileYaml, ConfigurationFile},
     tui::{
         Frame, MessageBoxModalWidget, ProcessInputResult, StatefulList, TerminalEvent,
         TerminalRenderable,
     },
 };
 
-const CHARACTERS_FOLDER_NAME: &str = "characters";
+pub const CHARACTERS_FOLDER_NAME: &str = "characters";
 
 pub struct CharacterSelectState {
     character_names: Vec<(String, PathBuf)>,
@@ -25,6 +25,9 @@ pub struct CharacterSelectState {
 
     // contains a modal dialog widget used to show a message or alert to the user
     modal_messagebox: Option<MessageBoxModalWidget>,
+
+    // the application configuration, used for the selector's appearance
+    config: ConfigurationFile,
 }
 impl TerminalRenderable for CharacterSelectState {
     fn process_input(&mut self, event: TerminalEvent) -> ProcessInputResult {
@@ -38,6 +41,24 @@ impl TerminalRenderable for CharacterSelectState {
                 return ProcessInputResult::ChangeScene(
                     crate::application::ApplicationState::MainMenu,
                 );
+            } else if key.code == KeyCode::Char('n')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                // ctrl + n makes a new character
+                return ProcessInputResult::ChangeScene(
+                    crate::application::ApplicationState::CharacterEdit(None),
+                );
+            } else if key.code == KeyCode::Char('e')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                // ctrl + e edits the selected character
+                if let Some(sel_index) = self.list_state.state.selected() {
+                    return ProcessInputResult::ChangeScene(
+                        crate::application::ApplicationState::CharacterEdit(Some(
+                            self.character_names[sel_index].1.clone(),
+                        )),
+                    );
+                }
             } else if key.code == KeyCode::Char('k') {
                 self.list_state.previous()
             } else if key.code == KeyCode::Char('j') {
@@ -46,6 +67,8 @@ impl TerminalRenderable for CharacterSelectState {
                 let help_strings = "j      = move down\n\
                                     k      = move up\n\
                                     enter  = load selected character\n\
+                                    ctrl-n = make a new character\n\
+                                    ctrl-e = edit the selected character\n\
                                     esc    = go back to main menu\n";
 
                 // show the dialog to create a new log
@@ -93,18 +116,13 @@ impl TerminalRenderable for CharacterSelectState {
             .unwrap()
             .width();
 
-        // TODO: allow customization of 'highlight color'
         let items = List::new(items)
-            .highlight_style(
-                Style::default()
-                    .fg(Color::LightGreen)
-                    .add_modifier(Modifier::BOLD),
-            )
-            .highlight_symbol(">> ");
+            .highlight_style(self.config.get_selection_highlight_style())
+            .highlight_symbol(self.config.get_selection_highlight_symbol());
 
         // break things up horizontally to create some padding
         let middle_column_size = 3 + max_width.max(divider_len) as u16;
-        let padding_size = (frame.size().width - middle_column_size) / 2;
+        let padding_size = frame.size().width.saturating_sub(middle_column_size) / 2;
         let hchunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints(
@@ -145,7 +163,7 @@ impl TerminalRenderable for CharacterSelectState {
     }
 }
 impl CharacterSelectState {
-    pub fn new() -> Self {
+    pub fn new(config: ConfigurationFile) -> Self {
         let mut character_names: Vec<(String, PathBuf)> = Vec::new();
         let mut list_items = vec![];
 
@@ -179,6 +197,7 @@ impl CharacterSelectState {
             character_names,
             list_state,
             modal_messagebox: None,
+            config,
         }
     }
 }
@@ -5,19 +5,273 @@ use rand::prelude::*;
 use ratatui::prelude::{Alignment, Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Style, Stylize};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Chart, Clear, Dataset, Paragraph, Sparkline};
+use ratatui::widgets::{
+    Block, Borders, Chart, Clear, Dataset, List, ListItem, Paragraph, Sparkline,
+};
+use regex::Regex;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::chatlog::{ChatLog, ChatLogItem};
+use crate::chatlog::{
+    append_to_journal, backup_json_file, clear_journal, collapse_blank_lines, get_journal_filepath,
+    ChatLog, ChatLogItem, SimilarSentenceMatch, DEFAULT_LOG_BACKUP_COUNT,
+};
 use crate::config::*;
 use crate::llm_engine::TextInferenceContext;
-use crate::llm_engine::{self, LlmEngineRequest, LlmEngineResponse};
+use crate::llm_engine::{self, LlmEngineCommand, LlmEngineRequest, LlmEngineResponse};
 use crate::tui::{
-    centered_rect, slice_up_string, Frame, MessageBoxModalWidget, ProcessInputResult,
-    TerminalEvent, TerminalRenderable, TextEditingBlockModalWidget,
+    centered_rect, find_wrapped_position, is_compact_layout, slice_up_string, ConfirmModalWidget,
+    Frame, MessageBoxModalWidget, ProcessInputResult, StatefulList, TerminalEvent,
+    TerminalRenderable, TextEditingBlockModalWidget,
 };
 
+// the word that has to be typed to confirm clearing the whole chat
+const CLEAR_CHAT_CONFIRMATION: &str = "clear";
+
+// how long after the user last scrolled the chatlog that a new message won't scroll it back
+const AUTOSCROLL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// how full the context has to get, in percent, before the token usage estimate turns yellow
+// and then red, as a warning that older turns are about to be left out of the prompt
+const TOKEN_USAGE_WARNING_PERCENT: usize = 75;
+const TOKEN_USAGE_DANGER_PERCENT: usize = 90;
+
+// The sampler settings that can be changed in the parameters modal, in display order.
+#[derive(Clone, Copy, PartialEq)]
+enum EditableParameter {
+    Temperature,
+    TopK,
+    TopP,
+    MinP,
+    RepeatPenalty,
+    RepeatPenaltyRange,
+    Mirostat,
+    MirostatEta,
+    MirostatTau,
+}
+const EDITABLE_PARAMETERS: [EditableParameter; 9] = [
+    EditableParameter::Temperature,
+    EditableParameter::TopK,
+    EditableParameter::TopP,
+    EditableParameter::MinP,
+    EditableParameter::RepeatPenalty,
+    EditableParameter::RepeatPenaltyRange,
+    EditableParameter::Mirostat,
+    EditableParameter::MirostatEta,
+    EditableParameter::MirostatTau,
+];
+impl EditableParameter {
+    fn label(&self) -> &'static str {
+        match self {
+            EditableParameter::Temperature => "temperature",
+            EditableParameter::TopK => "top k",
+            EditableParameter::TopP => "top p",
+            EditableParameter::MinP => "min p",
+            EditableParameter::RepeatPenalty => "repeat penalty",
+            EditableParameter::RepeatPenaltyRange => "repeat range",
+            EditableParameter::Mirostat => "mirostat",
+            EditableParameter::MirostatEta => "mirostat eta",
+            EditableParameter::MirostatTau => "mirostat tau",
+        }
+    }
+
+    fn value_string(&self, params: &ConfiguredParameters) -> Option<String> {
+        match self {
+            EditableParameter::Temperature => params.temperature.map(|v| v.to_string()),
+            EditableParameter::TopK => params.top_k.map(|v| v.to_string()),
+            EditableParameter::TopP => params.top_p.map(|v| v.to_string()),
+            EditableParameter::MinP => params.min_p.map(|v| v.to_string()),
+            EditableParameter::RepeatPenalty => params.repeat_penalty.map(|v| v.to_string()),
+            EditableParameter::RepeatPenaltyRange => {
+                params.repeat_penalty_range.map(|v| v.to_string())
+            }
+            EditableParameter::Mirostat => params.mirostat.map(|v| v.to_string()),
+            EditableParameter::MirostatEta => params.mirostat_eta.map(|v| v.to_string()),
+            EditableParameter::MirostatTau => params.mirostat_tau.map(|v| v.to_string()),
+        }
+    }
+
+    // moves the value by `steps` increments while keeping it within the range the backends
+    // accept. an unset value is first set to a typical starting value instead.
+    fn adjust(&self, params: &mut ConfiguredParameters, steps: i32) {
+        match self {
+            EditableParameter::Temperature => {
+                params.temperature = step_f32(params.temperature, 0.7, 0.05, steps, 0.0, 5.0)
+            }
+            EditableParameter::TopK => {
+                params.top_k = step_usize(params.top_k, 40, 5, steps, 0, 200)
+            }
+            EditableParameter::TopP => {
+                params.top_p = step_f32(params.top_p, 0.9, 0.05, steps, 0.0, 1.0)
+            }
+            EditableParameter::MinP => {
+                params.min_p = step_f32(params.min_p, 0.05, 0.01, steps, 0.0, 1.0)
+            }
+            EditableParameter::RepeatPenalty => {
+                params.repeat_penalty = step_f32(params.repeat_penalty, 1.1, 0.01, steps, 0.01, 3.0)
+            }
+            EditableParameter::RepeatPenaltyRange => {
+                params.repeat_penalty_range =
+                    step_usize(params.repeat_penalty_range, 512, 64, steps, 0, 8192)
+            }
+            EditableParameter::Mirostat => {
+                params.mirostat = step_usize(params.mirostat, 0, 1, steps, 0, 2)
+            }
+            EditableParameter::MirostatEta => {
+                params.mirostat_eta = step_f32(params.mirostat_eta, 0.1, 0.01, steps, 0.0, 1.0)
+            }
+            EditableParameter::MirostatTau => {
+                params.mirostat_tau = step_f32(params.mirostat_tau, 5.0, 0.1, steps, 0.0, 20.0)
+            }
+        }
+    }
+
+    // unsets the value so that the backend's default gets used
+    fn clear(&self, params: &mut ConfiguredParameters) {
+        match self {
+            EditableParameter::Temperature => params.temperature = None,
+            EditableParameter::TopK => params.top_k = None,
+            EditableParameter::TopP => params.top_p = None,
+            EditableParameter::MinP => params.min_p = None,
+            EditableParameter::RepeatPenalty => params.repeat_penalty = None,
+            EditableParameter::RepeatPenaltyRange => params.repeat_penalty_range = None,
+            EditableParameter::Mirostat => params.mirostat = None,
+            EditableParameter::MirostatEta => params.mirostat_eta = None,
+            EditableParameter::MirostatTau => params.mirostat_tau = None,
+        }
+    }
+}
+
+// returns the configured `user_narration_patterns` that compile; invalid patterns were
+// already reported when the configuration was validated
+fn compile_user_narration_patterns(config: &ConfigurationFile) -> Vec<Regex> {
+    config
+        .user_narration_patterns
+        .iter()
+        .flatten()
+        .filter_map(|pattern| Regex::new(pattern).ok())
+        .collect()
+}
+
+// picks the parameter set a chat starts with: the startup parameters, replaced by the
+// character's default set and then by the set last used with the chatlog. a parameter set
+// picked on the command line skips both of those, and the sampler settings given on the
+// command line are applied last so they always win.
+fn pick_starting_parameters(
+    config: &ConfigurationFile,
+    character: &CharacterFileYaml,
+    chatlog: &ChatLog,
+    inference_parameters: Option<&ConfiguredParameters>,
+    parameter_overrides: &ParameterOverrides,
+) -> ConfiguredParameters {
+    let current_parameters = inference_parameters.cloned().unwrap_or_default();
+
+    // the character may prefer a parameter set of its own, unless one was asked for on the
+    // command line
+    let default_parameters_name = match parameter_overrides.parameters_name {
+        Some(_) => None,
+        None => character.default_parameters_name.as_deref(),
+    };
+    let current_parameters = match default_parameters_name {
+        Some(name) => match config
+            .parameters
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+        {
+            Some(pset) => pset.clone(),
+            None => {
+                log::warn!(
+                    "The default parameter set '{}' for {} isn't configured; using '{}' instead.",
+                    name,
+                    character.name,
+                    current_parameters.name
+                );
+                current_parameters
+            }
+        },
+        None => current_parameters,
+    };
+
+    // pick up the parameter set last used with this log, if it's still configured and
+    // no set was asked for on the command line
+    let last_parameters_name = match parameter_overrides.parameters_name {
+        Some(_) => None,
+        None => chatlog.last_parameters_name.as_deref(),
+    };
+    let mut current_parameters = match last_parameters_name {
+        Some(name) => match config
+            .parameters
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+        {
+            Some(pset) => pset.clone(),
+            None => {
+                log::warn!(
+                    "The parameter set '{}' last used with this chatlog isn't configured anymore; using '{}' instead.",
+                    name,
+                    current_parameters.name
+                );
+                current_parameters
+            }
+        },
+        None => current_parameters,
+    };
+
+    parameter_overrides.apply(&mut current_parameters);
+    current_parameters
+}
+
+// describes how many of the oldest chatlog items were left out of the prompt to fit
+fn get_omitted_turns_text(omitted_turns: usize) -> String {
+    if omitted_turns == 1 {
+        "1 earlier message omitted from context".to_owned()
+    } else {
+        format!("{} earlier messages omitted from context", omitted_turns)
+    }
+}
+
+fn step_f32(
+    value: Option<f32>,
+    start: f32,
+    step: f32,
+    steps: i32,
+    min: f32,
+    max: f32,
+) -> Option<f32> {
+    let new_value = match value {
+        Some(v) => (v + step * steps as f32).clamp(min, max),
+        None => start,
+    };
+    // round off the float error so the values stay readable when displayed and saved
+    Some((new_value * 100.0).round() / 100.0)
+}
+
+fn step_usize(
+    value: Option<usize>,
+    start: usize,
+    step: usize,
+    steps: i32,
+    min: usize,
+    max: usize,
+) -> Option<usize> {
+    let new_value = match value {
+        Some(v) => {
+            let delta = step * steps.unsigned_abs() as usize;
+            if steps < 0 {
+                v.saturating_sub(delta)
+            } else {
+                v.saturating_add(delta)
+            }
+        }
+        None => start,
+    };
+    Some(new_value.clamp(min, max))
+}
+
 pub struct ChatState {
     // a copy of the configuration file passed into the UI at creation
     config: ConfigurationFile,
@@ -29,16 +283,35 @@ pub struct ChatState {
 
     chatlog: ChatLog,
     chatlog_scroll: usize,
+
+    // the time the user last scrolled the chatlog, used to hold off `autoscroll_on_new_message`
+    last_scrolled_at: Option<Instant>,
+
+    // how many chatlog items fit in the chatlog area the last time it was drawn, which is how
+    // far page up and page down scroll
+    last_visible_item_count: Cell<usize>,
+
     current_parameters: ConfiguredParameters,
+
+    // the sampler settings given on the command line, applied again whenever the parameter
+    // set gets refreshed from a reloaded configuration
+    parameter_overrides: ParameterOverrides,
     manual_reply_mode: bool,
 
+    // if false, sending a reply doesn't start generating a response outside of multi-chat
+    auto_generate_on_reply: bool,
+
     send_to_server: Sender<LlmEngineRequest>,
+    send_cmd_to_server: Sender<LlmEngineCommand>,
     recv_on_client: Receiver<LlmEngineResponse>,
 
     editing_reply: bool,
     editing_parameters: bool,
     reply_text: String,
 
+    // the byte index into `reply_text` where typing goes, always on a char boundary
+    reply_cursor: usize,
+
     waiting_for_operation: bool,
 
     // The character that is currently causing the `waiting_for_operation`
@@ -46,20 +319,167 @@ pub struct ChatState {
     // for. If set to None, that mean's it's the user.
     waiting_for_character: Option<CharacterFileYaml>,
 
+    // the text streamed in so far for the response being generated, shown at the top of the
+    // chatlog until the whole response arrives; if `in_flight_continues` is set, it's shown on
+    // the end of the last item since that's the one being continued.
+    in_flight_text: String,
+    in_flight_continues: bool,
+
+    // the byte offset into `in_flight_text` where each streamed fragment ends, kept so that
+    // the token boundaries can be shaded when `shade_streamed_tokens` is configured
+    in_flight_fragment_ends: Vec<usize>,
+
+    // set when streaming has been turned on or off in the chat, which wins over the
+    // `disable_response_streaming` configuration; true shows responses as they stream in
+    response_streaming_override: Option<bool>,
+
+    // showing the stream turned on or off for single participants in multi-chat mode, keyed by
+    // character name, which wins over `response_streaming_override` for their responses
+    participant_streaming_overrides: HashMap<String, bool>,
+
+    // how many lines back from the newest the response streaming in is scrolled, when it's
+    // taller than the chatlog area; 0 follows the text as it arrives
+    in_flight_scroll: usize,
+
     progress_widget: Option<ProgressBarScopeSignal>,
 
+    // describes what the engine is doing besides generating text, such as loading a model,
+    // so a pause before the response starts can be explained next to the progress bar.
+    engine_status: Option<String>,
+
+    // the name of the model configuration the engine has loaded, shown in the corner of the chat
+    active_model_name: String,
+
+    // the response taken off the chatlog to be regenerated, or cut short to have the rest of it
+    // regenerated; the new response keeps it as an alternative to switch back to, and it's put
+    // back if no new response arrives.
+    regenerated_item: Option<ChatLogItem>,
+
+    // how many times in a row the last response has been regenerated this session;
+    // reset whenever a brand new response is requested.
+    regenerate_count: usize,
+
     // contains a modal dialog widget used to show a message or alert to the user
     modal_messagebox: Option<MessageBoxModalWidget>,
 
+    // asks to confirm leaving the chat while a response is still being generated
+    leave_confirm: Option<ConfirmModalWidget>,
+
     // contains the modal dialog widget used to update the chatlog context
     context_editor: Option<TextEditingBlockModalWidget>,
 
     // contains the modal dialog widget used to update the user's description context
     userdesc_editor: Option<TextEditingBlockModalWidget>,
 
+    // contains the modal dialog widget used to update the tags for the chatlog
+    tags_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to enter a one-off instruction for a regeneration
+    nudge_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to name a copy of the current parameter set
+    parameters_name_editor: Option<TextEditingBlockModalWidget>,
+
+    // the list of every configured parameter set name, shown while browsing for one to use
+    parameters_browser: Option<StatefulList<String>>,
+
+    // set when the parameter set picked in `parameters_browser` should be used right away to
+    // regenerate the last response
+    regenerate_after_parameters_browser: bool,
+
+    // while candidate responses are being generated, how many are done out of how many
+    candidate_progress: Option<(usize, usize)>,
+
+    // contains the name of the speaker, the list of candidate responses to pick one from
+    // and the sentence similarity matches used for their prompt
+    candidate_chooser: Option<(
+        String,
+        StatefulList<String>,
+        Option<Vec<SimilarSentenceMatch>>,
+    )>,
+
+    // set to true to highlight the chatlog items that were matched by sentence similarity
+    // when the selected item was generated
+    show_similar_matches: bool,
+
+    // the index into EDITABLE_PARAMETERS of the field selected in the parameters modal
+    parameters_field_index: usize,
+
     // contains the modal dialog widget used to update the chatlog item that
     // is 'current' - as determined by the 'chatlog_scroll` member
     logitem_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to pick a new filepath to save the chatlog to
+    saveas_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to name a new chatlog for the character that
+    // the current chat gets saved into, branching it off from the current log
+    newlog_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to pick the dataset file that the selected
+    // exchange gets appended to, along with the index of the selected chatlog item
+    dataset_editor: Option<(usize, TextEditingBlockModalWidget)>,
+
+    // contains the modal dialog widget used to confirm clearing the whole chat
+    clear_chat_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to enter the text to search the chatlog for
+    find_editor: Option<TextEditingBlockModalWidget>,
+
+    // contains the modal dialog widget used to edit the instruct template of the active model
+    // configuration, with its newlines written as "\n" so that they can be typed
+    instruct_template_editor: Option<TextEditingBlockModalWidget>,
+
+    // the text the chatlog was last searched for; items containing it are highlighted and
+    // 'n'/'N' jump between them until an empty search clears it
+    find_text: Option<String>,
+
+    // the dataset file name last used for appending an exchange, offered again next time
+    last_dataset_filename: String,
+
+    // set to true when the last attempt to save the chatlog failed; this drives the sticky
+    // warning in the UI and is cleared once a save succeeds again.
+    last_save_failed: bool,
+
+    // set to leave the similar sentences out of the next generation; if pinned, they stay
+    // left out until it's turned off again
+    skip_similar_sentences: bool,
+    pin_skip_similar_sentences: bool,
+
+    // the compiled `user_narration_patterns` from the configuration
+    user_narration_patterns: Vec<Regex>,
+
+    // the compiled `response_trim_patterns` of each character that has responded, by name
+    response_trim_patterns: HashMap<String, Vec<Regex>>,
+
+    // if set, the estimated token usage of the chat is kept up to date and shown above the chatlog
+    show_prompt_estimate: bool,
+
+    // the last prompt estimate from the engine: the prompt tokens, the tokens the prompt would
+    // need to hold the whole chatlog, the context size of the model and the number of chatlog
+    // items left out of the prompt to fit
+    prompt_estimate: Option<(usize, usize, usize, usize)>,
+
+    // the number of chatlog items that were left out of the prompt for the last response
+    omitted_turns: usize,
+
+    // set when the chatlog changed since the prompt estimate was last asked for
+    prompt_estimate_stale: bool,
+
+    // set once a response contains the configured `scene_end_marker`; cleared again when the
+    // chat is cleared or a response is regenerated or requested by hand
+    scene_ended: bool,
+
+    // describes what the user narration patterns did to the last response, shown above the
+    // chatlog until the next key press
+    user_narration_notice: Option<String>,
+
+    // the last chatlog item deleted and the index it was at, kept so ctrl-z can put it back
+    last_deleted: Option<(usize, ChatLogItem)>,
+
+    // when idle auto-saving is configured, this is the time of the last activity since a new
+    // item went into the journal without the chatlog being saved; None if nothing is pending.
+    autosave_pending_since: Option<Instant>,
 }
 impl ChatState {
     // Creates a new ChatState for the selected character.
@@ -67,100 +487,442 @@ impl ChatState {
         character: CharacterFileYaml,
         chatlog: ChatLog,
         inference_parameters: Option<&ConfiguredParameters>,
+        parameter_overrides: &ParameterOverrides,
         config: ConfigurationFile,
         send_to_server: Sender<LlmEngineRequest>,
+        send_cmd_to_server: Sender<LlmEngineCommand>,
         recv_on_client: Receiver<LlmEngineResponse>,
+        active_model_name: String,
     ) -> ChatState {
         let config = config.clone();
 
-        let current_parameters = match inference_parameters {
-            Some(params) => ConfiguredParameters {
-                name: params.name.clone(),
-                top_k: params.top_k,
-                top_p: params.top_p,
-                min_p: params.min_p,
-                temperature: params.temperature,
-                repeat_penalty: params.repeat_penalty,
-                repeat_penalty_range: params.repeat_penalty_range,
-                mirostat: params.mirostat,
-                mirostat_eta: params.mirostat_eta,
-                mirostat_tau: params.mirostat_tau,
-            },
-            None => ConfiguredParameters::default(),
-        };
+        let current_parameters = pick_starting_parameters(
+            &config,
+            &character,
+            &chatlog,
+            inference_parameters,
+            parameter_overrides,
+        );
 
         let send_to_server = send_to_server.clone();
         let recv_on_client = recv_on_client.clone();
 
+        // optionally tidy up logs where one speaker's turn got split across several items
+        let mut chatlog = chatlog;
+        if config.merge_consecutive_speakers_on_load.unwrap_or(false)
+            && chatlog.merge_consecutive_speakers() > 0
+        {
+            if let Err(err) = chatlog.save_to_last_used_json_file() {
+                log::error!(
+                    "Failed to save the chatlog after merging consecutive speakers: {}",
+                    err
+                );
+            }
+        }
+
+        let auto_generate_on_reply = config.auto_generate_on_reply.unwrap_or(true);
+        let show_prompt_estimate = config.show_prompt_estimate.unwrap_or(true);
+
+        let user_narration_patterns = compile_user_narration_patterns(&config);
+
         ChatState {
             config,
             character,
             other_participants: Vec::new(),
             chatlog,
             chatlog_scroll: 0,
+            last_scrolled_at: None,
+            last_visible_item_count: Cell::new(1),
             current_parameters,
+            parameter_overrides: parameter_overrides.clone(),
             manual_reply_mode: false,
+            auto_generate_on_reply,
             send_to_server,
+            send_cmd_to_server,
             recv_on_client,
             editing_reply: false,
             editing_parameters: false,
             reply_text: String::new(),
+            reply_cursor: 0,
             waiting_for_operation: false,
+            in_flight_text: String::new(),
+            in_flight_fragment_ends: Vec::new(),
+            in_flight_scroll: 0,
+            response_streaming_override: None,
+            participant_streaming_overrides: HashMap::new(),
+            in_flight_continues: false,
             waiting_for_character: None,
             progress_widget: None,
+            engine_status: None,
+            active_model_name,
+            regenerated_item: None,
+            regenerate_count: 0,
             modal_messagebox: None,
+            leave_confirm: None,
             context_editor: None,
             userdesc_editor: None,
+            tags_editor: None,
+            nudge_editor: None,
+            parameters_name_editor: None,
+            parameters_browser: None,
+            regenerate_after_parameters_browser: false,
+            candidate_progress: None,
+            candidate_chooser: None,
+            show_similar_matches: false,
+            parameters_field_index: 0,
             logitem_editor: None,
+            saveas_editor: None,
+            newlog_editor: None,
+            dataset_editor: None,
+            clear_chat_editor: None,
+            find_editor: None,
+            instruct_template_editor: None,
+            find_text: None,
+            last_dataset_filename: String::new(),
+            last_save_failed: false,
+            skip_similar_sentences: false,
+            pin_skip_similar_sentences: false,
+            user_narration_patterns,
+            response_trim_patterns: HashMap::new(),
+            show_prompt_estimate,
+            prompt_estimate: None,
+            omitted_turns: 0,
+            prompt_estimate_stale: true,
+            scene_ended: false,
+            user_narration_notice: None,
+            last_deleted: None,
+            autosave_pending_since: None,
         }
     }
 
     // saves the file out to the file it was last loaded from and returns a bool
     // indicating if the log was successfully saved. if no last_used_filepath is
     // set, then the function doesn't do anything and returns false.
-    fn save_chatlog_to_last_used(&self) -> bool {
+    // the first failure in a row pops up a warning so that the user knows their
+    // work isn't being persisted and can use 'save as' to put it somewhere else.
+    fn save_chatlog_to_last_used(&mut self) -> bool {
+        self.prompt_estimate_stale = true;
+
+        // keep a copy of what's about to be overwritten
+        let backup_count = self
+            .config
+            .log_backup_count
+            .unwrap_or(DEFAULT_LOG_BACKUP_COUNT);
+        if backup_count > 0 {
+            if let Some(fp) = self.chatlog.get_last_used_filepath() {
+                if fp.exists() {
+                    if let Err(err) = backup_json_file(fp, backup_count) {
+                        log::error!("Failed to back up the chatlog ({:?}): {}", fp, err);
+                    }
+                }
+            }
+        }
+
         // save the log file out if the last-used filepath was set
         if let Err(err) = self.chatlog.save_to_last_used_json_file() {
             log::error!(
                 "Failed to write the chatlog after receiving next text inference response: {}",
                 err
             );
+            if !self.last_save_failed {
+                self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                    "Warning",
+                    format!("The chatlog could not be saved: {:#}. Changes are only in memory until a save succeeds. Use ctrl-s to save the chatlog to a different file.", err).as_str(),
+                    60,
+                    30,
+                ));
+            }
+            self.last_save_failed = true;
             false
         } else {
+            self.last_save_failed = false;
+            self.autosave_pending_since = None;
+
+            // everything in the journal is in the saved log now
+            if let Some(fp) = self.chatlog.get_last_used_filepath() {
+                if let Err(err) = clear_journal(fp) {
+                    log::error!("Failed to clear the chatlog journal ({:?}): {}", fp, err);
+                }
+            }
             true
         }
     }
 
+    // called after a new item is put at the end of the chatlog, or the last item is replaced.
+    // the item is written to the journal right away and then the chatlog is saved, unless idle
+    // auto-saving is configured, in which case the save waits for the chat to go quiet.
+    fn commit_last_chatlog_item(&mut self, replaces_last: bool) {
+        self.prompt_estimate_stale = true;
+        if let (Some(fp), Some(item)) = (self.chatlog.get_last_used_filepath(), self.chatlog.last())
+        {
+            if let Err(err) = append_to_journal(fp, item, replaces_last) {
+                log::error!("Failed to write to the chatlog journal ({:?}): {}", fp, err);
+            }
+        }
+
+        match self.config.autosave_idle_seconds {
+            Some(idle_seconds) if idle_seconds > 0 => {
+                self.autosave_pending_since = Some(Instant::now());
+            }
+            _ => {
+                let _ = self.save_chatlog_to_last_used();
+            }
+        }
+    }
+
+    // when `autoscroll_on_new_message` is set, scrolls the chatlog back to the newest
+    // item, unless the user has scrolled it recently and is likely still reading.
+    fn autoscroll_to_newest(&mut self) {
+        if !self.config.autoscroll_on_new_message.unwrap_or(false) {
+            return;
+        }
+        let recently_scrolled = self
+            .last_scrolled_at
+            .is_some_and(|t| t.elapsed() < AUTOSCROLL_GRACE_PERIOD);
+        if !recently_scrolled {
+            self.chatlog_scroll = 0;
+        }
+    }
+
+    // saves the chatlog if a save has been put off and the chat has been idle long enough,
+    // or right away if `force` is set.
+    fn autosave_if_idle(&mut self, force: bool) {
+        if let Some(pending_since) = self.autosave_pending_since {
+            let idle_limit = Duration::from_secs(self.config.autosave_idle_seconds.unwrap_or(0));
+            if force || pending_since.elapsed() >= idle_limit {
+                let _ = self.save_chatlog_to_last_used();
+            }
+        }
+    }
+
+    // saves the chatlog to a new filepath, creating any missing folders along the way,
+    // and makes that the file used for all further saves.
+    fn save_chatlog_as(&mut self, filepath: &str) {
+        let fp = PathBuf::from(filepath);
+        if let Some(parent) = fp.parent() {
+            if !parent.as_os_str().is_empty() {
+                if let Err(err) = std::fs::create_dir_all(parent) {
+                    log::error!(
+                        "Failed to create the folder for the chatlog ({:?}): {}",
+                        parent,
+                        err
+                    );
+                }
+            }
+        }
+
+        if let Err(err) = self.chatlog.save_to_json_file(&fp) {
+            log::error!("Failed to save the chatlog to {:?}: {}", fp, err);
+            self.last_save_failed = true;
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Warning",
+                format!("The chatlog could not be saved to {}: {:#}", filepath, err).as_str(),
+                60,
+                30,
+            ));
+        } else {
+            self.last_save_failed = false;
+        }
+    }
+
+    // saves the chat into a new chatlog folder for the character, which becomes the log the
+    // chat saves to from then on. the other files kept with the current log come along too.
+    fn save_chatlog_as_new_log(&mut self, log_name: &str) {
+        let new_log_dir = get_log_folder(self.character.name.as_str()).join(log_name);
+        let new_log_file = new_log_dir.join(LOG_FILE_NAME);
+        if new_log_file.exists() {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Chatlog Not Created",
+                format!(
+                    "A chatlog named '{}' already exists for this character.",
+                    log_name
+                )
+                .as_str(),
+                50,
+                20,
+            ));
+            return;
+        }
+
+        let old_log_file = self.chatlog.get_last_used_filepath().cloned();
+        self.save_chatlog_as(new_log_file.to_string_lossy().as_ref());
+        if self.last_save_failed {
+            return;
+        }
+
+        if let Some(old_log_file) = old_log_file {
+            if let Err(err) = copy_log_folder_files(&old_log_file, &new_log_dir) {
+                log::error!(
+                    "Failed to copy the files kept with the chatlog to {:?}: {}",
+                    new_log_dir,
+                    err
+                );
+            }
+        }
+    }
+
     fn process_incoming_llm_engine_messages(&mut self) {
         // see if there are any incoming messages from the server
         if self.recv_on_client.is_empty() == false {
             match self.recv_on_client.try_recv() {
+                // a response that this chat didn't ask for carries some other chat's log, so
+                // it can't be allowed to replace this one
+                Ok(llm_engine::LlmEngineResponse::NewText(_, _))
+                | Ok(llm_engine::LlmEngineResponse::NewCandidates(_, _))
+                    if !self.waiting_for_operation =>
+                {
+                    log::warn!("Ignoring a text inference response this chat wasn't waiting for.");
+                }
                 Ok(llm_engine::LlmEngineResponse::NewText(maybe_resp, context)) => {
+                    let regenerated_item = self.regenerated_item.take();
                     if let Some(resp) = maybe_resp {
                         //TODO: consider a different way of getting vector embeddings back from the thread
+                        self.omitted_turns = context.dropped_turns;
                         self.chatlog = context.chatlog;
+                        self.end_turn_for_similar_sentences_skip();
 
                         // FIXME: this is going to be broken for other_participants
                         if context.should_continue == false {
-                            let new_item = ChatLogItem::new_from_str(
+                            let mut new_item = ChatLogItem::new_from_str(
                                 context.character.name.to_owned(),
                                 resp.trim(),
                             );
+                            self.tidy_new_response(&mut new_item);
+                            self.record_similar_matches(&mut new_item, context.similar_matches);
+                            if let Some(previous) = regenerated_item {
+                                new_item.keep_alternatives_from(previous);
+                            }
                             self.chatlog.push(new_item);
+                            self.commit_last_chatlog_item(false);
                         } else {
                             // if we don't have a log item to append we just make a new one
                             let mut last_item = self.chatlog.pop().unwrap_or_default();
                             last_item.add_to_last(resp.as_str());
+                            self.tidy_new_response(&mut last_item);
+                            if let Some(previous) = regenerated_item {
+                                last_item.keep_alternatives_from(previous);
+                            }
+                            if context.similar_matches.is_some() {
+                                self.record_similar_matches(
+                                    &mut last_item,
+                                    context.similar_matches,
+                                );
+                            }
                             self.chatlog.push(last_item);
+                            self.commit_last_chatlog_item(true);
                         }
 
-                        // save the log file out
-                        let _ = self.save_chatlog_to_last_used();
+                        self.autoscroll_to_newest();
                         self.hide_progress_bar();
                     } else {
                         log::error!("Response for the text inferrence was empty.");
+                        self.restore_regenerated_item(regenerated_item, context.should_continue);
+                        self.hide_progress_bar();
+                    }
+                }
+                Ok(llm_engine::LlmEngineResponse::NewTextFragment(fragment))
+                    if self.waiting_for_operation =>
+                {
+                    self.in_flight_text.push_str(&fragment);
+                    self.in_flight_fragment_ends.push(self.in_flight_text.len());
+                }
+                Ok(llm_engine::LlmEngineResponse::CandidateProgress(done, total)) => {
+                    self.candidate_progress = Some((done, total));
+                }
+                Ok(llm_engine::LlmEngineResponse::NewCandidates(candidates, context)) => {
+                    self.omitted_turns = context.dropped_turns;
+                    self.chatlog = context.chatlog;
+                    self.end_turn_for_similar_sentences_skip();
+                    self.hide_progress_bar();
+                    if candidates.is_empty() {
+                        log::error!("None of the candidate text inferrences returned a response.");
+                    } else {
+                        let mut chooser = StatefulList::with_items(
+                            candidates.iter().map(|c| c.trim().to_owned()).collect(),
+                        );
+                        chooser.state.select(Some(0));
+                        self.candidate_chooser =
+                            Some((context.character.name, chooser, context.similar_matches));
                     }
                 }
+                Ok(llm_engine::LlmEngineResponse::TextInferenceCancelled(context)) => {
+                    let regenerated_item = self.regenerated_item.take();
+                    self.restore_regenerated_item(regenerated_item, context.should_continue);
+                    self.hide_progress_bar();
+                }
+                Ok(llm_engine::LlmEngineResponse::ModelLoading(model_name)) => {
+                    self.engine_status = Some(format!("Loading model {}...", model_name));
+                }
+                Ok(llm_engine::LlmEngineResponse::ModelLoaded)
+                | Ok(llm_engine::LlmEngineResponse::ConfigReloaded) => {
+                    self.engine_status = None;
+                }
+                Ok(llm_engine::LlmEngineResponse::ModelLoadFailed(reason)) => {
+                    self.engine_status = None;
+                    self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                        "Model Load Failed",
+                        reason.as_str(),
+                        60,
+                        30,
+                    ));
+                }
+                Ok(llm_engine::LlmEngineResponse::ModelSwitched(model_name)) => {
+                    log::debug!(
+                        "The engine switched to the model configuration {}",
+                        model_name
+                    );
+                    self.active_model_name = model_name;
+                }
+                Ok(llm_engine::LlmEngineResponse::EngineStatus(status)) => {
+                    self.engine_status = status;
+                }
+                Ok(llm_engine::LlmEngineResponse::ContextSizeWarning(message)) => {
+                    self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                        "Context Size",
+                        message.as_str(),
+                        60,
+                        30,
+                    ));
+                }
+                Ok(llm_engine::LlmEngineResponse::PromptEstimate(
+                    prompt_tokens,
+                    whole_chat_tokens,
+                    context_size,
+                    dropped_turns,
+                )) => {
+                    self.prompt_estimate = Some((
+                        prompt_tokens,
+                        whole_chat_tokens,
+                        context_size,
+                        dropped_turns,
+                    ));
+                }
+                Ok(llm_engine::LlmEngineResponse::IgnoredParameters(message)) => {
+                    self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                        "Ignored Parameters",
+                        message.as_str(),
+                        60,
+                        40,
+                    ));
+                }
+                Ok(llm_engine::LlmEngineResponse::InstructTemplate(model_name, template)) => {
+                    let ce = TextEditingBlockModalWidget::new(
+                        format!(
+                            "Instruct template for '{}' until restarted (\\n = new line):",
+                            model_name
+                        ),
+                        template.replace('\n', "\\n"),
+                    );
+                    self.instruct_template_editor = Some(ce);
+                }
+                Ok(llm_engine::LlmEngineResponse::PromptPreview(prompt)) => {
+                    self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                        "Prompt Preview",
+                        prompt.as_str(),
+                        80,
+                        80,
+                    ));
+                }
                 _ => {}
             }
         }
@@ -171,9 +933,67 @@ impl ChatState {
             match key.code {
                 KeyCode::Esc => {
                     self.editing_parameters = false;
+                    self.remember_parameters_in_chatlog();
                 }
                 KeyCode::Enter => {
                     self.editing_parameters = false;
+                    self.remember_parameters_in_chatlog();
+                }
+                KeyCode::Char('j') => {
+                    self.parameters_field_index =
+                        (self.parameters_field_index + 1) % EDITABLE_PARAMETERS.len();
+                }
+                KeyCode::Char('k') => {
+                    self.parameters_field_index = if self.parameters_field_index == 0 {
+                        EDITABLE_PARAMETERS.len() - 1
+                    } else {
+                        self.parameters_field_index - 1
+                    };
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    EDITABLE_PARAMETERS[self.parameters_field_index]
+                        .adjust(&mut self.current_parameters, 1);
+                    self.store_current_parameters();
+                }
+                KeyCode::Char('-') => {
+                    EDITABLE_PARAMETERS[self.parameters_field_index]
+                        .adjust(&mut self.current_parameters, -1);
+                    self.store_current_parameters();
+                }
+                KeyCode::Char('x') | KeyCode::Delete | KeyCode::Backspace => {
+                    EDITABLE_PARAMETERS[self.parameters_field_index]
+                        .clear(&mut self.current_parameters);
+                    self.store_current_parameters();
+                }
+                KeyCode::Char('b') => {
+                    self.open_parameters_browser();
+                }
+                KeyCode::Char('c') => {
+                    let ce = TextEditingBlockModalWidget::new(
+                        "Name for the copy of this parameter set".to_owned(),
+                        format!("{} (copy)", self.current_parameters.name),
+                    );
+                    self.parameters_name_editor = Some(ce);
+                }
+                KeyCode::Char('s') => {
+                    let modal = match self.config.save_config() {
+                        Ok(_) => MessageBoxModalWidget::new(
+                            "Parameters Saved",
+                            "The parameter sets were saved to the configuration file.",
+                            50,
+                            20,
+                        ),
+                        Err(err) => {
+                            log::error!("Failed to save the parameter sets: {}", err);
+                            MessageBoxModalWidget::new(
+                                "Parameters NOT Saved",
+                                format!("Failed to save the configuration file: {}", err).as_str(),
+                                50,
+                                30,
+                            )
+                        }
+                    };
+                    self.modal_messagebox = Some(modal);
                 }
                 KeyCode::Char('h') => {
                     for (i, pset) in self.config.parameters.iter().enumerate() {
@@ -213,49 +1033,89 @@ impl ChatState {
     }
 
     fn process_input_for_editing_replies(&mut self, event: TerminalEvent) {
+        // pasted text goes in at the cursor all at once, new lines and all
+        if let TerminalEvent::Paste(pasted) = &event {
+            let pasted = pasted.replace("\r\n", "\n").replace('\r', "\n");
+            self.reply_text.insert_str(self.reply_cursor, &pasted);
+            self.reply_cursor += pasted.len();
+            return;
+        }
         if let TerminalEvent::Key(key) = event {
             match key.code {
                 KeyCode::Esc => {
                     self.editing_reply = false;
                 }
+                KeyCode::Left => {
+                    if let Some(c) = self.reply_text[..self.reply_cursor].chars().next_back() {
+                        self.reply_cursor -= c.len_utf8();
+                    }
+                }
+                KeyCode::Right => {
+                    if let Some(c) = self.reply_text[self.reply_cursor..].chars().next() {
+                        self.reply_cursor += c.len_utf8();
+                    }
+                }
+                KeyCode::Home => {
+                    // home and end stay on the line the cursor is on
+                    self.reply_cursor = self.reply_text[..self.reply_cursor]
+                        .rfind('\n')
+                        .map_or(0, |i| i + 1);
+                }
+                KeyCode::End => {
+                    self.reply_cursor = self.reply_text[self.reply_cursor..]
+                        .find('\n')
+                        .map_or(self.reply_text.len(), |i| self.reply_cursor + i);
+                }
                 KeyCode::Backspace => {
-                    self.reply_text.pop();
+                    if let Some(c) = self.reply_text[..self.reply_cursor].chars().next_back() {
+                        self.reply_cursor -= c.len_utf8();
+                        self.reply_text.remove(self.reply_cursor);
+                    }
                 }
-                KeyCode::Char(to_insert) => {
-                    self.reply_text.push(to_insert);
+                KeyCode::Delete if self.reply_cursor < self.reply_text.len() => {
+                    self.reply_text.remove(self.reply_cursor);
                 }
-                KeyCode::Enter => {
-                    let mut trimmed_reply_text = self.reply_text.trim().to_string();
+                KeyCode::Enter if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.reply_text.insert(self.reply_cursor, '\n');
+                    self.reply_cursor += 1;
+                }
+                KeyCode::Char(to_insert)
+                    if !(to_insert == 's' && key.modifiers.contains(KeyModifiers::CONTROL)) =>
+                {
+                    self.reply_text.insert(self.reply_cursor, to_insert);
+                    self.reply_cursor += to_insert.len_utf8();
+                }
+                KeyCode::Enter | KeyCode::Char('s') => {
+                    // ctrl + s sends the reply, as does ctrl + enter where the terminal reports it
+                    let trimmed_reply_text = self.reply_text.trim().to_string();
 
                     // if the reply text is empty, we just ignore all of this and return
                     if trimmed_reply_text.is_empty() {
                         return;
                     }
 
-                    // check to see if the string just ends with a non-escaped "\n" and if so,
-                    // just replace that with a newline character.
-                    if trimmed_reply_text.ends_with("\\n") {
-                        trimmed_reply_text.pop();
-                        trimmed_reply_text.pop();
-                        trimmed_reply_text.push_str("\n");
-                        self.reply_text = trimmed_reply_text;
+                    // the reply stays in the editor until the response being generated is done
+                    if self.refuse_while_generating("send a reply") {
                         return;
                     }
 
                     // officially add the message we sent to the log
                     let new_message = ChatLogItem::new_from_str(
                         self.config.display_name.clone(),
-                        self.reply_text.as_str(),
+                        trimmed_reply_text.as_str(),
                     );
                     self.chatlog.push(new_message);
                     self.reply_text.clear();
+                    self.reply_cursor = 0;
                     self.editing_reply = false;
+                    self.commit_last_chatlog_item(false);
 
-                    // save the log file out
-                    let _ = self.save_chatlog_to_last_used();
-
-                    // if we're not in manual reply mode, automatically run inferrence
-                    if self.manual_reply_mode == false {
+                    // if we're not in manual reply mode, automatically run inferrence unless
+                    // that's been turned off so several messages can be sent in a row, or the
+                    // scene has ended and that's configured to stop it
+                    let scene_end_stops = self.scene_ended
+                        && self.config.scene_end_stops_auto_generation.unwrap_or(false);
+                    if !self.manual_reply_mode && self.auto_generate_on_reply && !scene_end_stops {
                         let context = TextInferenceContext {
                             character: self.character.clone(),
                             model_config_override: None,
@@ -264,12 +1124,18 @@ impl ChatState {
                             chatlog: self.chatlog.clone(),
                             should_continue: false,
                             parameters: self.current_parameters.clone(),
+                            nudge: None,
+                            randomize_seed: false,
+                            similar_matches: None,
+                            skip_similar_sentences: self.skip_similar_sentences,
+                            dropped_turns: 0,
                         };
 
                         let msg = llm_engine::LlmEngineRequest::TextInference(context);
                         if let Err(err) = self.send_to_server.send(msg) {
                             log::error!("Error during text infer: {}", err);
                         }
+                        self.regenerate_count = 0;
 
                         self.show_progress_bar(self.character.clone());
                     }
@@ -282,11 +1148,19 @@ impl ChatState {
     fn process_input_for_viewing_chatlog(&mut self, event: TerminalEvent) -> ProcessInputResult {
         if let TerminalEvent::Key(key) = event {
             if key.code == KeyCode::Esc {
-                return ProcessInputResult::ChangeScene(
-                    crate::application::ApplicationState::MainMenu,
-                );
+                // leaving while a response is generated throws it away, so ask first
+                if self.waiting_for_operation && self.config.confirm_on_quit.unwrap_or(true) {
+                    self.leave_confirm = Some(ConfirmModalWidget::new(
+                        "Leave Chat",
+                        "A response is still being generated. Stop it and leave the chat?",
+                        50,
+                    ));
+                    return ProcessInputResult::None;
+                }
+                return self.leave_chat();
             } else if key.code == KeyCode::Char('y') {
                 if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    self.scene_ended = false;
                     let context = TextInferenceContext {
                         character: self.character.clone(),
                         model_config_override: None,
@@ -295,119 +1169,307 @@ impl ChatState {
                         chatlog: self.chatlog.clone(),
                         should_continue: false,
                         parameters: self.current_parameters.clone(),
+                        nudge: None,
+                        randomize_seed: false,
+                        similar_matches: None,
+                        skip_similar_sentences: self.skip_similar_sentences,
+                        dropped_turns: 0,
                     };
                     let msg = llm_engine::LlmEngineRequest::TextInference(context);
                     if let Err(err) = self.send_to_server.send(msg) {
                         log::error!("Error during text infer additional request: {}", err);
                     }
+                    self.regenerate_count = 0;
                     self.show_progress_bar(self.character.clone());
                 }
-            } else if key.code == KeyCode::Char('r') {
+            } else if key.code == KeyCode::Char('b') {
                 if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    let last_message = self.chatlog.pop();
-                    if last_message.is_none() {
-                        return ProcessInputResult::None; // can't regenerate nothing, not even with AI.
+                    // ctrl + b jumps back to the chatlog used before this one
+                    if self.refuse_while_generating("switch to the previous chatlog") {
+                        return ProcessInputResult::None;
                     }
-
-                    // save the log file out
-                    let _ = self.save_chatlog_to_last_used();
-
-                    let mut context = TextInferenceContext {
-                        character: self.character.clone(),
-                        model_config_override: None,
-                        chatlog_owner: self.character.clone(),
-                        other_participants: self.other_participants.clone(),
-                        chatlog: self.chatlog.clone(),
-                        should_continue: false,
-                        parameters: self.current_parameters.clone(),
-                    };
-
-                    // check to see if the last message was sent by the 'main' character
-                    // or one of the other participants
-                    if let Some(lastmsg) = last_message {
-                        if !lastmsg.entity.eq(self.character.name.as_str()) {
-                            if !self.other_participants.is_empty() {
-                                // find the first match and update the request context
-                                for (character, model_ovrride) in &self.other_participants {
-                                    if lastmsg.entity.eq(character.name.as_str()) {
-                                        context.character = character.clone();
-                                        if let Some(ovrride) = model_ovrride {
-                                            context.model_config_override = Some(ovrride.clone());
-                                        }
-                                        break;
-                                    }
-                                }
-                            }
-                        }
+                    return ProcessInputResult::ChangeScene(
+                        crate::application::ApplicationState::PreviousChat,
+                    );
+                }
+            } else if key.code == KeyCode::Char('a') {
+                self.auto_generate_on_reply = !self.auto_generate_on_reply;
+                let message = if self.auto_generate_on_reply {
+                    "Sending a reply will now automatically generate a response."
+                } else {
+                    "Sending a reply will no longer generate a response. Use ctrl-y to ask for one when you're ready."
+                };
+                self.modal_messagebox =
+                    Some(MessageBoxModalWidget::new("Information", message, 60, 30));
+            } else if key.code == KeyCode::Char('S') {
+                let show_streaming = !self.is_response_streaming_shown();
+                // in multi-chat mode, a response being generated picks out whose stream to toggle
+                let participant = match &self.waiting_for_character {
+                    Some(character) if self.manual_reply_mode && self.waiting_for_operation => {
+                        Some(character.name.clone())
                     }
-
-                    self.show_progress_bar(context.character.clone());
-
-                    let msg = llm_engine::LlmEngineRequest::TextInference(context);
-                    if let Err(err) = self.send_to_server.send(msg) {
-                        log::error!("Error during text infer redo request: {}", err);
+                    _ => None,
+                };
+                let message = match participant {
+                    Some(name) => {
+                        let message = if show_streaming {
+                            format!(
+                                "Responses from {} will now be shown as they stream in.",
+                                name
+                            )
+                        } else {
+                            format!(
+                                "Responses from {} will now only be shown once they're done.",
+                                name
+                            )
+                        };
+                        self.participant_streaming_overrides
+                            .insert(name, show_streaming);
+                        message
                     }
+                    None => {
+                        // the whole chat follows the toggle, so earlier picks are dropped
+                        self.participant_streaming_overrides.clear();
+                        self.response_streaming_override = Some(show_streaming);
+                        if show_streaming {
+                            "Responses will now be shown as they stream in.".to_owned()
+                        } else {
+                            "Responses will now only be shown once they're done.".to_owned()
+                        }
+                    }
+                };
+                self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                    "Information",
+                    message.as_str(),
+                    60,
+                    30,
+                ));
+            } else if key.code == KeyCode::Char('v') {
+                if !self.refuse_while_generating("generate candidate responses") {
+                    self.generate_candidate_responses();
+                }
+            } else if key.code == KeyCode::Char('i') {
+                // toggle highlighting the items that sentence similarity matched for the
+                // selected item, which only works if the matches were recorded
+                let index = self.get_currently_select_chatlogitem_index();
+                let has_matches = self
+                    .chatlog
+                    .get(index)
+                    .is_some_and(|item| item.similar_matches.is_some());
+                if self.show_similar_matches {
+                    self.show_similar_matches = false;
+                } else if has_matches {
+                    self.show_similar_matches = true;
+                } else {
+                    self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                        "Similar Sentences",
+                        "No sentence similarity matches were recorded for the selected chatlog item. Set record_similar_sentence_matches in the config to keep them for new responses.",
+                        60,
+                        30,
+                    ));
+                }
+            } else if key.code == KeyCode::Char('r') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    self.regenerate_last_response(None);
                 } else {
                     // regular 'r' is for reply
                     self.editing_reply = true;
                 }
-            } else if key.code == KeyCode::Char('t') {
+            } else if key.code == KeyCode::Char('R') {
+                // shift + r picks a parameter set to regenerate the last response with
+                if !self.refuse_while_generating("regenerate a response")
+                    && !self.refuse_regenerating_user_reply()
+                {
+                    if self.config.parameters.is_empty() {
+                        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                            "Regenerate",
+                            "There are no parameter sets in the configuration to pick from.",
+                            50,
+                            20,
+                        ));
+                    } else {
+                        self.open_parameters_browser();
+                        self.regenerate_after_parameters_browser = true;
+                    }
+                }
+            } else if key.code == KeyCode::Char('n') {
                 if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // ctrl + t is for continue
-                    let mut context = TextInferenceContext {
-                        character: self.character.clone(),
+                    // ctrl + n regenerates with a one-off instruction that isn't kept in the log
+                    if !self.refuse_while_generating("regenerate a response")
+                        && !self.refuse_regenerating_user_reply()
+                    {
+                        let ce = TextEditingBlockModalWidget::new(
+                            "Instruction for the regenerated response".to_owned(),
+                            String::new(),
+                        );
+                        self.nudge_editor = Some(ce);
+                    }
+                } else if self.find_text.is_some() {
+                    // regular 'n' jumps to the next older item matching the search
+                    self.jump_to_find_match(true);
+                }
+            } else if key.code == KeyCode::Char('N') {
+                // shift + n jumps to the next newer item matching the search
+                if self.find_text.is_some() {
+                    self.jump_to_find_match(false);
+                }
+            } else if key.code == KeyCode::Char('T') {
+                // shift + t edits the instruct template of the active model until restarted
+                if let Err(err) = self
+                    .send_cmd_to_server
+                    .send(LlmEngineCommand::GetInstructTemplate)
+                {
+                    log::error!("Error sending the instruct template command: {}", err);
+                }
+            } else if key.code == KeyCode::Char('C') {
+                // shift + c reads the configuration file again
+                return ProcessInputResult::ReloadConfig;
+            } else if key.code == KeyCode::Char('/') {
+                let ce = TextEditingBlockModalWidget::new(
+                    "Search the chatlog for (empty to clear the search):".to_owned(),
+                    self.find_text.clone().unwrap_or_default(),
+                );
+                self.find_editor = Some(ce);
+            } else if key.code == KeyCode::Char('t') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + t is for continue
+                    self.continue_last_response();
+                }
+            } else if key.code == KeyCode::Char('f') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + f keeps the opening sentence and regenerates the rest
+                    self.regenerate_keeping_first_sentence();
+                }
+            } else if key.code == KeyCode::Char('c') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) && self.waiting_for_operation {
+                    // ctrl + c stops the text inference currently running
+                    if let Err(err) = self
+                        .send_cmd_to_server
+                        .send(LlmEngineCommand::CancelTextInference)
+                    {
+                        log::error!("Error sending the cancel command: {}", err);
+                    }
+                }
+            } else if key.code == KeyCode::Char('p') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + p shows the prompt that would be sent for the main character
+                    let context = TextInferenceContext {
+                        character: self.character.clone(),
                         model_config_override: None,
                         chatlog_owner: self.character.clone(),
                         other_participants: self.other_participants.clone(),
                         chatlog: self.chatlog.clone(),
-                        should_continue: true,
+                        should_continue: false,
                         parameters: self.current_parameters.clone(),
+                        nudge: None,
+                        randomize_seed: false,
+                        similar_matches: None,
+                        skip_similar_sentences: self.skip_similar_sentences,
+                        dropped_turns: 0,
                     };
-
-                    // check to see if the last message was sent by the 'main' character
-                    // or one of the other participants
-                    if let Some(lastmsg) = self.chatlog.last() {
-                        if !lastmsg.entity.eq(self.character.name.as_str()) {
-                            if !self.other_participants.is_empty() {
-                                // find the first match and update the request context
-                                for (character, model_ovrride) in &self.other_participants {
-                                    if lastmsg.entity.eq(character.name.as_str()) {
-                                        context.character = character.clone();
-                                        if let Some(ovrride) = model_ovrride {
-                                            context.model_config_override = Some(ovrride.clone());
-                                        }
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    self.show_progress_bar(context.character.clone());
-
-                    let msg = llm_engine::LlmEngineRequest::TextInference(context);
-                    if let Err(err) = self.send_to_server.send(msg) {
-                        log::error!("Error during text infer redo request: {}", err);
+                    let cmd = LlmEngineCommand::PreviewPrompt(context);
+                    if let Err(err) = self.send_cmd_to_server.send(cmd) {
+                        log::error!("Error sending the prompt preview command: {}", err);
                     }
+                } else {
+                    self.editing_parameters = true;
                 }
-            } else if key.code == KeyCode::Char('p') {
-                self.editing_parameters = true;
             } else if key.code == KeyCode::Char('j') {
                 self.chatlog_scroll = std::cmp::min(self.chatlog_scroll + 1, self.chatlog.len());
+                self.last_scrolled_at = Some(Instant::now());
+            } else if (key.code == KeyCode::Up || key.code == KeyCode::Down)
+                && key.modifiers.is_empty()
+                && !self.in_flight_text.is_empty()
+                && self.is_response_streaming_shown()
+            {
+                // up/down scroll through a response that's still streaming in; the render
+                // keeps the scroll within the text
+                if key.code == KeyCode::Up {
+                    self.in_flight_scroll += 1;
+                } else {
+                    self.in_flight_scroll = self.in_flight_scroll.saturating_sub(1);
+                }
+            } else if key.code == KeyCode::Home || key.code == KeyCode::End {
+                // home jumps to the newest item at the top and end to the oldest one
+                self.chatlog_scroll = if key.code == KeyCode::Home {
+                    0
+                } else {
+                    self.chatlog.len().saturating_sub(1)
+                };
+                self.last_scrolled_at = Some(Instant::now());
+            } else if key.code == KeyCode::PageUp || key.code == KeyCode::PageDown {
+                // page up/down scroll by as many items as fit on the screen last time
+                let page = self.last_visible_item_count.get().max(1);
+                self.chatlog_scroll = if key.code == KeyCode::PageUp {
+                    self.chatlog_scroll.saturating_sub(page)
+                } else {
+                    (self.chatlog_scroll + page).min(self.chatlog.len().saturating_sub(1))
+                };
+                self.last_scrolled_at = Some(Instant::now());
             } else if key.code == KeyCode::Char('k') {
-                if self.chatlog_scroll > 0 {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + k toggles the token usage estimate above the chatlog
+                    self.show_prompt_estimate = !self.show_prompt_estimate;
+                    self.prompt_estimate = None;
+                    self.prompt_estimate_stale = true;
+                } else if self.chatlog_scroll > 0 {
                     self.chatlog_scroll -= 1;
+                    self.last_scrolled_at = Some(Instant::now());
+                }
+            } else if (key.code == KeyCode::Left || key.code == KeyCode::Right)
+                && self.chatlog_scroll == 0
+            {
+                // left/right switch the newest item between its regenerated responses
+                if !self.refuse_while_generating("switch between regenerated responses") {
+                    self.select_last_item_alternative(key.code == KeyCode::Left);
+                }
+            } else if key.modifiers.contains(KeyModifiers::CONTROL)
+                && (key.code == KeyCode::Up || key.code == KeyCode::Down)
+            {
+                // ctrl + up/down moves the selected entry earlier or later in the log,
+                // with the scroll following it so that it stays selected
+                if self.refuse_while_generating("move chatlog items") {
+                    return ProcessInputResult::None;
+                }
+                let index = self.get_currently_select_chatlogitem_index();
+                let moved = if key.code == KeyCode::Up {
+                    index > 0 && self.chatlog.move_item(index, index - 1)
+                } else {
+                    self.chatlog.move_item(index, index + 1)
+                };
+                if moved {
+                    if key.code == KeyCode::Up {
+                        self.chatlog_scroll += 1;
+                    } else {
+                        self.chatlog_scroll -= 1;
+                    }
+                    let _ = self.save_chatlog_to_last_used();
                 }
             } else if key.code == KeyCode::Char('x') {
                 if key.modifiers.contains(KeyModifiers::CONTROL) {
                     // ctrl + x for deleting selected entry
+                    if self.refuse_while_generating("delete chatlog items") {
+                        return ProcessInputResult::None;
+                    }
                     let index = self.get_currently_select_chatlogitem_index();
-                    self.chatlog.remove(index);
+                    if let Some(removed) = self.chatlog.remove(index) {
+                        self.last_deleted = Some((index, removed));
+                    }
 
                     // save the log file out
                     let _ = self.save_chatlog_to_last_used();
                 }
+            } else if key.code == KeyCode::Char('z') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + z puts back the last deleted entry
+                    if !self.refuse_while_generating("undo a deletion") {
+                        self.undo_last_delete();
+                    }
+                }
             } else if key.code == KeyCode::Char('o') {
+                if self.refuse_while_generating("edit the chatlog's descriptions") {
+                    return ProcessInputResult::None;
+                }
                 if key.modifiers.contains(KeyModifiers::CONTROL) {
                     let user_desc = self.chatlog.user_description.clone().unwrap_or_default();
                     let ce =
@@ -420,7 +1482,108 @@ impl ChatState {
                     );
                     self.context_editor = Some(ce);
                 }
+            } else if key.code == KeyCode::Char('g') {
+                if self.refuse_while_generating("change the chatlog") {
+                    return ProcessInputResult::None;
+                }
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + g glues together adjacent items from the same speaker
+                    let merged_count = self.chatlog.merge_consecutive_speakers();
+                    if merged_count > 0 {
+                        self.chatlog_scroll = self.chatlog_scroll.min(self.chatlog.len());
+                        let _ = self.save_chatlog_to_last_used();
+                    }
+                    let modal = MessageBoxModalWidget::new(
+                        "Merge Consecutive Speakers",
+                        format!(
+                            "Merged {} chatlog item(s) into the preceding item from the same speaker.",
+                            merged_count
+                        )
+                        .as_str(),
+                        50,
+                        20,
+                    );
+                    self.modal_messagebox = Some(modal);
+                } else {
+                    let tags = self.chatlog.tags.clone().unwrap_or_default().join(", ");
+                    let ce =
+                        TextEditingBlockModalWidget::new("Tags (comma separated)".to_owned(), tags);
+                    self.tags_editor = Some(ce);
+                }
+            } else if key.code == KeyCode::Char('s') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + s lets the user pick a new file to save the chatlog to
+                    if self.refuse_while_generating("save the chatlog to a different file") {
+                        return ProcessInputResult::None;
+                    }
+                    let current_path = self
+                        .chatlog
+                        .get_last_used_filepath()
+                        .and_then(|p| p.to_str())
+                        .unwrap_or_default()
+                        .to_owned();
+                    let ce = TextEditingBlockModalWidget::new(
+                        "Save chatlog as:".to_owned(),
+                        current_path,
+                    );
+                    self.saveas_editor = Some(ce);
+                }
+            } else if key.code == KeyCode::Char('d') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + d saves the chat as a new chatlog, starting from the current name
+                    if self.refuse_while_generating("save the chat as a new chatlog") {
+                        return ProcessInputResult::None;
+                    }
+                    let current_name = self
+                        .chatlog
+                        .get_last_used_filepath()
+                        .and_then(|fp| fp.parent())
+                        .and_then(|dir| dir.file_name())
+                        .and_then(|name| name.to_str())
+                        .unwrap_or_default()
+                        .to_owned();
+                    let ce = TextEditingBlockModalWidget::new(
+                        "Enter a name for the new chatlog:".to_owned(),
+                        current_name,
+                    );
+                    self.newlog_editor = Some(ce);
+                }
+            } else if key.code == KeyCode::Char('l') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + l clears the chat after the user types the word to confirm it
+                    if self.refuse_while_generating("clear the chat") {
+                        return ProcessInputResult::None;
+                    }
+                    let ce = TextEditingBlockModalWidget::new(
+                        format!(
+                            "Type '{}' to restart the chat, keeping the context and participants:",
+                            CLEAR_CHAT_CONFIRMATION
+                        ),
+                        String::new(),
+                    );
+                    self.clear_chat_editor = Some(ce);
+                }
+            } else if key.code == KeyCode::Char('u') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + u leaves the similar sentences out until it's pressed again
+                    self.pin_skip_similar_sentences = !self.pin_skip_similar_sentences;
+                    self.skip_similar_sentences = self.pin_skip_similar_sentences;
+                } else {
+                    // regular 'u' leaves them out of just the next generation
+                    self.pin_skip_similar_sentences = false;
+                    self.skip_similar_sentences = !self.skip_similar_sentences;
+                }
+            } else if key.code == KeyCode::Char('w') {
+                self.describe_selected_chatlog_item();
             } else if key.code == KeyCode::Char('e') {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    // ctrl + e adds the selected response and what led to it to a dataset
+                    self.open_dataset_editor();
+                    return ProcessInputResult::None;
+                }
+                if self.refuse_while_generating("edit chatlog items") {
+                    return ProcessInputResult::None;
+                }
                 let index = self.get_currently_select_chatlogitem_index();
                 if let Some(cli) = self.chatlog.get(index) {
                     let ce = TextEditingBlockModalWidget::new(
@@ -466,21 +1629,51 @@ impl ChatState {
             } else if key.code == KeyCode::Char('?') {
                 let help_strings = "j      = scroll chatlog down\n\
                                     k      = scroll chatlog up\n\
-                                    r      = type a new message to the AI (esc to cancel)\n\
+                                    home/end = jump to the newest/oldest chatlog item\n\
+                                    pgup/pgdn = scroll chatlog up/down a page\n\
+                                    up/down = scroll a long response while it streams in\n\
+                                    S      = toggle showing responses as they stream in (just the responder in multi-chat)\n\
+                                    r      = type a new message to the AI (ctrl-s to send, esc to cancel)\n\
                                     ctrl-r = regenerate the AI's last response\n\
+                                    R      = pick a parameter set and regenerate the AI's last response with it\n\
+                                    ctrl-n = regenerate the AI's last response with a one-off instruction\n\
+                                    left/right = switch the newest response between its regenerated versions\n\
                                     ctrl-t = continues the AI's last response\n\
+                                    ctrl-f = regenerate the AI's last response after its first sentence\n\
                                     ctrl-y = generate another AI response manually\n\
+                                    a      = toggle generating a response when a reply is sent\n\
+                                    v      = generate several AI responses and pick one to keep\n\
+                                    i      = highlight the similar sentences used for the selected item\n\
+                                    ctrl-c = cancel the AI response being generated\n\
+                                    ctrl-p = preview the prompt for the next AI response\n\
+                                    T      = edit the active model's instruct template until restarted\n\
+                                    C      = reload the configuration file\n\
                                     ctrl-x = delete the currently selected chatlog item\n\
+                                    ctrl-z = undo the last chatlog item deletion\n\
+                                    ctrl-up/ctrl-down = move the selected chatlog item earlier/later\n\
                                     o      = set the current context description for the chatlog\n\
+                                    g      = set the tags for the chatlog\n\
+                                    ctrl-g = merge adjacent chatlog items from the same speaker\n\
                                     ctrl-o = regenerate the AI's last response\n\
                                     e      = edit the currently selected chatlog item\n\
+                                    ctrl-e = append the selected AI response to a training dataset file\n\
+                                    w      = show what's stored for the selected chatlog item\n\
+                                    u      = leave the similar sentences out of the next AI response\n\
+                                    ctrl-u = leave the similar sentences out until pressed again\n\
+                                    ctrl-s = save the chatlog to a different file\n\
+                                    ctrl-d = save the chat as a new chatlog for the character\n\
+                                    ctrl-b = switch back to the previously opened chatlog\n\
+                                    ctrl-l = clear the chat back to the greeting (asks to confirm)\n\
+                                    ctrl-k = toggle the estimate of the chat's token usage\n\
+                                    /      = search the chatlog for some text\n\
+                                    n/N    = jump to the next older/newer item matching the search\n\
                                     esc    = exit back to the main menu\n\
                                     \n\
                                     m      = enter multi-chat mode\n\
                                     <1>    = generate a reply for the main AI character\n\
                                     <2-0>  = generate a reply for subesquent 'other participants'\n\
                                     \n\
-                                    p      = select a parameter configuration for inference\n\
+                                    p      = view, edit and select parameter configurations for inference\n\
                                     h      = select parameter config to the left\n\
                                     l      = select parameter config to the right";
 
@@ -496,11 +1689,17 @@ impl ChatState {
                     chatlog: self.chatlog.clone(),
                     should_continue: false,
                     parameters: self.current_parameters.clone(),
+                    nudge: None,
+                    randomize_seed: false,
+                    similar_matches: None,
+                    skip_similar_sentences: self.skip_similar_sentences,
+                    dropped_turns: 0,
                 };
                 let msg = llm_engine::LlmEngineRequest::TextInference(context);
                 if let Err(err) = self.send_to_server.send(msg) {
                     log::error!("Error during text infer additional request: {}", err);
                 }
+                self.regenerate_count = 0;
                 self.show_progress_bar(self.character.clone());
             } else if self.manual_reply_mode {
                 // the case for the normal character is handled above, so this
@@ -532,12 +1731,18 @@ impl ChatState {
                                 chatlog: self.chatlog.clone(),
                                 should_continue: false,
                                 parameters: self.current_parameters.clone(),
+                                nudge: None,
+                                randomize_seed: false,
+                                similar_matches: None,
+                                skip_similar_sentences: self.skip_similar_sentences,
+                                dropped_turns: 0,
                             };
                             self.show_progress_bar(context.character.clone());
                             let msg = llm_engine::LlmEngineRequest::TextInference(context);
                             if let Err(err) = self.send_to_server.send(msg) {
                                 log::error!("Error during text infer additional request: {}", err);
                             }
+                            self.regenerate_count = 0;
                         } else {
                             log::debug!("No other participants defined for generation.");
                         }
@@ -551,44 +1756,42 @@ impl ChatState {
     }
 
     fn render_editing_parameters_modal(&self, frame: &mut Frame) {
-        let mut area = centered_rect(60, 30, frame.size());
-        area.height = std::cmp::min(area.height, 8);
+        let mut area = centered_rect(60, 60, frame.size());
+        area.height = std::cmp::min(area.height, 6 + EDITABLE_PARAMETERS.len() as u16 + 2);
 
         let mut hyperparameter_strings =
             vec![Line::from(format!("\"{}\"", self.current_parameters.name))
                 .alignment(Alignment::Center)];
 
-        if let Some(repeat_penalty) = self.current_parameters.repeat_penalty {
-            hyperparameter_strings.push(Line::from(format!("repeat penalty: {}", repeat_penalty)));
-        }
-        if let Some(repeat_range) = self.current_parameters.repeat_penalty_range {
-            hyperparameter_strings.push(Line::from(format!("repeat range: {}", repeat_range)));
+        for (i, field) in EDITABLE_PARAMETERS.iter().enumerate() {
+            let value = field
+                .value_string(&self.current_parameters)
+                .unwrap_or_else(|| "unset".to_owned());
+            let text = format!("{}: {}", field.label(), value);
+            if i == self.parameters_field_index {
+                hyperparameter_strings.push(Line::from(Span::styled(
+                    format!(">> {}", text),
+                    Style::default().fg(Color::LightGreen).bold(),
+                )));
+            } else {
+                hyperparameter_strings.push(Line::from(format!("   {}", text)));
+            }
         }
 
-        if let Some(mirostat) = self.current_parameters.mirostat {
-            if mirostat == 1 || mirostat == 2 {
-                hyperparameter_strings.push(Line::from(format!("Mirostat {}", mirostat)));
-                if let Some(eta) = self.current_parameters.mirostat_eta {
-                    hyperparameter_strings.push(Line::from(format!("Eta: {}", eta)));
-                }
-                if let Some(tau) = self.current_parameters.mirostat_tau {
-                    hyperparameter_strings.push(Line::from(format!("Tau: {}", tau)));
-                }
-            }
-        } else {
-            if let Some(top_k) = self.current_parameters.top_k {
-                hyperparameter_strings.push(Line::from(format!("top k: {}", top_k)));
-            }
-            if let Some(top_p) = self.current_parameters.top_p {
-                hyperparameter_strings.push(Line::from(format!("top p: {}", top_p)));
-            }
-            if let Some(min_p) = self.current_parameters.min_p {
-                hyperparameter_strings.push(Line::from(format!("min p: {}", min_p)));
-            }
-            if let Some(temp) = self.current_parameters.temperature {
-                hyperparameter_strings.push(Line::from(format!("temperature: {}", temp)));
-            }
-        };
+        let hint_style = Style::default().fg(Color::Rgb(100, 100, 100));
+        hyperparameter_strings.push(Line::from(""));
+        hyperparameter_strings.push(Line::from(Span::styled(
+            "j/k = select, +/- = adjust, x = unset, h/l = switch set",
+            hint_style,
+        )));
+        hyperparameter_strings.push(Line::from(Span::styled(
+            "c = copy to a new set, s = save sets to the config file",
+            hint_style,
+        )));
+        hyperparameter_strings.push(Line::from(Span::styled(
+            "b = browse all of the sets",
+            hint_style,
+        )));
 
         let textarea = Paragraph::new(hyperparameter_strings)
             .style(Style::default().fg(Color::Cyan))
@@ -606,186 +1809,1429 @@ impl ChatState {
         // loop through the chat history and build up each line we want to render
         let mut chat_history = vec![];
         let lines_needed: usize = area.height as usize;
+        let names_as_headers =
+            self.config.name_display_style.clone().unwrap_or_default() == NameDisplayStyle::Header;
+        let render_max_blank_lines = if self
+            .config
+            .collapse_blank_lines_when_rendering
+            .unwrap_or(false)
+        {
+            self.config.collapse_blank_lines
+        } else {
+            None
+        };
 
-        for chatlogitem in self.chatlog.iter().rev().skip(self.chatlog_scroll) {
-            // the bool keeps track of whether or not we're in a quote and
-            // the chunker string is a buffer used so that we don't create
-            // hundreds of strings in the loop.
-            let mut in_quotes_state = false;
-            let mut quote_chunker = String::new();
-
-            // setup the styles depending on who's talking
-            let mut text_style = Style::default();
-            let mut quotes_style = Style::default();
-            let mut name_style = Style::default();
-            // check to see if this is from a character
-            if chatlogitem
-                .entity
-                .eq_ignore_ascii_case(self.character.name.as_str())
-            {
-                if let Some(rgbs) = &self.character.name_rgb {
-                    name_style = name_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+        // when showing the similar sentence matches, find the ones for the selected item
+        let selected_index = self.chatlog.len().checked_sub(self.chatlog_scroll + 1);
+        let similar_matches: &[SimilarSentenceMatch] = if self.show_similar_matches {
+            selected_index
+                .and_then(|i| self.chatlog.get(i))
+                .and_then(|item| item.similar_matches.as_deref())
+                .unwrap_or_default()
+        } else {
+            &[]
+        };
+        let similar_style = Style::default().fg(Color::Yellow);
+
+        // the response streaming in goes above the newest item, or replaces it if continuing
+        let in_flight_item = self.build_in_flight_item();
+        let shade_in_flight_tokens = self.config.shade_streamed_tokens.unwrap_or(false);
+        if let (Some(item), false) = (&in_flight_item, self.in_flight_continues) {
+            if self.chatlog_scroll == 0 {
+                if shade_in_flight_tokens {
+                    self.push_shaded_in_flight_lines(
+                        Some(&item.entity),
+                        area.width as usize,
+                        names_as_headers,
+                        &mut chat_history,
+                    );
+                } else {
+                    self.render_chatlog_item(
+                        item,
+                        area.width as usize,
+                        names_as_headers,
+                        render_max_blank_lines,
+                        false,
+                        &mut chat_history,
+                    );
                 }
-                if let Some(rgbs) = &self.character.text_rgb {
-                    text_style = text_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+                self.keep_in_flight_lines_visible(&mut chat_history, lines_needed);
+                if self.config.add_visual_buffer_between_chatlog_items == Some(true) {
+                    chat_history.push(Line::from(" "));
                 }
-                if let Some(rgbs) = &self.character.quotes_rgb {
-                    quotes_style = quotes_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+        }
+
+        let mut visible_item_count = 0;
+        for (rev_index, chatlogitem) in self
+            .chatlog
+            .iter()
+            .rev()
+            .skip(self.chatlog_scroll)
+            .enumerate()
+        {
+            let item_index = self.chatlog.len() - 1 - self.chatlog_scroll - rev_index;
+            visible_item_count = rev_index + 1;
+
+            // point out the selected item's matches and where they came from
+            if self.show_similar_matches {
+                if Some(item_index) == selected_index && !similar_matches.is_empty() {
+                    let match_list: Vec<String> = similar_matches
+                        .iter()
+                        .map(|m| format!("#{} ({:.2})", m.index + 1, m.score))
+                        .collect();
+                    chat_history.push(Line::from(Span::styled(
+                        format!("[similar sentences used: {}]", match_list.join(", ")),
+                        similar_style,
+                    )));
+                } else if let Some(m) = similar_matches.iter().find(|m| m.index == item_index) {
+                    chat_history.push(Line::from(Span::styled(
+                        format!("[similar sentence #{}, score {:.2}]", m.index + 1, m.score),
+                        similar_style,
+                    )));
+                }
+            }
+
+            let is_in_flight = in_flight_item.is_some()
+                && self.in_flight_continues
+                && item_index + 1 == self.chatlog.len();
+            // shaded tokens go on the lines after the item being continued
+            let chatlogitem = match &in_flight_item {
+                Some(item) if is_in_flight && !shade_in_flight_tokens => item,
+                _ => chatlogitem,
+            };
+            let highlighted = self.chatlog_item_matches_find(chatlogitem);
+            self.render_chatlog_item(
+                chatlogitem,
+                area.width as usize,
+                names_as_headers,
+                render_max_blank_lines,
+                highlighted,
+                &mut chat_history,
+            );
+            if is_in_flight {
+                if shade_in_flight_tokens {
+                    self.push_shaded_in_flight_lines(
+                        None,
+                        area.width as usize,
+                        names_as_headers,
+                        &mut chat_history,
+                    );
+                }
+                self.keep_in_flight_lines_visible(&mut chat_history, lines_needed);
+            }
+
+            // the newest item notes which of its regenerated responses is showing
+            if item_index + 1 == self.chatlog.len() && chatlogitem.alternatives.len() > 1 {
+                chat_history.push(Line::from(Span::styled(
+                    format!(
+                        "< response {} of {} (left/right to switch) >",
+                        chatlogitem.current_alt + 1,
+                        chatlogitem.alternatives.len()
+                    ),
+                    Style::default().fg(Color::Rgb(100, 100, 100)),
+                )));
+            }
+
+            if chat_history.len() >= lines_needed {
+                break;
+            }
+
+            // potentially add a buffer line if configured to do so
+            if let Some(add_divider) = self.config.add_visual_buffer_between_chatlog_items {
+                if add_divider {
+                    chat_history.push(Line::from(" "));
+                }
+            }
+        }
+
+        // the last item drawn is usually cut off, so it doesn't count as a full page
+        self.last_visible_item_count
+            .set(visible_item_count.saturating_sub(1).max(1));
+
+        let chatlog = Paragraph::new(chat_history).alignment(self.get_chat_text_alignment());
+        frame.render_widget(chatlog, area);
+    }
+
+    // when the response streaming in is taller than the chatlog area, drops lines from the
+    // start of it so the newest text stays in view, or the lines around `in_flight_scroll` if
+    // it's been scrolled back. `lines` should only hold the in-flight response so far.
+    fn keep_in_flight_lines_visible(&self, lines: &mut Vec<Line>, lines_needed: usize) {
+        if lines.len() <= lines_needed {
+            return;
+        }
+        let max_scroll = lines.len() - lines_needed;
+        let first_line = max_scroll - self.in_flight_scroll.min(max_scroll);
+        lines.drain(..first_line);
+        lines.truncate(lines_needed);
+    }
+
+    // wraps the reply being typed to `width` and draws the cursor over the character it's on,
+    // or just past the end of its line.
+    fn build_reply_lines(&self, width: usize) -> Vec<Line<'static>> {
+        let cursor_style = Style::default().reversed();
+        let before_cursor = &self.reply_text[..self.reply_cursor];
+        let cursor_line_index = before_cursor.matches('\n').count();
+        let cursor_column = before_cursor
+            .rfind('\n')
+            .map_or(self.reply_cursor, |i| self.reply_cursor - i - 1);
+
+        let mut lines = vec![];
+        // we don't add our name here, so leading space can be 0
+        for (line_index, reply_line) in self.reply_text.split('\n').enumerate() {
+            let mut split_lines = slice_up_string(reply_line, width, 0);
+            if split_lines.is_empty() {
+                // lines of nothing but whitespace can wrap down to nothing
+                split_lines.push(String::new());
+            }
+            let cursor_position = if line_index == cursor_line_index {
+                Some(find_wrapped_position(
+                    reply_line,
+                    &split_lines,
+                    cursor_column,
+                ))
+            } else {
+                None
+            };
+            for (split_index, split_line) in split_lines.into_iter().enumerate() {
+                match cursor_position {
+                    Some((cursor_split, offset)) if cursor_split == split_index => {
+                        let (before, rest) = split_line.split_at(offset);
+                        let mut rest_chars = rest.chars();
+                        let under_cursor =
+                            rest_chars.next().map_or(" ".to_owned(), |c| c.to_string());
+                        lines.push(Line::from(vec![
+                            Span::raw(before.to_owned()),
+                            Span::styled(under_cursor, cursor_style),
+                            Span::raw(rest_chars.as_str().to_owned()),
+                        ]));
+                    }
+                    _ => lines.push(Line::from(split_line)),
+                }
+            }
+        }
+        lines
+    }
+
+    // returns true if responses should be shown as they stream in, checking the setting
+    // toggled for the character responding, then the one toggled in the chat and then the
+    // configuration.
+    fn is_response_streaming_shown(&self) -> bool {
+        let participant_override = self
+            .waiting_for_character
+            .as_ref()
+            .and_then(|c| self.participant_streaming_overrides.get(&c.name));
+        participant_override
+            .copied()
+            .or(self.response_streaming_override)
+            .unwrap_or(!self.config.disable_response_streaming.unwrap_or(false))
+    }
+
+    // builds a chatlog item for the response streaming in so far, if any of it has arrived
+    // and streaming is being shown.
+    fn build_in_flight_item(&self) -> Option<ChatLogItem> {
+        if self.in_flight_text.is_empty() || !self.is_response_streaming_shown() {
+            return None;
+        }
+        let name = self
+            .waiting_for_character
+            .as_ref()
+            .map(|c| c.name.clone())
+            .unwrap_or_default();
+        let text = self.get_visible_in_flight_text();
+        if text.trim().is_empty() {
+            return None;
+        }
+        if self.in_flight_continues {
+            let mut item = self.chatlog.last()?.clone();
+            item.add_to_last(text);
+            Some(item)
+        } else {
+            Some(ChatLogItem::new_from_str(name, text.trim_start()))
+        }
+    }
+
+    // returns the part of the response streaming in that's worth showing. when responses stop
+    // at the participants' names, the text from the first name starting a new turn is left out,
+    // since it gets cut there once the whole response arrives anyway.
+    fn get_visible_in_flight_text(&self) -> &str {
+        if !self.config.stop_on_display_name {
+            return &self.in_flight_text;
+        }
+        let names = [
+            self.config.display_name.as_str(),
+            self.character.name.as_str(),
+        ]
+        .into_iter()
+        .chain(self.waiting_for_character.iter().map(|c| c.name.as_str()))
+        .chain(self.other_participants.iter().map(|o| o.0.name.as_str()));
+        let cut_at = llm_engine::build_participant_name_stops(names)
+            .iter()
+            .filter_map(|stop| self.in_flight_text.find(stop.as_str()))
+            .min()
+            .unwrap_or(self.in_flight_text.len());
+        &self.in_flight_text[..cut_at]
+    }
+
+    // adds the lines for the response streaming in with each streamed fragment shaded in turn,
+    // so that the token boundaries show. the name is put in front if given, the same way
+    // `render_chatlog_item` does it, and the text is wrapped to `width`.
+    fn push_shaded_in_flight_lines(
+        &self,
+        name: Option<&str>,
+        width: usize,
+        names_as_headers: bool,
+        lines: &mut Vec<Line<'static>>,
+    ) {
+        let shades = [
+            Style::default(),
+            Style::default().bg(Color::Rgb(50, 50, 50)),
+        ];
+        let text = self.get_visible_in_flight_text();
+        let text_start = if self.in_flight_continues {
+            0
+        } else {
+            text.len() - text.trim_start().len()
+        };
+
+        let mut spans = Vec::new();
+        let mut line_width = 0;
+        if let Some(name) = name {
+            if names_as_headers {
+                lines.push(Line::from(Span::styled(
+                    name.to_owned(),
+                    Style::default().bold(),
+                )));
+            } else {
+                spans.push(Span::styled(name.to_owned(), Style::default().bold()));
+                spans.push(Span::styled(": ", Style::default().bold()));
+                line_width = name.width() + 2;
+            }
+        }
+
+        let mut fragment_start = text_start;
+        for (i, fragment_end) in self.in_flight_fragment_ends.iter().enumerate() {
+            let fragment_end = (*fragment_end).min(text.len());
+            if fragment_end <= fragment_start {
+                continue;
+            }
+            let fragment = &text[fragment_start..fragment_end];
+            fragment_start = fragment_end;
+
+            let mut chunk = String::new();
+            for g in UnicodeSegmentation::graphemes(fragment, true) {
+                let is_new_line = g == "\n" || g == "\r\n";
+                if is_new_line || line_width + g.width() > width {
+                    if !chunk.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut chunk), shades[i % 2]));
+                    }
+                    lines.push(Line::from(std::mem::take(&mut spans)));
+                    line_width = 0;
+                    if is_new_line {
+                        continue;
+                    }
                 }
+                chunk.push_str(g);
+                line_width += g.width();
+            }
+            if !chunk.is_empty() {
+                spans.push(Span::styled(chunk, shades[i % 2]));
             }
-            // or if this is from the user
-            else if chatlogitem
-                .entity
-                .eq_ignore_ascii_case(&self.config.display_name.as_str())
+        }
+        if !spans.is_empty() {
+            lines.push(Line::from(spans));
+        }
+    }
+
+    // returns the configured alignment for the chatlog text.
+    fn get_chat_text_alignment(&self) -> Alignment {
+        if let Some(justification) = &self.config.chat_text_justification {
+            justification.clone().into()
+        } else {
+            Alignment::Right
+        }
+    }
+
+    // builds the styled lines for one chatlog item, wrapped to `width`, and adds them to `lines`.
+    fn render_chatlog_item(
+        &self,
+        chatlogitem: &ChatLogItem,
+        width: usize,
+        names_as_headers: bool,
+        render_max_blank_lines: Option<usize>,
+        highlighted: bool,
+        lines: &mut Vec<Line<'static>>,
+    ) {
+        // the bool keeps track of whether or not we're in a quote and
+        // the chunker string is a buffer used so that we don't create
+        // hundreds of strings in the loop.
+        let mut in_quotes_state = false;
+        let mut quote_chunker = String::new();
+
+        // setup the styles depending on who's talking
+        let mut text_style = Style::default();
+        let mut quotes_style = Style::default();
+        let mut name_style = Style::default();
+        // check to see if this is from a character
+        if chatlogitem
+            .entity
+            .eq_ignore_ascii_case(self.character.name.as_str())
+        {
+            if let Some(rgbs) = &self.character.name_rgb {
+                name_style = name_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+            if let Some(rgbs) = &self.character.text_rgb {
+                text_style = text_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+            if let Some(rgbs) = &self.character.quotes_rgb {
+                quotes_style = quotes_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+        }
+        // or if this is from the user
+        else if chatlogitem
+            .entity
+            .eq_ignore_ascii_case(&self.config.display_name.as_str())
+        {
+            if let Some(rgbs) = &self.config.display_name_rgb {
+                name_style = name_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+            if let Some(rgbs) = &self.config.text_rgb {
+                text_style = text_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+            if let Some(rgbs) = &self.config.quotes_rgb {
+                quotes_style = quotes_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+            }
+        }
+
+        // check to see if other participants are loaded and if they have color syntax rules
+        for other in &self.other_participants {
+            if other
+                .0
+                .name
+                .eq_ignore_ascii_case(chatlogitem.entity.as_str())
             {
-                if let Some(rgbs) = &self.config.display_name_rgb {
+                if let Some(rgbs) = &other.0.name_rgb {
                     name_style = name_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
                 }
-                if let Some(rgbs) = &self.config.text_rgb {
+                if let Some(rgbs) = &other.0.text_rgb {
                     text_style = text_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
                 }
-                if let Some(rgbs) = &self.config.quotes_rgb {
+                if let Some(rgbs) = &other.0.quotes_rgb {
                     quotes_style = quotes_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
                 }
             }
+        }
 
-            // check to see if other participants are loaded and if they have color syntax rules
-            for other in &self.other_participants {
-                if other
-                    .0
-                    .name
-                    .eq_ignore_ascii_case(chatlogitem.entity.as_str())
-                {
-                    if let Some(rgbs) = &other.0.name_rgb {
-                        name_style = name_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
-                    }
-                    if let Some(rgbs) = &other.0.text_rgb {
-                        text_style = text_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+        // items matching the chatlog search get their name picked out
+        if highlighted {
+            name_style = name_style.reversed();
+        }
+
+        // when configured, the name gets a line of its own above the message
+        if names_as_headers {
+            lines.push(Line::from(Span::styled(
+                chatlogitem.entity.to_owned(),
+                name_style.bold(),
+            )));
+        }
+        let first_line_offset = if names_as_headers {
+            0
+        } else {
+            chatlogitem.entity.len() + 2 // 2 == ": "
+        };
+
+        // each log item may have multiple lines
+        let item_lines = match render_max_blank_lines {
+            Some(max_blank_lines) => collapse_blank_lines(&chatlogitem.lines, max_blank_lines),
+            None => chatlogitem.lines.clone(),
+        };
+        for (il_index, item_line) in item_lines.iter().enumerate() {
+            // each line in the log item may be too long, so we break it apart
+            let split_item_lines = slice_up_string(item_line, width, first_line_offset);
+            for (si_index, split_item_line) in split_item_lines.iter().enumerate() {
+                let mut spans = Vec::new();
+                if !names_as_headers && il_index == 0 && si_index == 0 {
+                    // for the first line of the chat log item we see if we have
+                    // a known talker name, and color it differently
+                    spans.push(Span::styled(
+                        chatlogitem.entity.to_owned(),
+                        name_style.bold(),
+                    ));
+                    spans.push(Span::styled(": ", text_style.bold()));
+                }
+
+                // Loop through the split line by graphemes and manually chunk things
+                // up into quoted text and unquoted text.
+                quote_chunker.clear();
+                for g in UnicodeSegmentation::graphemes(split_item_line.as_str(), true) {
+                    if g == "\"" {
+                        if in_quotes_state {
+                            quote_chunker.push_str(g);
+                            spans.push(Span::styled(quote_chunker.to_owned(), quotes_style));
+                            quote_chunker.clear();
+                        } else {
+                            spans.push(Span::styled(quote_chunker.to_owned(), text_style));
+                            quote_chunker.clear();
+                            quote_chunker.push_str(g);
+                        }
+                        in_quotes_state = !in_quotes_state;
+                    } else {
+                        quote_chunker.push_str(g);
                     }
-                    if let Some(rgbs) = &other.0.quotes_rgb {
-                        quotes_style = quotes_style.fg(Color::Rgb(rgbs[0], rgbs[1], rgbs[2]));
+                }
+                // handle any left behind grapheme chunks
+                if quote_chunker.is_empty() == false {
+                    if in_quotes_state {
+                        spans.push(Span::styled(quote_chunker.to_owned(), quotes_style));
+                    } else {
+                        spans.push(Span::styled(quote_chunker.to_owned(), text_style));
                     }
                 }
+
+                lines.push(Line::from(spans));
             }
+        }
+    }
 
-            // each log item may have multiple lines
-            let item_lines = &chatlogitem.lines;
-            for (il_index, item_line) in item_lines.iter().enumerate() {
-                // each line in the log item may be too long, so we break it apart
-                let split_item_lines =
-                    slice_up_string(item_line, area.width as usize, chatlogitem.entity.len() + 2); // 2 == ": "
-                for (si_index, split_item_line) in split_item_lines.iter().enumerate() {
-                    let mut spans = Vec::new();
-                    if il_index == 0 && si_index == 0 {
-                        // for the first line of the chat log item we see if we have
-                        // a known talker name, and color it differently
-                        spans.push(Span::styled(
-                            chatlogitem.entity.to_owned(),
-                            name_style.bold(),
-                        ));
-                        spans.push(Span::styled(": ", text_style.bold()));
-                    }
+    fn render_progress_bar(&mut self, frame: &mut Frame, area: Rect) {
+        // lets create the widget if we haven't already
+        if self.progress_widget.is_none() {
+            let mut primary = self.config.progress_primary_rgb.unwrap_or([10, 242, 10]);
+            let mut secondary = self.config.progress_secondary_rgb.unwrap_or([62, 62, 62]);
 
-                    // Loop through the split line by graphemes and manually chunk things
-                    // up into quoted text and unquoted text.
-                    quote_chunker.clear();
-                    for g in UnicodeSegmentation::graphemes(split_item_line.as_str(), true) {
-                        if g == "\"" {
-                            if in_quotes_state {
-                                quote_chunker.push_str(g);
-                                spans.push(Span::styled(quote_chunker.to_owned(), quotes_style));
-                                quote_chunker.clear();
-                            } else {
-                                spans.push(Span::styled(quote_chunker.to_owned(), text_style));
-                                quote_chunker.clear();
-                                quote_chunker.push_str(g);
+            // check to see if the character we're waiting on has an rgb value set for the name
+            // and if so, use that for the primary color; likewise for the secondary color
+            if let Some(char) = &self.waiting_for_character {
+                if let Some(rgb) = char.name_rgb {
+                    primary = rgb;
+                }
+                if let Some(rgb) = char.progress_secondary_rgb {
+                    secondary = rgb;
+                }
+            }
+
+            let new_pw = ProgressBarScopeSignal::new(primary, secondary);
+            self.progress_widget = Some(new_pw);
+        }
+
+        let pw = self.progress_widget.as_mut().unwrap();
+        pw.render(frame, area);
+    }
+
+    // removes the last response from the chatlog and asks the engine for a new one from
+    // the same speaker. an optional one-off instruction can be passed to steer the new response.
+    fn regenerate_last_response(&mut self, nudge: Option<String>) {
+        // never throw away the user's own reply by regenerating it
+        if self.refuse_regenerating_user_reply() {
+            return;
+        }
+
+        let last_message = self.chatlog.pop();
+        if last_message.is_none() {
+            return; // can't regenerate nothing, not even with AI.
+        }
+        self.scene_ended = false;
+        self.regenerated_item = last_message.clone();
+
+        // save the log file out
+        let _ = self.save_chatlog_to_last_used();
+
+        let mut context = TextInferenceContext {
+            character: self.character.clone(),
+            model_config_override: None,
+            chatlog_owner: self.character.clone(),
+            other_participants: self.other_participants.clone(),
+            chatlog: self.chatlog.clone(),
+            should_continue: false,
+            parameters: self.current_parameters.clone(),
+            nudge,
+            randomize_seed: false,
+            similar_matches: None,
+            skip_similar_sentences: self.skip_similar_sentences,
+            dropped_turns: 0,
+        };
+
+        // check to see if the last message was sent by the 'main' character
+        // or one of the other participants
+        if let Some(lastmsg) = last_message {
+            if !lastmsg.entity.eq(self.character.name.as_str()) {
+                if !self.other_participants.is_empty() {
+                    // find the first match and update the request context
+                    for (character, model_ovrride) in &self.other_participants {
+                        if lastmsg.entity.eq(character.name.as_str()) {
+                            context.character = character.clone();
+                            if let Some(ovrride) = model_ovrride {
+                                context.model_config_override = Some(ovrride.clone());
                             }
-                            in_quotes_state = !in_quotes_state;
-                        } else {
-                            quote_chunker.push_str(g);
+                            break;
                         }
                     }
-                    // handle any left behind grapheme chunks
-                    if quote_chunker.is_empty() == false {
-                        if in_quotes_state {
-                            spans.push(Span::styled(quote_chunker.to_owned(), quotes_style));
-                        } else {
-                            spans.push(Span::styled(quote_chunker.to_owned(), text_style));
+                }
+            }
+        }
+
+        self.show_progress_bar(context.character.clone());
+
+        let msg = llm_engine::LlmEngineRequest::TextInference(context);
+        if let Err(err) = self.send_to_server.send(msg) {
+            log::error!("Error during text infer redo request: {}", err);
+        }
+        self.regenerate_count += 1;
+    }
+
+    // puts the response that was being regenerated back at the end of the chatlog, for when
+    // the regeneration was cancelled or came back empty.
+    fn restore_regenerated_item(
+        &mut self,
+        regenerated_item: Option<ChatLogItem>,
+        replaces_last: bool,
+    ) {
+        if let Some(item) = regenerated_item {
+            // a response that was being continued is still in the log, cut short
+            if replaces_last {
+                self.chatlog.pop();
+            }
+            self.chatlog.push(item);
+            self.commit_last_chatlog_item(replaces_last);
+        }
+    }
+
+    // switches the last chatlog item over to its previous or next alternative response, if
+    // it was regenerated and has one in that direction.
+    fn select_last_item_alternative(&mut self, previous: bool) {
+        let Some(last_index) = self.chatlog.len().checked_sub(1) else {
+            return;
+        };
+        let switched = self.chatlog.get_mut(last_index).is_some_and(|item| {
+            let index = if previous {
+                item.current_alt.checked_sub(1)
+            } else {
+                Some(item.current_alt + 1)
+            };
+            index.is_some_and(|i| item.select_alternative(i))
+        });
+        if switched {
+            self.commit_last_chatlog_item(true);
+        }
+    }
+
+    // asks the engine to keep writing the last response in the chatlog from where it ends.
+    fn continue_last_response(&mut self) {
+        // there's nothing to continue in an empty chatlog
+        if self.chatlog.len() == 0 {
+            return;
+        }
+
+        let mut context = TextInferenceContext {
+            character: self.character.clone(),
+            model_config_override: None,
+            chatlog_owner: self.character.clone(),
+            other_participants: self.other_participants.clone(),
+            chatlog: self.chatlog.clone(),
+            should_continue: true,
+            parameters: self.current_parameters.clone(),
+            nudge: None,
+            randomize_seed: false,
+            similar_matches: None,
+            skip_similar_sentences: self.skip_similar_sentences,
+            dropped_turns: 0,
+        };
+
+        // check to see if the last message was sent by the 'main' character
+        // or one of the other participants
+        if let Some(lastmsg) = self.chatlog.last() {
+            if !lastmsg.entity.eq(self.character.name.as_str()) {
+                if !self.other_participants.is_empty() {
+                    // find the first match and update the request context
+                    for (character, model_ovrride) in &self.other_participants {
+                        if lastmsg.entity.eq(character.name.as_str()) {
+                            context.character = character.clone();
+                            if let Some(ovrride) = model_ovrride {
+                                context.model_config_override = Some(ovrride.clone());
+                            }
+                            break;
                         }
                     }
+                }
+            }
+        }
+
+        self.show_progress_bar(context.character.clone());
+        self.in_flight_continues = true;
+
+        let msg = llm_engine::LlmEngineRequest::TextInference(context);
+        if let Err(err) = self.send_to_server.send(msg) {
+            log::error!("Error during text infer redo request: {}", err);
+        }
+    }
+
+    // cuts the last response down to its first sentence and continues it from there, for
+    // when a response starts well but then goes somewhere unwanted.
+    fn regenerate_keeping_first_sentence(&mut self) {
+        if self.refuse_while_generating("regenerate a response")
+            || self.refuse_regenerating_user_reply()
+        {
+            return;
+        }
+
+        // only the newest response can be continued
+        if self.chatlog_scroll != 0 {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Cannot Regenerate",
+                "Only the newest response can be regenerated after its first sentence. Press home to select it first.",
+                50,
+                30,
+            ));
+            return;
+        }
+
+        let Some(original) = self.chatlog.last().cloned() else {
+            return;
+        };
+        let was_truncated = self
+            .chatlog
+            .get_mut(self.chatlog.len() - 1)
+            .is_some_and(|item| item.truncate_to_first_sentence());
+        if !was_truncated {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Cannot Regenerate",
+                "The last response is only a single sentence, so there's nothing after it to regenerate. Use ctrl-r to regenerate the whole response instead.",
+                50,
+                30,
+            ));
+            return;
+        }
+
+        self.commit_last_chatlog_item(true);
+        self.continue_last_response();
+        self.regenerated_item = Some(original);
+        self.regenerate_count += 1;
+    }
+
+    // a skip of the similar sentences that isn't pinned only lasts for one response.
+    fn end_turn_for_similar_sentences_skip(&mut self) {
+        if !self.pin_skip_similar_sentences {
+            self.skip_similar_sentences = false;
+        }
+    }
+
+    // applies the configured clean up to a new response before it gets committed to the log.
+    fn tidy_new_response(&mut self, item: &mut ChatLogItem) {
+        if let Some(marker) = self.config.scene_end_marker.as_deref() {
+            if item.truncate_at_marker(marker) {
+                self.scene_ended = true;
+            }
+        }
+
+        // the patterns come from the file of the character that gave the response
+        if !self.response_trim_patterns.contains_key(&item.entity) {
+            let compiled = std::iter::once(&self.character)
+                .chain(self.other_participants.iter().map(|(c, _)| c))
+                .find(|c| c.name == item.entity)
+                .map(|c| c.compile_response_trim_patterns())
+                .unwrap_or_default();
+            self.response_trim_patterns
+                .insert(item.entity.clone(), compiled);
+        }
+        if let Some(patterns) = self.response_trim_patterns.get(&item.entity) {
+            item.remove_pattern_matches(patterns);
+        }
+
+        if let Some(max_blank_lines) = self.config.collapse_blank_lines {
+            item.collapse_blank_lines(max_blank_lines);
+        }
+
+        if self.user_narration_patterns.is_empty() {
+            return;
+        }
+        let action = self
+            .config
+            .user_narration_action
+            .clone()
+            .unwrap_or_default();
+        self.user_narration_notice = match action {
+            UserNarrationAction::Remove => {
+                let removed = item.remove_matching_sentences(&self.user_narration_patterns);
+                (removed > 0).then(|| {
+                    format!(
+                        "Removed {} sentence(s) narrating for you from the response",
+                        removed
+                    )
+                })
+            }
+            UserNarrationAction::Flag => {
+                let found = item.count_matching_sentences(&self.user_narration_patterns);
+                (found > 0)
+                    .then(|| format!("The response has {} sentence(s) narrating for you", found))
+            }
+        };
+    }
+
+    // keeps the sentence similarity matches used to generate the item on it, if configured to.
+    fn record_similar_matches(
+        &self,
+        item: &mut ChatLogItem,
+        similar_matches: Option<Vec<SimilarSentenceMatch>>,
+    ) {
+        if self.config.record_similar_sentence_matches.unwrap_or(false) {
+            item.similar_matches = similar_matches;
+        }
+    }
+
+    // asks the engine for several responses from the main character, each with its own seed,
+    // which are then shown in a chooser so the best one can be kept.
+    fn generate_candidate_responses(&mut self) {
+        let count = self
+            .config
+            .candidate_count
+            .unwrap_or(DEFAULT_CANDIDATE_COUNT)
+            .clamp(1, MAX_CANDIDATE_COUNT);
+        let context = TextInferenceContext {
+            character: self.character.clone(),
+            model_config_override: None,
+            chatlog_owner: self.character.clone(),
+            other_participants: self.other_participants.clone(),
+            chatlog: self.chatlog.clone(),
+            should_continue: false,
+            parameters: self.current_parameters.clone(),
+            nudge: None,
+            randomize_seed: true,
+            similar_matches: None,
+            skip_similar_sentences: self.skip_similar_sentences,
+            dropped_turns: 0,
+        };
+        let msg = llm_engine::LlmEngineRequest::TextInferenceCandidates(context, count);
+        if let Err(err) = self.send_to_server.send(msg) {
+            log::error!("Error during text infer candidates request: {}", err);
+        }
+        self.regenerate_count = 0;
+        self.candidate_progress = Some((0, count));
+        self.show_progress_bar(self.character.clone());
+    }
+
+    fn process_input_for_candidate_chooser(&mut self, event: TerminalEvent) {
+        if let TerminalEvent::Key(key) = event {
+            let Some((speaker, chooser, similar_matches)) = self.candidate_chooser.as_mut() else {
+                return;
+            };
+            match key.code {
+                KeyCode::Esc => {
+                    self.candidate_chooser = None;
+                }
+                KeyCode::Char('k') | KeyCode::Char('h') | KeyCode::Left => chooser.previous(),
+                KeyCode::Char('j') | KeyCode::Char('l') | KeyCode::Right => chooser.next(),
+                KeyCode::Char('r') => {
+                    // throw all of them away and ask for a fresh batch
+                    self.candidate_chooser = None;
+                    self.generate_candidate_responses();
+                }
+                KeyCode::Enter => {
+                    let chosen = chooser
+                        .state
+                        .selected()
+                        .and_then(|i| chooser.items.get(i))
+                        .map(|resp| ChatLogItem::new_from_str(speaker.clone(), resp.as_str()));
+                    let similar_matches = similar_matches.take();
+                    self.candidate_chooser = None;
+
+                    // the chosen response gets committed just like a normal new response
+                    if let Some(mut new_item) = chosen {
+                        self.tidy_new_response(&mut new_item);
+                        self.record_similar_matches(&mut new_item, similar_matches);
+                        self.chatlog.push(new_item);
+                        self.commit_last_chatlog_item(false);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render_candidate_chooser(&mut self, frame: &mut Frame) {
+        let Some((speaker, chooser, _)) = self.candidate_chooser.as_ref() else {
+            return;
+        };
+        let area = centered_rect(80, 80, frame.size());
+        let list_height = (chooser.items.len() as u16 + 2).min(area.height / 2);
+        let vchunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(list_height), Constraint::Min(3)].as_ref())
+            .split(area);
+
+        // the list only shows the start of each response; the selected one is shown in full below
+        let preview_width = (vchunks[0].width as usize).saturating_sub(6);
+        let items: Vec<ListItem> = chooser
+            .items
+            .iter()
+            .map(|c| {
+                let preview: String = c
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .chars()
+                    .take(preview_width)
+                    .collect();
+                ListItem::new(preview)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title("Candidate Responses (left/right = preview, enter = keep, r = discard all and regenerate, esc = discard all)")
+                    .borders(Borders::ALL),
+            )
+            .highlight_style(self.config.get_selection_highlight_style())
+            .highlight_symbol(self.config.get_selection_highlight_symbol());
+
+        // the selected one is previewed the way it would look once it's in the chatlog
+        let text_width = (vchunks[1].width as usize).saturating_sub(2);
+        let mut selected_lines = vec![];
+        if let Some(selected) = chooser.state.selected().and_then(|i| chooser.items.get(i)) {
+            let preview_item = ChatLogItem::new_from_str(speaker.clone(), selected.as_str());
+            self.render_chatlog_item(
+                &preview_item,
+                text_width,
+                self.config.name_display_style.clone().unwrap_or_default()
+                    == NameDisplayStyle::Header,
+                self.config.collapse_blank_lines,
+                false,
+                &mut selected_lines,
+            );
+        }
+        let selected_p = Paragraph::new(selected_lines)
+            .alignment(self.get_chat_text_alignment())
+            .block(
+                Block::default()
+                    .title("Selected Response")
+                    .borders(Borders::ALL),
+            );
+        let mut list_state = chooser.state.clone();
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, vchunks[0], &mut list_state);
+        frame.render_widget(selected_p, vchunks[1]);
+    }
+
+    // shows a message and returns true if the last chatlog item is the user's own reply,
+    // since regenerating would otherwise delete it.
+    fn refuse_regenerating_user_reply(&mut self) -> bool {
+        if let Some(lastmsg) = self.chatlog.last() {
+            if lastmsg.entity.eq(self.config.display_name.as_str()) {
+                let modal = MessageBoxModalWidget::new(
+                    "Cannot Regenerate",
+                    "The last message in the chatlog is your own reply, so there's no AI response to regenerate.\n\n\
+                    Use ctrl-y to generate a response or ctrl-x to delete your reply instead.",
+                    50,
+                    30,
+                );
+                self.modal_messagebox = Some(modal);
+                return true;
+            }
+        }
+        false
+    }
+
+    // shows the raw entity and lines stored for the selected chatlog item, with control
+    // characters and quotes escaped, to help track down items that render strangely.
+    fn describe_selected_chatlog_item(&mut self) {
+        let Some(index) = self.chatlog.len().checked_sub(self.chatlog_scroll + 1) else {
+            return;
+        };
+        let Some(item) = self.chatlog.get(index) else {
+            return;
+        };
+
+        let mut description = format!(
+            "item: {}\nentity: {:?}\nlines: {}\n",
+            index,
+            item.entity,
+            item.lines.len()
+        );
+        for (i, line) in item.lines.iter().enumerate() {
+            description.push_str(&format!("\n[{}] {:?}", i, line));
+        }
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+            "Chatlog Item (j/k = scroll)",
+            description.as_str(),
+            80,
+            80,
+        ));
+    }
+
+    // asks the engine for a new estimate of the prompt size if it's being shown and the chatlog
+    // changed since the last one. it waits while the engine is busy generating.
+    fn request_prompt_estimate_if_stale(&mut self) {
+        if !self.show_prompt_estimate || !self.prompt_estimate_stale || self.waiting_for_operation {
+            return;
+        }
+        self.prompt_estimate_stale = false;
+
+        let context = TextInferenceContext {
+            character: self.character.clone(),
+            model_config_override: None,
+            chatlog_owner: self.character.clone(),
+            other_participants: self.other_participants.clone(),
+            chatlog: self.chatlog.clone(),
+            should_continue: false,
+            parameters: self.current_parameters.clone(),
+            nudge: None,
+            randomize_seed: false,
+            similar_matches: None,
+            skip_similar_sentences: true,
+            dropped_turns: 0,
+        };
+        let cmd = LlmEngineCommand::EstimatePrompt(context);
+        if let Err(err) = self.send_cmd_to_server.send(cmd) {
+            log::error!("Error sending the prompt estimate command: {}", err);
+        }
+    }
+
+    // restarts the chat from the character's greeting, or from nothing if so configured, while
+    // keeping the rest of the chatlog's setup. the save backs up the log first, so the cleared
+    // conversation can be restored from the log selector's backups.
+    fn clear_chat(&mut self) {
+        self.chatlog.restart_from_greeting(
+            &self.character,
+            &self.config,
+            self.config.clear_chat_keeps_greeting.unwrap_or(true),
+        );
+        self.chatlog_scroll = 0;
+        self.scene_ended = false;
+        self.omitted_turns = 0;
+        self.show_similar_matches = false;
+        self.regenerate_count = 0;
+        let _ = self.save_chatlog_to_last_used();
+    }
+
+    // asks for the dataset file to append the selected AI response to, as long as there's
+    // an AI response selected.
+    fn open_dataset_editor(&mut self) {
+        let index = self.get_currently_select_chatlogitem_index();
+        let Some(item) = self.chatlog.get(index) else {
+            return;
+        };
+        if item.entity.eq(self.config.display_name.as_str()) {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Not Added To Dataset",
+                "Select an AI response to use as the output of the training example.",
+                50,
+                20,
+            ));
+            return;
+        }
+
+        let ce = TextEditingBlockModalWidget::new(
+            "Dataset file to append the exchange to (next to the chatlog):".to_owned(),
+            self.last_dataset_filename.clone(),
+        );
+        self.dataset_editor = Some((index, ce));
+    }
+
+    // appends the chatlog item at `index` and the turn(s) before it as a single input-output
+    // item to the jsonl dataset file, which lives in the chatlog's folder unless it's absolute.
+    fn append_exchange_to_dataset(&mut self, index: usize, filename: String) {
+        let Some(log_dir) = self
+            .chatlog
+            .get_last_used_filepath()
+            .and_then(|fp| fp.parent())
+        else {
+            log::error!("Unable to find the chatlog's folder to append the dataset item to.");
+            return;
+        };
+        let dataset_filepath = log_dir.join(&filename);
+        self.last_dataset_filename = filename;
+
+        let (title, message) = match self
+            .chatlog
+            .append_dataset_item_to_file(index, &dataset_filepath)
+        {
+            Ok(_) => (
+                "Added To Dataset",
+                format!("Appended the exchange to {:?}.", dataset_filepath),
+            ),
+            Err(err) => {
+                log::error!(
+                    "Failed to append the exchange to the dataset ({:?}): {}",
+                    dataset_filepath,
+                    err
+                );
+                ("Not Added To Dataset", format!("{}", err))
+            }
+        };
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(title, message.as_str(), 50, 20));
+    }
+
+    // opens the list of all the parameter sets with the current one selected.
+    fn open_parameters_browser(&mut self) {
+        let names: Vec<String> = self
+            .config
+            .parameters
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        let current_index = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(self.current_parameters.name.as_str()));
+        let mut browser = StatefulList::with_items(names);
+        if !browser.items.is_empty() {
+            browser.state.select(Some(current_index.unwrap_or(0)));
+        }
+        self.parameters_browser = Some(browser);
+    }
+
+    fn process_input_for_parameters_browser(&mut self, event: TerminalEvent) {
+        if let TerminalEvent::Key(key) = event {
+            let Some(browser) = self.parameters_browser.as_mut() else {
+                return;
+            };
+            match key.code {
+                KeyCode::Esc => {
+                    self.parameters_browser = None;
+                    self.regenerate_after_parameters_browser = false;
+                }
+                KeyCode::Char('k') => browser.previous(),
+                KeyCode::Char('j') => browser.next(),
+                KeyCode::Enter => {
+                    if let Some(pset) = browser
+                        .state
+                        .selected()
+                        .and_then(|i| self.config.parameters.get(i))
+                    {
+                        self.current_parameters = pset.clone();
+                    }
+                    self.parameters_browser = None;
+                    self.remember_parameters_in_chatlog();
+                    if self.regenerate_after_parameters_browser {
+                        self.regenerate_after_parameters_browser = false;
+                        self.regenerate_last_response(None);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render_parameters_browser(&mut self, frame: &mut Frame) {
+        let Some(browser) = self.parameters_browser.as_mut() else {
+            return;
+        };
+        let area = centered_rect(70, 60, frame.size());
+        let hchunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+            .split(area);
+
+        let items: Vec<ListItem> = browser
+            .items
+            .iter()
+            .map(|name| ListItem::new(name.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(if self.regenerate_after_parameters_browser {
+                        "Parameter Sets (enter = regenerate with it, esc = back)"
+                    } else {
+                        "Parameter Sets (enter = use, esc = back)"
+                    })
+                    .borders(Borders::ALL),
+            )
+            .highlight_style(self.config.get_selection_highlight_style())
+            .highlight_symbol(self.config.get_selection_highlight_symbol());
 
-                    chat_history.push(Line::from(spans));
-                }
+        // preview the values of the highlighted set next to the list
+        let mut preview_lines = vec![];
+        if let Some(pset) = browser
+            .state
+            .selected()
+            .and_then(|i| self.config.parameters.get(i))
+        {
+            for field in EDITABLE_PARAMETERS.iter() {
+                let value = field
+                    .value_string(pset)
+                    .unwrap_or_else(|| "unset".to_owned());
+                preview_lines.push(Line::from(format!("{}: {}", field.label(), value)));
             }
+        }
+        let preview = Paragraph::new(preview_lines)
+            .style(Style::default().fg(Color::Cyan))
+            .block(Block::default().title("Values").borders(Borders::ALL));
 
-            if chat_history.len() >= lines_needed {
-                break;
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, hchunks[0], &mut browser.state);
+        frame.render_widget(preview, hchunks[1]);
+    }
+
+    // copies the edited current parameters over the configured set with the same name so
+    // the changes survive switching between sets and can be saved to the config file.
+    fn store_current_parameters(&mut self) {
+        if let Some(pset) = self.config.parameters.iter_mut().find(|p| {
+            p.name
+                .eq_ignore_ascii_case(self.current_parameters.name.as_str())
+        }) {
+            *pset = self.current_parameters.clone();
+        }
+    }
+
+    // searches the chatlog for `text`, ignoring case, and selects the newest item containing it.
+    // an empty `text` just clears the last search.
+    fn find_in_chatlog(&mut self, text: String) {
+        if text.is_empty() {
+            self.find_text = None;
+            return;
+        }
+        self.find_text = Some(text);
+        match self.find_matching_items().last() {
+            Some(newest) => {
+                self.chatlog_scroll = self.chatlog.len() - 1 - newest;
+                self.last_scrolled_at = Some(Instant::now());
             }
+            None => {
+                let message = format!(
+                    "Nothing in the chatlog matches '{}'.",
+                    self.find_text.take().unwrap_or_default()
+                );
+                self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                    "Search",
+                    message.as_str(),
+                    50,
+                    20,
+                ));
+            }
+        }
+    }
 
-            // potentially add a buffer line if configured to do so
-            if let Some(add_divider) = self.config.add_visual_buffer_between_chatlog_items {
-                if add_divider {
-                    chat_history.push(Line::from(" "));
-                }
+    // returns true if the item contains the text of the current chatlog search, ignoring case.
+    fn chatlog_item_matches_find(&self, item: &ChatLogItem) -> bool {
+        match self.find_text.as_deref() {
+            Some(find_text) => {
+                let find_text = find_text.to_lowercase();
+                item.lines
+                    .iter()
+                    .any(|line| line.to_lowercase().contains(&find_text))
             }
+            None => false,
         }
+    }
 
-        // use the configured text alignment here.
-        let alignment = if let Some(justification) = &self.config.chat_text_justification {
-            justification.clone().into()
+    // returns the indices of the chatlog items matching the current search, oldest first.
+    fn find_matching_items(&self) -> Vec<usize> {
+        self.chatlog
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| self.chatlog_item_matches_find(item))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // selects the next item matching the current search that's older (or newer) than the
+    // selected one, wrapping around at the end of the chatlog.
+    fn jump_to_find_match(&mut self, older: bool) {
+        let matches = self.find_matching_items();
+        let selected = self.get_currently_select_chatlogitem_index();
+        let next = if older {
+            matches
+                .iter()
+                .rev()
+                .find(|i| **i < selected)
+                .or(matches.last())
         } else {
-            Alignment::Right
+            matches.iter().find(|i| **i > selected).or(matches.first())
         };
-        let chatlog = Paragraph::new(chat_history).alignment(alignment);
-        frame.render_widget(chatlog, area);
+        match next {
+            Some(index) => {
+                self.chatlog_scroll = self.chatlog.len() - 1 - index;
+                self.last_scrolled_at = Some(Instant::now());
+            }
+            None => {
+                self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                    "Search",
+                    "Nothing in the chatlog matches the search anymore.",
+                    50,
+                    20,
+                ));
+            }
+        }
     }
 
-    fn render_progress_bar(&mut self, frame: &mut Frame, area: Rect) {
-        // lets create the widget if we haven't already
-        if self.progress_widget.is_none() {
-            let mut primary = self.config.progress_primary_rgb.unwrap_or([10, 242, 10]);
-            let secondary = self.config.progress_secondary_rgb.unwrap_or([62, 62, 62]);
+    // puts the last deleted chatlog item back where it was, selects it and saves the log.
+    // only one deletion is kept, so a second undo has nothing to do.
+    fn undo_last_delete(&mut self) {
+        let Some((index, item)) = self.last_deleted.take() else {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Undo",
+                "There's no deleted chatlog item to put back.",
+                50,
+                20,
+            ));
+            return;
+        };
+        self.chatlog.insert(index, item);
+        let index = index.min(self.chatlog.len() - 1);
+        self.chatlog_scroll = self.chatlog.len() - 1 - index;
+        let _ = self.save_chatlog_to_last_used();
+    }
 
-            // check to see if the character we're waiting on has an rgb value set for the name
-            // and if so, use that for the primary color
-            if let Some(char) = &self.waiting_for_character {
-                if let Some(rgb) = char.name_rgb {
-                    primary = rgb;
+    // records the current parameter set's name in the chatlog and saves it, if it changed.
+    fn remember_parameters_in_chatlog(&mut self) {
+        let name = &self.current_parameters.name;
+        if !name.is_empty() && self.chatlog.last_parameters_name.as_ref() != Some(name) {
+            self.chatlog.last_parameters_name = Some(name.clone());
+            let _ = self.save_chatlog_to_last_used();
+        }
+    }
+
+    // adds a copy of the current parameters as a new set with the given name and selects it.
+    fn clone_current_parameters(&mut self, new_name: String) {
+        if self
+            .config
+            .parameters
+            .iter()
+            .any(|p| p.name.eq_ignore_ascii_case(new_name.as_str()))
+        {
+            let modal = MessageBoxModalWidget::new(
+                "Name Already Used",
+                format!("A parameter set named \"{}\" already exists.", new_name).as_str(),
+                50,
+                20,
+            );
+            self.modal_messagebox = Some(modal);
+            return;
+        }
+
+        let mut new_params = self.current_parameters.clone();
+        new_params.name = new_name;
+        self.config.parameters.push(new_params.clone());
+        self.current_parameters = new_params;
+    }
+
+    // returns the file the chatlog gets saved to, if it has one.
+    pub fn get_chatlog_filepath(&self) -> Option<&PathBuf> {
+        self.chatlog.get_last_used_filepath()
+    }
+
+    // goes back to the main menu, stopping any response still being generated first so that it
+    // doesn't show up in the next chat that gets opened.
+    fn leave_chat(&mut self) -> ProcessInputResult {
+        if self.waiting_for_operation {
+            if let Err(err) = self
+                .send_cmd_to_server
+                .send(LlmEngineCommand::CancelTextInference)
+            {
+                log::error!("Error sending the cancel command: {}", err);
+            }
+
+            // the cancel only stops the engine at its next chance, so wait here for the
+            // request to finish; whatever it sends back is thrown away with this chat.
+            loop {
+                match self.recv_on_client.recv() {
+                    Ok(llm_engine::LlmEngineResponse::NewText(_, _))
+                    | Ok(llm_engine::LlmEngineResponse::NewCandidates(_, _))
+                    | Ok(llm_engine::LlmEngineResponse::TextInferenceCancelled(_)) => break,
+                    Ok(llm_engine::LlmEngineResponse::ModelSwitched(model_name)) => {
+                        self.active_model_name = model_name;
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::error!("Error waiting for the cancelled text inference: {}", err);
+                        break;
+                    }
                 }
             }
+            self.hide_progress_bar();
+        }
+        ProcessInputResult::ChangeScene(crate::application::ApplicationState::MainMenu)
+    }
 
-            let new_pw = ProgressBarScopeSignal::new(primary, secondary);
-            self.progress_widget = Some(new_pw);
+    // returns the name of the model configuration the engine last reported as loaded.
+    pub fn get_active_model_name(&self) -> &str {
+        &self.active_model_name
+    }
+
+    // pops up a message box in the chat with the given title and text.
+    pub fn show_message(&mut self, title: &str, text: &str) {
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(title, text, 60, 30));
+    }
+
+    // pops up a message saying the action can't be done while a response is being generated
+    // and returns true if one is; the response replaces the whole chatlog when it arrives.
+    fn refuse_while_generating(&mut self, action: &str) -> bool {
+        if self.waiting_for_operation {
+            self.show_message(
+                "Still Generating",
+                format!(
+                    "Can't {} while a response is being generated. Wait for it to finish or cancel it with ctrl-c first.",
+                    action
+                )
+                .as_str(),
+            );
         }
+        self.waiting_for_operation
+    }
 
-        let pw = self.progress_widget.as_mut().unwrap();
-        pw.render(frame, area);
+    // switches over to a reloaded configuration. the parameter set in use is refreshed from it
+    // if it's still configured, otherwise it's kept as it was; either way the sampler settings
+    // from the command line still win.
+    pub fn apply_config(&mut self, config: ConfigurationFile) {
+        if let Some(pset) = config
+            .parameters
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(&self.current_parameters.name))
+        {
+            self.current_parameters = pset.clone();
+            self.parameter_overrides.apply(&mut self.current_parameters);
+        }
+        self.user_narration_patterns = compile_user_narration_patterns(&config);
+        self.config = config;
+        self.prompt_estimate_stale = true;
+    }
+
+    // returns the configured parameter sets, including any edits made in the parameters modal.
+    pub fn get_parameter_sets(&self) -> &Vec<ConfiguredParameters> {
+        &self.config.parameters
     }
 
     // tells the UI to show the progress bar on next render
     fn show_progress_bar(&mut self, char_to_wait_on: CharacterFileYaml) {
         self.waiting_for_character = Some(char_to_wait_on);
         self.waiting_for_operation = true;
+        self.in_flight_text.clear();
+        self.in_flight_fragment_ends.clear();
+        self.in_flight_continues = false;
+        self.in_flight_scroll = 0;
     }
 
     // tells the UI to no longer show the progress bar and free the widget
     fn hide_progress_bar(&mut self) {
         self.waiting_for_operation = false;
+        self.engine_status = None;
+        self.candidate_progress = None;
         self.progress_widget = None;
         self.waiting_for_character = None;
+        self.in_flight_text.clear();
+        self.in_flight_fragment_ends.clear();
+        self.in_flight_scroll = 0;
     }
 
     // a helper function to return the index into the chatlog for the currently
     // selected item. barely more space efficient than typing the code out...
     fn get_currently_select_chatlogitem_index(&self) -> usize {
-        self.chatlog.len() - self.chatlog_scroll - 1
+        // an empty chatlog, or one scrolled past its first item, just points at the start
+        self.chatlog.len().saturating_sub(self.chatlog_scroll + 1)
     }
 }
 
 impl TerminalRenderable for ChatState {
     fn process_input(&mut self, event: TerminalEvent) -> ProcessInputResult {
+        // the note about user narration in the last response goes away with the next key press
+        let is_key_press = matches!(event, TerminalEvent::Key(_));
+        if is_key_press {
+            self.user_narration_notice = None;
+        }
+
         // make sure to check for incoming message from the LLM engine
         self.process_incoming_llm_engine_messages();
+        self.request_prompt_estimate_if_stale();
 
         let mut result = ProcessInputResult::None;
         let index = self.get_currently_select_chatlogitem_index();
@@ -795,13 +3241,24 @@ impl TerminalRenderable for ChatState {
             if msgbox.is_finished {
                 self.modal_messagebox = None;
             }
+        } else if let Some(confirm) = self.leave_confirm.as_mut() {
+            confirm.process_input(event);
+            if confirm.is_finished {
+                let confirmed = confirm.is_confirmed;
+                self.leave_confirm = None;
+                if confirmed {
+                    result = self.leave_chat();
+                }
+            }
         } else if let Some(logitem_editor) = self.logitem_editor.as_mut() {
             logitem_editor.process_input(event);
             if logitem_editor.is_finished {
                 if logitem_editor.is_success {
                     // if the editted string is empty, then just remove the chatlogitem
                     if logitem_editor.text.is_empty() {
-                        self.chatlog.remove(index);
+                        if let Some(removed) = self.chatlog.remove(index) {
+                            self.last_deleted = Some((index, removed));
+                        }
                     } else {
                         // if we made an edit, replace the strings in the chatlogitem
                         // and then attempt to save the logfile to secure the edits
@@ -835,6 +3292,90 @@ impl TerminalRenderable for ChatState {
                         self.chatlog.get_last_used_filepath());
                 }
             }
+        } else if let Some(editor) = self.saveas_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let new_filepath = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.saveas_editor = None;
+                if accepted && !new_filepath.is_empty() {
+                    // saving over some other file would lose it, so only the file the chat
+                    // already saves to may be written again
+                    let fp = PathBuf::from(&new_filepath);
+                    let is_current_file = self
+                        .chatlog
+                        .get_last_used_filepath()
+                        .is_some_and(|current_fp| *current_fp == fp);
+                    if fp.exists() && !is_current_file {
+                        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                            "Chatlog Not Saved",
+                            format!("A file already exists at {}.", new_filepath).as_str(),
+                            50,
+                            20,
+                        ));
+                    } else {
+                        self.save_chatlog_as(&new_filepath);
+                    }
+                }
+            }
+        } else if let Some(editor) = self.newlog_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let log_name = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.newlog_editor = None;
+                if accepted && !log_name.is_empty() {
+                    self.save_chatlog_as_new_log(&log_name);
+                }
+            }
+        } else if let Some(editor) = self.clear_chat_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let confirmed = editor.is_success
+                    && editor
+                        .text
+                        .trim()
+                        .eq_ignore_ascii_case(CLEAR_CHAT_CONFIRMATION);
+                self.clear_chat_editor = None;
+                if confirmed {
+                    self.clear_chat();
+                }
+            }
+        } else if let Some(editor) = self.find_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let text = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.find_editor = None;
+                if accepted {
+                    self.find_in_chatlog(text);
+                }
+            }
+        } else if let Some(editor) = self.instruct_template_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let template = editor.text.replace("\\n", "\n");
+                let accepted = editor.is_success;
+                self.instruct_template_editor = None;
+                if accepted && !template.trim().is_empty() {
+                    let cmd = LlmEngineCommand::SetInstructTemplate(template);
+                    if let Err(err) = self.send_cmd_to_server.send(cmd) {
+                        log::error!("Error sending the instruct template command: {}", err);
+                    }
+                    self.prompt_estimate_stale = true;
+                }
+            }
+        } else if let Some((index, editor)) = self.dataset_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let index = *index;
+                let filename = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.dataset_editor = None;
+                if accepted && !filename.is_empty() {
+                    self.append_exchange_to_dataset(index, filename);
+                }
+            }
         } else if let Some(editor) = self.userdesc_editor.as_mut() {
             editor.process_input(event);
             if editor.is_finished {
@@ -853,6 +3394,52 @@ impl TerminalRenderable for ChatState {
                         self.chatlog.get_last_used_filepath());
                 }
             }
+        } else if let Some(editor) = self.nudge_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let nudge = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.nudge_editor = None;
+                if accepted && !nudge.is_empty() {
+                    self.regenerate_last_response(Some(nudge));
+                }
+            }
+        } else if let Some(editor) = self.tags_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                if editor.is_success {
+                    let tags: Vec<String> = editor
+                        .text
+                        .split(',')
+                        .map(|t| t.trim().to_owned())
+                        .filter(|t| !t.is_empty())
+                        .collect();
+                    self.chatlog.tags = if tags.is_empty() { None } else { Some(tags) };
+                }
+                self.tags_editor = None;
+
+                // attempt to save the changes to the chatlog
+                if !self.save_chatlog_to_last_used() {
+                    log::error!(
+                        "Failed to save the chatlog to the last used file ({:?}) after editing the tags.",
+                        self.chatlog.get_last_used_filepath()
+                    );
+                }
+            }
+        } else if let Some(editor) = self.parameters_name_editor.as_mut() {
+            editor.process_input(event);
+            if editor.is_finished {
+                let new_name = editor.text.trim().to_owned();
+                let accepted = editor.is_success;
+                self.parameters_name_editor = None;
+                if accepted && !new_name.is_empty() {
+                    self.clone_current_parameters(new_name);
+                }
+            }
+        } else if self.candidate_chooser.is_some() {
+            self.process_input_for_candidate_chooser(event);
+        } else if self.parameters_browser.is_some() {
+            self.process_input_for_parameters_browser(event);
         } else if self.editing_parameters {
             self.process_input_for_editing_parameters(event);
         } else if self.editing_reply {
@@ -861,79 +3448,255 @@ impl TerminalRenderable for ChatState {
             result = self.process_input_for_viewing_chatlog(event);
         }
 
+        // any key press counts as activity and pushes back a pending idle auto-save,
+        // but leaving the chat saves right away.
+        if is_key_press && self.autosave_pending_since.is_some() {
+            self.autosave_pending_since = Some(Instant::now());
+        }
+        self.autosave_if_idle(!matches!(result, ProcessInputResult::None));
+
         result
     }
 
     fn render(&mut self, frame: &mut Frame) {
         frame.render_widget(Clear, frame.size());
 
-        // use 80% of the frame up to the max width
+        // use 80% of the frame up to the max width, or all of it on narrow terminals
+        let margin_percent = if is_compact_layout(frame.size()) {
+            0
+        } else {
+            10
+        };
         let hchunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints(
                 [
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(80),
-                    Constraint::Percentage(10),
+                    Constraint::Percentage(margin_percent),
+                    Constraint::Percentage(100 - margin_percent * 2),
+                    Constraint::Percentage(margin_percent),
                 ]
                 .as_ref(),
             )
             .split(frame.size());
 
-        let chatlog_widget_width: usize = hchunks[1].width as usize;
+        // optionally narrow the main column further, keeping it centered, so that
+        // lines of text don't get too long to read comfortably on wide terminals
+        let mut main_column = hchunks[1];
+        if let Some(max_line_width) = self.config.max_line_width {
+            if max_line_width > 0 && main_column.width > max_line_width {
+                main_column.x += (main_column.width - max_line_width) / 2;
+                main_column.width = max_line_width;
+            }
+        }
+
+        // the reply box can be narrower still, and its border takes up a column on each side
+        let mut reply_box_width = main_column.width;
+        if let Some(max_reply_width) = self.config.max_reply_width {
+            if max_reply_width > 2 {
+                reply_box_width = reply_box_width.min(max_reply_width);
+            }
+        }
+        let reply_text_width = reply_box_width.saturating_sub(2) as usize;
 
-        // build up the reply we're editing into a list of strings our column size
+        // build up the reply we're editing into a list of strings the reply box's size
         let mut editing_reply_lines = vec![];
         if self.editing_reply {
             if !self.reply_text.is_empty() {
-                // we don't add our name here, so leading space can be 0
-                for reply_line in self.reply_text.lines() {
-                    let split_lines = slice_up_string(reply_line, chatlog_widget_width, 0);
-                    for split_line in split_lines {
-                        editing_reply_lines.push(Line::from(split_line));
-                    }
-                }
+                editing_reply_lines = self.build_reply_lines(reply_text_width);
             } else {
                 editing_reply_lines.push(Line::from(vec![Span::styled(
                     "<Type Reply Here>",
                     Style::default().fg(Color::Rgb(100, 100, 100)),
                 )]));
             }
-            editing_reply_lines.push(Line::from("-".repeat(chatlog_widget_width)));
         }
 
+        // build the status line shown above the progress bar, if there's anything to say
+        let progress_status = if let Some(status) = &self.engine_status {
+            Some(status.clone())
+        } else if let Some((done, total)) = self.candidate_progress {
+            Some(format!(
+                "Generating candidate {} of {}",
+                (done + 1).min(total),
+                total
+            ))
+        } else if self.regenerate_count > 0 {
+            Some(format!("Regeneration #{}", self.regenerate_count))
+        } else {
+            None
+        };
+
         // start to budget how much space we need in that first row
         let editing_vertical_size = if self.waiting_for_operation {
-            if let Some(widget) = &self.progress_widget {
+            let progress_height = if let Some(widget) = &self.progress_widget {
                 widget.get_requested_widget_height()
             } else {
                 3 // assume there's some space needed
+            };
+            // leave a row for the status message if it's showing
+            if progress_status.is_some() {
+                progress_height + 1
+            } else {
+                progress_height
             }
+        } else if self.editing_reply {
+            editing_reply_lines.len() as u16 + 2 // 2 == the top and bottom border
         } else {
-            editing_reply_lines.len() as u16
+            0
         };
 
         // do the layout for the main column
         let vchunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([Constraint::Max(editing_vertical_size), Constraint::Min(4)].as_ref())
-            .split(hchunks[1]);
+            .split(main_column);
 
         // render either the reply editing or a progress bar
         if self.editing_reply {
-            let alignment = if let Some(justification) = &self.config.chat_text_justification {
-                justification.clone().into()
+            // the border sets the reply apart from the chatlog below it
+            let mut reply_area = vchunks[0];
+            reply_area.x += (reply_area.width - reply_box_width.min(reply_area.width)) / 2;
+            reply_area.width = reply_box_width.min(reply_area.width);
+            let editing_reply_p = Paragraph::new(editing_reply_lines)
+                .alignment(self.get_chat_text_alignment())
+                .block(
+                    Block::default()
+                        .title("Reply (ctrl-s = send, enter = new line, esc = cancel)")
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Rgb(100, 100, 100))),
+                );
+            frame.render_widget(editing_reply_p, reply_area);
+        } else if self.waiting_for_operation {
+            // if the engine is switching models or regenerating, say so above the progress bar
+            if let Some(status) = progress_status {
+                let status_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
+                    .split(vchunks[0]);
+                let status_msg = Paragraph::new(Line::from(Span::styled(
+                    status,
+                    Style::default().fg(Color::Rgb(100, 100, 100)),
+                )))
+                .alignment(Alignment::Center);
+                frame.render_widget(status_msg, status_chunks[0]);
+                self.render_progress_bar(frame, status_chunks[1]);
+            } else {
+                self.render_progress_bar(frame, vchunks[0]);
+            }
+        }
+
+        // if the last save failed, keep a warning visible above the chatlog until one succeeds.
+        // otherwise, note anything the user narration patterns did to the last response.
+        let notice = if self.last_save_failed {
+            Some(Span::styled(
+                "Chatlog NOT saved! (ctrl-s to save as)",
+                Style::default().fg(Color::LightRed).bold(),
+            ))
+        } else if let Some(narration_notice) = self.user_narration_notice.as_deref() {
+            Some(Span::styled(
+                narration_notice,
+                Style::default().fg(Color::Yellow),
+            ))
+        } else if self.scene_ended {
+            Some(Span::styled(
+                "The scene has ended (ctrl-l to clear the chat and start over)",
+                Style::default().fg(Color::Cyan).bold(),
+            ))
+        } else if let Some(find_text) = self.find_text.as_deref() {
+            let matches = self.find_matching_items();
+            let selected = self.get_currently_select_chatlogitem_index();
+            let find_notice = match matches.iter().rposition(|i| *i == selected) {
+                Some(pos) => format!(
+                    "Match {} of {} for '{}' (n/N = older/newer, / to change)",
+                    matches.len() - pos,
+                    matches.len(),
+                    find_text
+                ),
+                None => format!(
+                    "{} match(es) for '{}' (n/N = older/newer, / to change)",
+                    matches.len(),
+                    find_text
+                ),
+            };
+            Some(Span::styled(
+                find_notice,
+                Style::default().fg(Color::Yellow),
+            ))
+        } else if self.skip_similar_sentences {
+            let skip_notice = if self.pin_skip_similar_sentences {
+                "Similar sentences are left out of responses (ctrl-u to include them)"
             } else {
-                Alignment::Right
+                "Similar sentences will be left out of the next response (u to include them)"
             };
-            let editing_reply_p = Paragraph::new(editing_reply_lines).alignment(alignment);
-            frame.render_widget(editing_reply_p, vchunks[0]);
-        } else if self.waiting_for_operation {
-            self.render_progress_bar(frame, vchunks[0]);
+            Some(Span::styled(
+                skip_notice,
+                Style::default().fg(Color::Rgb(100, 100, 100)),
+            ))
+        } else {
+            None
+        };
+        // the top line names the active model in the corner and shows the estimated token
+        // usage of the chat in the middle, when it's shown
+        let header_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(3)].as_ref())
+            .split(vchunks[1]);
+        let model_name = Paragraph::new(Line::from(Span::styled(
+            self.active_model_name.as_str(),
+            Style::default().fg(Color::Rgb(100, 100, 100)).italic(),
+        )))
+        .alignment(Alignment::Right);
+        frame.render_widget(model_name, header_chunks[0]);
+        match self.prompt_estimate {
+            Some((_, whole_chat_tokens, context_size, dropped_turns))
+                if self.show_prompt_estimate =>
+            {
+                let mut estimate_text = format!("~{} / {} tokens", whole_chat_tokens, context_size);
+                if dropped_turns > 0 {
+                    estimate_text
+                        .push_str(format!(" ({})", get_omitted_turns_text(dropped_turns)).as_str());
+                }
+                let usage_percent = whole_chat_tokens * 100 / context_size.max(1);
+                let estimate_color = if usage_percent > TOKEN_USAGE_DANGER_PERCENT {
+                    Color::LightRed
+                } else if usage_percent > TOKEN_USAGE_WARNING_PERCENT {
+                    Color::Yellow
+                } else {
+                    Color::Rgb(100, 100, 100)
+                };
+                let estimate = Paragraph::new(Line::from(Span::styled(
+                    estimate_text,
+                    Style::default().fg(estimate_color),
+                )))
+                .alignment(Alignment::Center);
+                frame.render_widget(estimate, header_chunks[0]);
+            }
+            _ if self.omitted_turns > 0 => {
+                // without the estimate, at least say how much the last prompt had to leave out
+                let omitted = Paragraph::new(Line::from(Span::styled(
+                    get_omitted_turns_text(self.omitted_turns),
+                    Style::default().fg(Color::Yellow),
+                )))
+                .alignment(Alignment::Center);
+                frame.render_widget(omitted, header_chunks[0]);
+            }
+            _ => {}
         }
+        let chatlog_area = header_chunks[1];
+        let chatlog_area = if let Some(notice) = notice {
+            let status_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(3)].as_ref())
+                .split(chatlog_area);
+            let warning = Paragraph::new(Line::from(notice)).alignment(Alignment::Center);
+            frame.render_widget(warning, status_chunks[0]);
+            status_chunks[1]
+        } else {
+            chatlog_area
+        };
 
         // render the visible portions of the chatlog
-        self.render_chatlog(frame, vchunks[1]);
+        self.render_chatlog(frame, chatlog_area);
 
         // Now render any modal boxes over the chat log, only selecting one of them to draw.
         // This *should* mimic the same order that input processing gets called so that
@@ -942,6 +3705,10 @@ impl TerminalRenderable for ChatState {
         if let Some(msgbox) = &self.modal_messagebox {
             msgbox.render(frame);
         }
+        // user is confirming they want to leave mid-generation
+        else if let Some(confirm) = &self.leave_confirm {
+            confirm.render(frame);
+        }
         // user is editing a chatlog item
         else if let Some(editor) = &self.logitem_editor {
             editor.render(frame);
@@ -950,10 +3717,54 @@ impl TerminalRenderable for ChatState {
         else if let Some(editor) = &self.context_editor {
             editor.render(frame);
         }
+        // user is picking a new file to save the chatlog to
+        else if let Some(editor) = &self.saveas_editor {
+            editor.render(frame);
+        }
+        // user is naming a new chatlog to save the chat into
+        else if let Some(editor) = &self.newlog_editor {
+            editor.render(frame);
+        }
+        // user is confirming that the chat should be cleared
+        else if let Some(editor) = &self.clear_chat_editor {
+            editor.render(frame);
+        }
+        // user is entering the text to search the chatlog for
+        else if let Some(editor) = &self.find_editor {
+            editor.render(frame);
+        }
+        // user is editing the instruct template of the active model
+        else if let Some(editor) = &self.instruct_template_editor {
+            editor.render(frame);
+        }
+        // user is picking the dataset file to append the selected exchange to
+        else if let Some((_, editor)) = &self.dataset_editor {
+            editor.render(frame);
+        }
         // user is editing the description they use in the chatlog
         else if let Some(editor) = &self.userdesc_editor {
             editor.render(frame);
         }
+        // user is entering an instruction for a regeneration
+        else if let Some(editor) = &self.nudge_editor {
+            editor.render(frame);
+        }
+        // user is editing the tags for the chatlog
+        else if let Some(editor) = &self.tags_editor {
+            editor.render(frame);
+        }
+        // user is naming a copy of the current parameter set
+        else if let Some(editor) = &self.parameters_name_editor {
+            editor.render(frame);
+        }
+        // user is picking one of the candidate responses to keep
+        else if self.candidate_chooser.is_some() {
+            self.render_candidate_chooser(frame);
+        }
+        // user is browsing all of the parameter sets for one to use
+        else if self.parameters_browser.is_some() {
+            self.render_parameters_browser(frame);
+        }
         // if we're showing the parameters, create a new frame for it.
         else if self.editing_parameters {
             self.render_editing_parameters_modal(frame);
@@ -1034,9 +3845,10 @@ impl ProgressBarScopeSignal {
         }
     }
 
-    // should return the number of rows requested for layout of this widget
+    // should return the number of rows requested for layout of this widget, which includes
+    // the row for the elapsed time label above the scope
     fn get_requested_widget_height(&self) -> u16 {
-        5
+        6
     }
 
     fn generate_2d_sin_waves(
@@ -1056,6 +3868,22 @@ impl ProgressBarScopeSignal {
     }
 
     fn render(&mut self, frame: &mut Frame, area: Rect) {
+        // the time spent waiting so far goes on the top row, above the scope
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
+            .split(area);
+        let elapsed_label = Paragraph::new(Line::from(Span::styled(
+            format!(
+                "generating… {:.1}s",
+                self.start_time.elapsed().as_secs_f64()
+            ),
+            Style::default().fg(Color::Rgb(100, 100, 100)),
+        )))
+        .alignment(Alignment::Center);
+        frame.render_widget(elapsed_label, chunks[0]);
+        let area = chunks[1];
+
         // update the data buffer
         let t = self.speed * self.start_time.elapsed().as_secs_f64();
 
@@ -1154,3 +3982,353 @@ impl ProgressBarRandomSparkline {
         frame.render_widget(sparkline, area);
     }
 }
+
+// copies the files kept in the folder of the chatlog at `log_file` into `dst_dir`, skipping the
+// chatlog itself and its journal. directories, like the backups folder, are skipped as well.
+fn copy_log_folder_files(log_file: &Path, dst_dir: &Path) -> std::io::Result<()> {
+    let src_dir = match log_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => return Ok(()),
+    };
+    let journal_file = get_journal_filepath(log_file);
+    for entry in std::fs::read_dir(src_dir)?.flatten() {
+        let path = entry.path();
+        if path.is_file() && path != log_file && path != journal_file {
+            std::fs::copy(&path, dst_dir.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> ConfigurationFile {
+        serde_yaml::from_str(
+            r#"
+version: 1
+display_name: "USER"
+stop_on_display_name: true
+models: []
+parameters:
+  - name: "Default"
+    temperature: 0.7
+    top_k: 40
+  - name: "Precise"
+    temperature: 0.2
+    top_k: 10
+  - name: "Creative"
+    temperature: 1.2
+    top_k: 80
+"#,
+        )
+        .unwrap()
+    }
+
+    fn find_parameters(config: &ConfigurationFile, name: &str) -> ConfiguredParameters {
+        config
+            .parameters
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .unwrap()
+    }
+
+    // builds a chat with the test configuration, returning the ends of the engine channels that
+    // the engine would normally hold so the requests sent by the chat can be checked.
+    fn test_chat_state(
+        chatlog: ChatLog,
+    ) -> (
+        ChatState,
+        Receiver<LlmEngineRequest>,
+        Receiver<LlmEngineCommand>,
+    ) {
+        let (send_to_server, recv_on_server) = crossbeam::channel::bounded(10);
+        let (send_cmd_to_server, recv_cmd_on_server) = crossbeam::channel::bounded(10);
+        let (_send_to_client, recv_on_client) = crossbeam::channel::bounded(10);
+        let character = CharacterFileYaml {
+            name: "Bot".to_owned(),
+            ..Default::default()
+        };
+        let chat_state = ChatState::new(
+            character,
+            chatlog,
+            None,
+            &ParameterOverrides::default(),
+            test_config(),
+            send_to_server,
+            send_cmd_to_server,
+            recv_on_client,
+            String::new(),
+        );
+        (chat_state, recv_on_server, recv_cmd_on_server)
+    }
+
+    #[test]
+    fn regenerating_refuses_a_user_reply() {
+        let mut chatlog = ChatLog::new();
+        chatlog.push(ChatLogItem::new_from_str("Bot".to_owned(), "Hello there."));
+        chatlog.push(ChatLogItem::new_from_str("USER".to_owned(), "Hi!"));
+        let (mut chat_state, recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(chatlog.clone());
+
+        assert!(chat_state.refuse_regenerating_user_reply());
+        assert!(chat_state.modal_messagebox.is_some());
+
+        // asking to regenerate leaves the log alone and doesn't bother the engine
+        chat_state.modal_messagebox = None;
+        chat_state.regenerate_last_response(None);
+        assert!(chat_state.chatlog == chatlog);
+        assert!(chat_state.modal_messagebox.is_some());
+        assert!(recv_on_server.is_empty());
+    }
+
+    #[test]
+    fn regenerating_allows_a_character_response() {
+        let mut chatlog = ChatLog::new();
+        chatlog.push(ChatLogItem::new_from_str("USER".to_owned(), "Hi!"));
+        chatlog.push(ChatLogItem::new_from_str("Bot".to_owned(), "Hello there."));
+        let (mut chat_state, _recv_on_server, _recv_cmd_on_server) = test_chat_state(chatlog);
+
+        assert!(!chat_state.refuse_regenerating_user_reply());
+        assert!(chat_state.modal_messagebox.is_none());
+    }
+
+    #[test]
+    fn regenerating_after_the_first_sentence_can_be_undone() {
+        let mut chatlog = ChatLog::new();
+        chatlog.push(ChatLogItem::new_from_str("USER".to_owned(), "Hi!"));
+        chatlog.push(ChatLogItem::new_from_str(
+            "Bot".to_owned(),
+            "Hello there. How are you today?",
+        ));
+        let (mut chat_state, recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(chatlog.clone());
+
+        chat_state.regenerate_keeping_first_sentence();
+        match recv_on_server.try_recv() {
+            Ok(LlmEngineRequest::TextInference(context)) => {
+                assert!(context.should_continue);
+                assert_eq!(
+                    context.chatlog.last().unwrap().get_items_as_string(),
+                    "Hello there."
+                );
+            }
+            _ => panic!("expected a text inference request"),
+        }
+
+        // a cancel or an empty response puts the whole response back
+        let regenerated_item = chat_state.regenerated_item.take();
+        chat_state.restore_regenerated_item(regenerated_item, true);
+        assert!(chat_state.chatlog == chatlog);
+    }
+
+    #[test]
+    fn regenerating_after_the_first_sentence_needs_the_newest_item() {
+        let mut chatlog = ChatLog::new();
+        chatlog.push(ChatLogItem::new_from_str(
+            "Bot".to_owned(),
+            "Hello there. How are you today?",
+        ));
+        chatlog.push(ChatLogItem::new_from_str("USER".to_owned(), "Hi!"));
+        chatlog.push(ChatLogItem::new_from_str("Bot".to_owned(), "Fine. Thanks."));
+        let (mut chat_state, recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(chatlog.clone());
+
+        chat_state.chatlog_scroll = 2;
+        chat_state.regenerate_keeping_first_sentence();
+        assert!(chat_state.modal_messagebox.is_some());
+        assert!(chat_state.chatlog == chatlog);
+        assert!(recv_on_server.is_empty());
+    }
+
+    #[test]
+    fn in_flight_text_hides_the_next_speaker() {
+        let (mut chat_state, _recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(ChatLog::new());
+        chat_state.in_flight_text = "Hello there.\nUSER: and then I".to_owned();
+        assert_eq!(chat_state.get_visible_in_flight_text(), "Hello there.\n");
+
+        chat_state.in_flight_text = "Hello there. Bot: again".to_owned();
+        assert_eq!(chat_state.get_visible_in_flight_text(), "Hello there. ");
+
+        chat_state.in_flight_text = "Nobody else is talking: yet".to_owned();
+        assert_eq!(
+            chat_state.get_visible_in_flight_text(),
+            "Nobody else is talking: yet"
+        );
+
+        // nothing is hidden unless responses stop at the participants' names
+        chat_state.config.stop_on_display_name = false;
+        chat_state.in_flight_text = "Hello there.\nUSER: and then I".to_owned();
+        assert_eq!(
+            chat_state.get_visible_in_flight_text(),
+            "Hello there.\nUSER: and then I"
+        );
+    }
+
+    #[test]
+    fn shaded_in_flight_lines_keep_the_fragment_boundaries() {
+        let (mut chat_state, _recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(ChatLog::new());
+        for fragment in [" Hel", "lo", " there", ".\nBye"] {
+            chat_state.in_flight_text.push_str(fragment);
+            chat_state
+                .in_flight_fragment_ends
+                .push(chat_state.in_flight_text.len());
+        }
+
+        let mut lines = Vec::new();
+        chat_state.push_shaded_in_flight_lines(Some("Bot"), 80, false, &mut lines);
+        assert_eq!(lines.len(), 2);
+        let first: Vec<(String, Style)> = lines[0]
+            .spans
+            .iter()
+            .map(|s| (s.content.to_string(), s.style))
+            .collect();
+        assert_eq!(first[2].0, "Hel");
+        assert_eq!(first[3].0, "lo");
+        assert_eq!(first[4].0, " there");
+        assert_eq!(first[5].0, ".");
+        assert_ne!(first[2].1, first[3].1);
+        assert_eq!(first[2].1, first[4].1);
+        assert_eq!(lines[1].spans[0].content, "Bye");
+        assert_eq!(lines[1].spans[0].style, first[5].1);
+    }
+
+    #[test]
+    fn streaming_toggle_picks_out_the_responding_participant() {
+        let (mut chat_state, _recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(ChatLog::new());
+        let toggle = TerminalEvent::Key(crossterm::event::KeyEvent::new(
+            KeyCode::Char('S'),
+            KeyModifiers::SHIFT,
+        ));
+        let side_character = CharacterFileYaml {
+            name: "Sidekick".to_owned(),
+            ..Default::default()
+        };
+
+        // in multi-chat mode only the character responding gets toggled
+        chat_state.manual_reply_mode = true;
+        chat_state.show_progress_bar(side_character);
+        assert!(chat_state.is_response_streaming_shown());
+        chat_state.process_input_for_viewing_chatlog(toggle.clone());
+        assert!(!chat_state.is_response_streaming_shown());
+        chat_state.hide_progress_bar();
+        chat_state.show_progress_bar(chat_state.character.clone());
+        assert!(chat_state.is_response_streaming_shown());
+
+        // otherwise the whole chat is toggled and the earlier pick is dropped
+        chat_state.hide_progress_bar();
+        chat_state.process_input_for_viewing_chatlog(toggle);
+        assert!(!chat_state.is_response_streaming_shown());
+        assert!(chat_state.participant_streaming_overrides.is_empty());
+    }
+
+    #[test]
+    fn starting_parameters_apply_command_line_overrides_last() {
+        let config = test_config();
+        let character = CharacterFileYaml::default();
+        let mut chatlog = ChatLog::new();
+        chatlog.last_parameters_name = Some("Creative".to_owned());
+        let startup = find_parameters(&config, "Default");
+
+        // without overrides the set last used with the chatlog wins
+        let params = pick_starting_parameters(
+            &config,
+            &character,
+            &chatlog,
+            Some(&startup),
+            &ParameterOverrides::default(),
+        );
+        assert_eq!(params.name, "Creative");
+        assert_eq!(params.temperature, Some(1.2));
+
+        // sampler flags are applied on top of the chatlog's set
+        let overrides = ParameterOverrides {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        let params =
+            pick_starting_parameters(&config, &character, &chatlog, Some(&startup), &overrides);
+        assert_eq!(params.name, "Creative");
+        assert_eq!(params.temperature, Some(0.5));
+        assert_eq!(params.top_k, Some(80));
+
+        // a parameter set picked on the command line skips the chatlog's set
+        let mut startup = find_parameters(&config, "Precise");
+        let overrides = ParameterOverrides {
+            parameters_name: Some("Precise".to_owned()),
+            top_k: Some(5),
+            ..Default::default()
+        };
+        overrides.apply(&mut startup);
+        let params =
+            pick_starting_parameters(&config, &character, &chatlog, Some(&startup), &overrides);
+        assert_eq!(params.name, "Precise");
+        assert_eq!(params.temperature, Some(0.2));
+        assert_eq!(params.top_k, Some(5));
+    }
+
+    #[test]
+    fn reloading_the_config_keeps_command_line_overrides() {
+        let (mut chat_state, _recv_on_server, _recv_cmd_on_server) =
+            test_chat_state(ChatLog::new());
+        chat_state.parameter_overrides = ParameterOverrides {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        chat_state.current_parameters = find_parameters(&chat_state.config, "Precise");
+
+        let mut config = test_config();
+        config.parameters[1].top_k = Some(20);
+        chat_state.apply_config(config);
+        assert_eq!(chat_state.current_parameters.name, "Precise");
+        assert_eq!(chat_state.current_parameters.top_k, Some(20));
+        assert_eq!(chat_state.current_parameters.temperature, Some(0.5));
+    }
+
+    #[test]
+    fn starting_parameters_prefer_command_line_over_character_default() {
+        let config = test_config();
+        let character = CharacterFileYaml {
+            default_parameters_name: Some("Creative".to_owned()),
+            ..Default::default()
+        };
+        let chatlog = ChatLog::new();
+        let startup = find_parameters(&config, "Default");
+
+        // without a set picked on the command line the character's default wins
+        let params = pick_starting_parameters(
+            &config,
+            &character,
+            &chatlog,
+            Some(&startup),
+            &ParameterOverrides::default(),
+        );
+        assert_eq!(params.name, "Creative");
+
+        // sampler flags still apply on top of the character's default
+        let overrides = ParameterOverrides {
+            min_p: Some(0.1),
+            ..Default::default()
+        };
+        let params =
+            pick_starting_parameters(&config, &character, &chatlog, Some(&startup), &overrides);
+        assert_eq!(params.name, "Creative");
+        assert_eq!(params.min_p, Some(0.1));
+
+        // a set picked with -p wins over the character's default
+        let startup = find_parameters(&config, "Precise");
+        let overrides = ParameterOverrides {
+            parameters_name: Some("Precise".to_owned()),
+            ..Default::default()
+        };
+        let params =
+            pick_starting_parameters(&config, &character, &chatlog, Some(&startup), &overrides);
+        assert_eq!(params.name, "Precise");
+        assert_eq!(params.temperature, Some(0.2));
+    }
+}
@@ -1,13 +1,14 @@
 use std::{
     fs::File,
     io::{BufRead, BufReader, BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result, anyhow};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 #[cfg(feature = "sentence_similarity")]
 use candle_core::Tensor;
@@ -18,11 +19,20 @@ use candle_core::Tensor;
 #[cfg(not(feature = "sentence_similarity"))]
 type Tensor = u8;
 
-use crate::config::CharacterFileYaml;
+use crate::config::{CharacterFileYaml, ConfigurationFile};
 
 const CURRENT_CHATLOG_VERSION: u32 = 1;
 static DEFAULT_ENTITY_NAME: &str = "Unknown";
 
+// the name of the folder, next to a chatlog file, that holds its backups
+const BACKUPS_FOLDER_NAME: &str = ".backups";
+
+// the number of backups kept for each chatlog when not configured
+pub const DEFAULT_LOG_BACKUP_COUNT: usize = 10;
+
+// the extension of the journal file kept next to a chatlog file
+const JOURNAL_FILE_EXTENSION: &str = "journal";
+
 // this is one turn of a conversation in the chat log (e.g. the AI's response or the human's query).
 // at present all embeddings generated for the ChatLogItem are kept without regard to which *parts*
 // of the `lines` each embedding covers, though you can reverse engineer that if you know the token
@@ -37,6 +47,33 @@ pub struct ChatLogItem {
 
     #[serde(skip)]
     pub embeddings: Vec<Tensor>,
+
+    // the earlier chatlog items that were matched by sentence similarity and put in the
+    // prompt when this item was generated, if that was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similar_matches: Option<Vec<SimilarSentenceMatch>>,
+
+    // every version of this item's lines that was generated by regenerating it, kept so the
+    // earlier ones can be switched back to. empty until the item is first regenerated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<Vec<String>>,
+
+    // the index into `alternatives` of the version that's in `lines`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub current_alt: usize,
+}
+
+// used to leave a zero `current_alt` out of the serialized chatlog items
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+// a chatlog item that was pulled into a prompt by sentence similarity. the index is the item's
+// position in the log at the time, so moving or removing earlier items can make it stale.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimilarSentenceMatch {
+    pub index: usize,
+    pub score: f32,
 }
 // customize partialeq to only care about the serializable data
 impl PartialEq for ChatLogItem {
@@ -51,6 +88,9 @@ impl ChatLogItem {
             entity: DEFAULT_ENTITY_NAME.to_owned(),
             lines: Vec::new(),
             embeddings: Vec::new(),
+            similar_matches: None,
+            alternatives: Vec::new(),
+            current_alt: 0,
         }
     }
 
@@ -61,6 +101,9 @@ impl ChatLogItem {
             entity,
             lines: v.to_owned(),
             embeddings: Vec::new(),
+            similar_matches: None,
+            alternatives: Vec::new(),
+            current_alt: 0,
         }
     }
 
@@ -102,6 +145,120 @@ impl ChatLogItem {
         self.lines.join("\n")
     }
 
+    // shortens any run of blank lines in the log item to at most `max_consecutive` lines.
+    pub fn collapse_blank_lines(&mut self, max_consecutive: usize) {
+        self.lines = collapse_blank_lines(&self.lines, max_consecutive);
+    }
+
+    // removes every sentence matching any of the patterns, dropping lines that end up empty
+    // because of it, and returns the number of sentences removed.
+    pub fn remove_matching_sentences(&mut self, patterns: &[Regex]) -> usize {
+        let mut removed = 0;
+        let mut kept_lines = Vec::with_capacity(self.lines.len());
+        for line in &self.lines {
+            if line.trim().is_empty() {
+                kept_lines.push(line.to_owned());
+                continue;
+            }
+            let mut kept = String::new();
+            for sentence in line.split_sentence_bounds() {
+                if patterns.iter().any(|p| p.is_match(sentence.trim())) {
+                    removed += 1;
+                } else {
+                    kept.push_str(sentence);
+                }
+            }
+            let kept = kept.trim_end();
+            if !kept.is_empty() {
+                kept_lines.push(kept.to_owned());
+            }
+        }
+        self.lines = kept_lines;
+        removed
+    }
+
+    // makes this newly generated item the latest alternative of the `previous` item it was
+    // regenerated from, carrying over all of the versions that one had.
+    pub fn keep_alternatives_from(&mut self, previous: ChatLogItem) {
+        let mut alternatives = previous.alternatives;
+        match alternatives.get_mut(previous.current_alt) {
+            // the selected version may have been edited since it was picked
+            Some(selected) => *selected = previous.lines,
+            None => alternatives.push(previous.lines),
+        }
+        alternatives.push(self.lines.clone());
+        self.current_alt = alternatives.len() - 1;
+        self.alternatives = alternatives;
+    }
+
+    // swaps the item's lines for the alternative at `index`, keeping the current lines as the
+    // alternative they came from. returns false if there's no other alternative at `index`.
+    pub fn select_alternative(&mut self, index: usize) -> bool {
+        if index == self.current_alt || index >= self.alternatives.len() {
+            return false;
+        }
+        if let Some(selected) = self.alternatives.get_mut(self.current_alt) {
+            *selected = self.lines.clone();
+        }
+        self.lines = self.alternatives[index].clone();
+        self.current_alt = index;
+        self.embeddings.clear();
+        true
+    }
+
+    // returns the number of sentences that match any of the patterns.
+    pub fn count_matching_sentences(&self, patterns: &[Regex]) -> usize {
+        self.lines
+            .iter()
+            .flat_map(|line| line.split_sentence_bounds())
+            .filter(|sentence| patterns.iter().any(|p| p.is_match(sentence.trim())))
+            .count()
+    }
+
+    // cuts the log item's text down to its first sentence. returns false, leaving the item
+    // alone, if there's no more than one sentence to begin with.
+    pub fn truncate_to_first_sentence(&mut self) -> bool {
+        let text = self.get_items_as_string();
+        match find_first_sentence_end(&text) {
+            Some(end) => {
+                self.replace_items_with_string(text[..end].to_owned());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // cuts the log item's text off where the marker starts, dropping the marker and everything
+    // after it. returns false, leaving the item alone, if the marker isn't in the text.
+    pub fn truncate_at_marker(&mut self, marker: &str) -> bool {
+        if marker.is_empty() {
+            return false;
+        }
+        let text = self.get_items_as_string();
+        match text.find(marker) {
+            Some(start) => {
+                self.replace_items_with_string(text[..start].trim_end().to_owned());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // removes every match of the patterns, applied in order, from the text of the log item
+    // and returns true if anything was removed.
+    pub fn remove_pattern_matches(&mut self, patterns: &[Regex]) -> bool {
+        let original = self.get_items_as_string();
+        let mut text = original.clone();
+        for pattern in patterns {
+            text = pattern.replace_all(&text, "").into_owned();
+        }
+        if text == original {
+            return false;
+        }
+        self.replace_items_with_string(text.trim().to_owned());
+        true
+    }
+
     pub fn replace_items_with_string(&mut self, paragraph: String) {
         self.lines.clear();
         if !paragraph.is_empty() {
@@ -148,6 +305,15 @@ pub struct ChatLog {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub other_participants: Option<Vec<Participant>>,
 
+    // optional tags used to organize and filter the logs in the log selector.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    // the name of the parameter set last used with this log, so the chat picks it back up
+    // the next time the log gets opened.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_parameters_name: Option<String>,
+
     // the context description for this log file, and is used in prompt temlates
     // under the <|current_context|> tag.
     pub current_context: String,
@@ -156,7 +322,6 @@ pub struct ChatLog {
     items: Vec<ChatLogItem>,
 }
 impl ChatLog {
-    #[allow(dead_code)]
     // Creates a new instance of the ChatLog with an empty vector of items
     pub fn new() -> Self {
         let items = Vec::new();
@@ -167,6 +332,8 @@ impl ChatLog {
             current_context: String::new(),
             other_participants: None,
             user_description: None,
+            tags: None,
+            last_parameters_name: None,
             last_used_filepath: None,
         }
     }
@@ -237,9 +404,12 @@ impl ChatLog {
     }
 
     // creates a new chatlog based on the greeting of the character file.
-    pub fn new_with_greeting(character_file: &CharacterFileYaml, user_name: &str) -> Self {
+    pub fn new_with_greeting(
+        character_file: &CharacterFileYaml,
+        config: &ConfigurationFile,
+    ) -> Self {
         let items = character_file
-            .get_greeting(user_name)
+            .get_greeting(config)
             .iter()
             .map(|s| {
                 // use this to pull out the first name mentioned in a log entry
@@ -267,6 +437,8 @@ impl ChatLog {
             current_context: character_file.context.to_owned(),
             other_participants: None,
             user_description: None,
+            tags: None,
+            last_parameters_name: None,
             last_used_filepath: None,
         }
     }
@@ -284,6 +456,88 @@ impl ChatLog {
         Ok(chatlog)
     }
 
+    // creates a new chatlog from a JSONL dataset, like the ones written by
+    // `export_dataset_input_ouptut`. each line can either be an input-output pair or a
+    // ShareGPT style `conversations` list. inputs and "human" turns become items for
+    // `user_name` while outputs and "gpt" turns become items for `character_name`; the
+    // first "system" turn found becomes the log's context. lines that can't be read are
+    // skipped and the number of them is returned with the log.
+    pub fn new_from_dataset_jsonl(
+        fp: &PathBuf,
+        user_name: &str,
+        character_name: &str,
+    ) -> Result<(Self, usize)> {
+        let f = File::open(fp).context("Attempting to open the jsonl dataset file")?;
+        let reader = BufReader::new(f);
+        let mut chatlog = ChatLog::new();
+        let mut skipped = 0;
+
+        for (line_number, line_res) in reader.lines().enumerate() {
+            let line = line_res.context("Attempting to read a line of the jsonl dataset")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut new_items = Vec::new();
+            match serde_json::from_str::<ImportedDatasetLine>(&line) {
+                Ok(ImportedDatasetLine::InputOutput(pair)) => {
+                    new_items.push(ChatLogItem::new_from_str(user_name.to_owned(), &pair.input));
+                    new_items.push(ChatLogItem::new_from_str(
+                        character_name.to_owned(),
+                        &pair.output,
+                    ));
+                }
+                Ok(ImportedDatasetLine::ShareGpt { conversations }) => {
+                    for turn in conversations {
+                        let entity = match turn.from.as_str() {
+                            "human" | "user" => user_name,
+                            "gpt" | "assistant" => character_name,
+                            "system" => {
+                                if chatlog.current_context.is_empty() {
+                                    chatlog.current_context = turn.value;
+                                }
+                                continue;
+                            }
+                            _ => {
+                                new_items.clear();
+                                break;
+                            }
+                        };
+                        new_items.push(ChatLogItem::new_from_str(entity.to_owned(), &turn.value));
+                    }
+                }
+                Err(_) => {}
+            }
+
+            if new_items.is_empty() {
+                log::warn!(
+                    "Skipping line {} of the dataset {:?} because it couldn't be imported.",
+                    line_number + 1,
+                    fp
+                );
+                skipped += 1;
+            } else {
+                chatlog.items.append(&mut new_items);
+            }
+        }
+
+        Ok((chatlog, skipped))
+    }
+
+    // reads just the tags out of a json chatlog file without keeping the rest of the log around.
+    pub fn read_tags_from_json(fp: &PathBuf) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct ChatLogTagsOnly {
+            tags: Option<Vec<String>>,
+        }
+
+        let f = File::open(fp).context("Attempting to open json chatlog file for tags")?;
+        let bf = BufReader::new(f);
+        let tags_only: ChatLogTagsOnly = serde_json::from_reader(bf)
+            .context("Attempting to deserialize the tags from the chatlog json")?;
+        Ok(tags_only.tags.unwrap_or_default())
+    }
+
     pub fn save_to_last_used_json_file(&self) -> Result<()> {
         if let Some(fp) = &self.last_used_filepath {
             let json = serde_json::to_string_pretty(self)
@@ -327,24 +581,8 @@ impl ChatLog {
         for cli in self.iter() {
             if cli.entity.eq(entity) {
                 if previous_logitems.is_empty() == false {
-                    let last_entity = &previous_logitems.last().unwrap().entity;
-
-                    // only use one previous items where the entity's match,
-                    // instead of combining all previous items incase of mult-chat
-                    let filtered_prev_items: Vec<&ChatLogItem> = previous_logitems
-                        .iter()
-                        .rev()
-                        .take_while(|item| item.entity.eq(last_entity))
-                        .cloned()
-                        .collect();
-
-                    let joined_input = filtered_prev_items
-                        .iter()
-                        .map(|item| item.get_items_as_string())
-                        .collect::<Vec<String>>()
-                        .join("\n");
                     dataset.push(InputOutputDatasetItem {
-                        input: joined_input,
+                        input: join_dataset_input(&previous_logitems),
                         output: cli.get_items_as_string(),
                     });
                     previous_logitems.clear();
@@ -381,6 +619,114 @@ impl ChatLog {
         Ok(())
     }
 
+    // writes the chatlog out as a single ShareGPT style conversation on one line of JSONL.
+    // items from `character_name` become "gpt" turns and everyone else's become "human"
+    // turns, with consecutive items from the same side merged into one turn. the context,
+    // if there is one, goes first as a "system" turn.
+    pub fn export_sharegpt(&self, fp: &PathBuf, character_name: &str) -> Result<()> {
+        let mut conversations: Vec<ShareGptTurn> = vec![];
+        let context = self.current_context.trim();
+        if !context.is_empty() {
+            conversations.push(ShareGptTurn {
+                from: "system".to_owned(),
+                value: context.to_owned(),
+            });
+        }
+        for cli in self.iter() {
+            let from = if cli.entity.eq(character_name) {
+                "gpt"
+            } else {
+                "human"
+            };
+            match conversations.last_mut() {
+                Some(last_turn) if last_turn.from == from => {
+                    last_turn.value.push('\n');
+                    last_turn.value.push_str(cli.get_items_as_string().as_str());
+                }
+                _ => conversations.push(ShareGptTurn {
+                    from: from.to_owned(),
+                    value: cli.get_items_as_string(),
+                }),
+            }
+        }
+
+        let json_string = serde_json::to_string(&ShareGptConversation { conversations })
+            .context("Attempting to serialize the chatlog for ShareGPT export")?;
+        let out_file = File::create(fp).context("Attempting to create file for ShareGPT export")?;
+        let mut writer = BufWriter::new(out_file);
+        writeln!(writer, "{}", json_string)
+            .context("Attempting to write out the JSONL row for ShareGPT export.")?;
+        writer
+            .flush()
+            .context("Attempting to flush ShareGPT export buffer.")?;
+        Ok(())
+    }
+
+    // writes the chatlog out to a readable Markdown transcript, with each item as a paragraph
+    // starting with the speaker's name in bold. the context, if there is one, goes first.
+    // the text is written as-is, so quotes and any markup the model wrote come through.
+    pub fn export_markdown(&self, fp: &PathBuf) -> Result<()> {
+        let out_file = File::create(fp).context("Attempting to create file for markdown export")?;
+        let mut writer = BufWriter::new(out_file);
+
+        let context = self.current_context.trim();
+        if !context.is_empty() {
+            writeln!(writer, "## Context\n\n{}\n\n---\n", context)
+                .context("Attempting to write the context for markdown export.")?;
+        }
+        for cli in self.iter() {
+            writeln!(
+                writer,
+                "**{}:** {}\n",
+                cli.entity,
+                cli.get_items_as_string().trim()
+            )
+            .context("Attempting to write a chatlog item for markdown export.")?;
+        }
+        writer
+            .flush()
+            .context("Attempting to flush markdown export buffer.")?;
+        Ok(())
+    }
+
+    // appends the single input-output pair ending with the ChatLogItem at `index` to the
+    // jsonl dataset file, creating it if needed. the input is built from the items
+    // before it the same way `export_dataset_input_ouptut` builds it.
+    pub fn append_dataset_item_to_file(&self, index: usize, fp: &Path) -> Result<()> {
+        let item = self
+            .get(index)
+            .ok_or_else(|| anyhow!("There's no chatlog item at index {}", index))?;
+        let mut previous_logitems: Vec<&ChatLogItem> = self.items[..index]
+            .iter()
+            .rev()
+            .take_while(|prev| !prev.entity.eq(&item.entity))
+            .collect();
+        if previous_logitems.is_empty() {
+            return Err(anyhow!(
+                "The item isn't preceded by anything from another speaker to use as the input"
+            ));
+        }
+        previous_logitems.reverse();
+
+        let dataset_item = InputOutputDatasetItem {
+            input: join_dataset_input(&previous_logitems),
+            output: item.get_items_as_string(),
+        };
+        let mut json_string = serde_json::to_string(&dataset_item)
+            .context("Attempting to serialize dataset item for input-ouput export")?;
+        json_string.push('\n');
+
+        let mut out_file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(fp)
+            .context("Attempting to open file to append the dataset item")?;
+        out_file
+            .write_all(json_string.as_bytes())
+            .context("Attempting to append JSONL row to the dataset file.")?;
+        Ok(())
+    }
+
     // returns a reference to the ChatLogItem at the specified index
     pub fn get(&self, index: usize) -> Option<&ChatLogItem> {
         self.items.get(index)
@@ -423,6 +769,21 @@ impl ChatLog {
         self.items.pop()
     }
 
+    // throws away every ChatLogItem and, if `keep_greeting` is set, starts the log over with the
+    // character's greeting. everything else about the log, like its context, is kept.
+    pub fn restart_from_greeting(
+        &mut self,
+        character_file: &CharacterFileYaml,
+        config: &ConfigurationFile,
+        keep_greeting: bool,
+    ) {
+        self.items = if keep_greeting {
+            ChatLog::new_with_greeting(character_file, config).items
+        } else {
+            Vec::new()
+        };
+    }
+
     // removes the ChatLogItem at the index and returns it.
     pub fn remove(&mut self, index: usize) -> Option<ChatLogItem> {
         if index < self.items.len() {
@@ -431,10 +792,335 @@ impl ChatLog {
             None
         }
     }
+
+    // puts the ChatLogItem in at the index, shifting the items after it; an index past the end
+    // of the log adds it to the end instead.
+    pub fn insert(&mut self, index: usize, item: ChatLogItem) {
+        let index = index.min(self.items.len());
+        self.items.insert(index, item);
+    }
+
+    // moves the ChatLogItem at the `from` index so that it ends up at the `to` index,
+    // shifting the items in between. returns false if either index is out of range.
+    pub fn move_item(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.items.len() || to >= self.items.len() {
+            return false;
+        }
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+        true
+    }
+
+    // merges runs of adjacent ChatLogItems from the same entity into a single item,
+    // appending the lines (and embeddings) of the later items to the first one.
+    // returns the number of items that were merged away.
+    pub fn merge_consecutive_speakers(&mut self) -> usize {
+        let original_len = self.items.len();
+        let mut merged: Vec<ChatLogItem> = Vec::with_capacity(original_len);
+        for item in self.items.drain(..) {
+            match merged.last_mut() {
+                Some(prev) if prev.entity == item.entity => {
+                    prev.lines.extend(item.lines);
+                    prev.embeddings.extend(item.embeddings);
+                }
+                _ => merged.push(item),
+            }
+        }
+        self.items = merged;
+        original_len - self.items.len()
+    }
+
+    // applies the entries of the journal file at `journal_fp` to the end of the log.
+    // returns the number of entries that were replayed.
+    pub fn replay_journal(&mut self, journal_fp: &Path) -> Result<usize> {
+        let f = File::open(journal_fp).context("Attempting to open the chatlog journal")?;
+        let reader = BufReader::new(f);
+        let mut replayed = 0;
+        for line in reader.lines() {
+            let line = line.context("Attempting to read a line of the chatlog journal")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // a crash while appending can leave a partial last line, so stop at the first bad one
+            let entry: JournalEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    log::warn!(
+                        "Stopping the replay of the chatlog journal ({:?}) at an unreadable entry: {}",
+                        journal_fp,
+                        err
+                    );
+                    break;
+                }
+            };
+            if entry.replaces_last {
+                self.items.pop();
+            }
+            self.items.push(entry.item);
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+// one line of the journal file: a chatlog item as it was committed to the log and whether it
+// replaced the last item (as happens when a response is continued) rather than being added.
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    replaces_last: bool,
+    item: ChatLogItem,
+}
+
+// returns the path of the journal file that goes with the chatlog file at `fp`.
+pub fn get_journal_filepath(fp: &Path) -> PathBuf {
+    fp.with_extension(JOURNAL_FILE_EXTENSION)
+}
+
+// appends the item to the journal of the chatlog file at `fp` so that it can be recovered
+// if the application goes down before the next full save of the log.
+pub fn append_to_journal(fp: &Path, item: &ChatLogItem, replaces_last: bool) -> Result<()> {
+    let entry = JournalEntry {
+        replaces_last,
+        item: item.clone(),
+    };
+    let json = serde_json::to_string(&entry).context("Attempting to serialize a journal entry")?;
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_journal_filepath(fp))
+        .context("Attempting to open the chatlog journal for appending")?;
+    writeln!(f, "{}", json).context("Attempting to write to the chatlog journal")?;
+    f.sync_data()
+        .context("Attempting to flush the chatlog journal to disk")?;
+    Ok(())
+}
+
+// removes the journal of the chatlog file at `fp`, which should be done once the log is saved.
+pub fn clear_journal(fp: &Path) -> Result<()> {
+    let journal_fp = get_journal_filepath(fp);
+    if journal_fp.exists() {
+        std::fs::remove_file(&journal_fp).context("Attempting to remove the chatlog journal")?;
+    }
+    Ok(())
+}
+
+// returns true if the chatlog file at `fp` has a journal with entries written after the
+// log was last saved, which means the application didn't get to save them.
+pub fn has_unsaved_journal(fp: &Path) -> bool {
+    let journal_modified = match std::fs::metadata(get_journal_filepath(fp)) {
+        Ok(meta) if meta.len() > 0 => meta.modified().ok(),
+        _ => return false,
+    };
+    let log_modified = std::fs::metadata(fp).and_then(|meta| meta.modified()).ok();
+    match (journal_modified, log_modified) {
+        (Some(journal_modified), Some(log_modified)) => journal_modified > log_modified,
+        _ => true,
+    }
+}
+
+// returns a copy of the lines where any run of blank (or whitespace only) lines longer than
+// `max_consecutive` has been shortened to that many lines.
+pub fn collapse_blank_lines(lines: &[String], max_consecutive: usize) -> Vec<String> {
+    let mut collapsed = Vec::with_capacity(lines.len());
+    let mut blank_run = 0;
+    for line in lines {
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > max_consecutive {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        collapsed.push(line.to_owned());
+    }
+    collapsed
+}
+
+// returns the byte index just past the end of the first sentence in `text`, including any
+// closing quotes or brackets after the punctuation, or None if no other text follows it.
+pub fn find_first_sentence_end(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+
+        // take in any run of punctuation and closing characters, like `?!"` or `...*`
+        let mut end = text.len();
+        while let Some(&(i, next)) = chars.peek() {
+            if matches!(
+                next,
+                '.' | '!' | '?' | '"' | '\'' | ')' | ']' | '*' | '”' | '’'
+            ) {
+                chars.next();
+            } else {
+                end = i;
+                break;
+            }
+        }
+
+        // it's only the end of a sentence if whitespace follows, with more text after that
+        let rest = &text[end..];
+        if rest.starts_with(char::is_whitespace) && !rest.trim().is_empty() {
+            return Some(end);
+        }
+    }
+    None
+}
+
+// copies the json file at `fp` into the backups folder next to it under a timestamped name,
+// then removes the oldest backups so that no more than `max_backups` are kept.
+pub fn backup_json_file(fp: &Path, max_backups: usize) -> Result<()> {
+    let parent = fp
+        .parent()
+        .context("Attempting to get the folder of the file to back up")?;
+    let stem = fp
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .context("Attempting to get the name of the file to back up")?;
+    let backups_dir = parent.join(BACKUPS_FOLDER_NAME);
+    std::fs::create_dir_all(&backups_dir).context("Attempting to create the backups folder")?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f");
+    let backup_fp = backups_dir.join(format!("{}-{}.json", stem, timestamp));
+    std::fs::copy(fp, &backup_fp).context("Attempting to copy the file to the backups folder")?;
+
+    // the timestamps sort in order, so the oldest backups are at the end of the list
+    let backups = list_json_file_backups(fp)?;
+    for old_backup in backups.iter().skip(max_backups) {
+        if let Err(err) = std::fs::remove_file(old_backup) {
+            log::error!(
+                "Failed to remove the old backup ({:?}): {}",
+                old_backup,
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// returns the backups made by `backup_json_file` for the json file at `fp`, newest first.
+pub fn list_json_file_backups(fp: &Path) -> Result<Vec<PathBuf>> {
+    let parent = fp
+        .parent()
+        .context("Attempting to get the folder of the backed up file")?;
+    let stem = fp
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .context("Attempting to get the name of the backed up file")?;
+    let backups_dir = parent.join(BACKUPS_FOLDER_NAME);
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}-", stem);
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(&backups_dir)
+        .context("Attempting to read the backups folder")?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".json"))
+        })
+        .collect();
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 struct InputOutputDatasetItem {
     input: String,
     output: String,
 }
+
+// one turn of a ShareGPT style conversation, where `from` is "human", "gpt" or "system".
+#[derive(Serialize, Deserialize)]
+struct ShareGptTurn {
+    from: String,
+    value: String,
+}
+
+// a whole chatlog as a ShareGPT style conversation, written out as one line of JSONL.
+#[derive(Serialize)]
+struct ShareGptConversation {
+    conversations: Vec<ShareGptTurn>,
+}
+
+// a line of a JSONL dataset that can be imported back into a chatlog.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportedDatasetLine {
+    InputOutput(InputOutputDatasetItem),
+    ShareGpt { conversations: Vec<ShareGptTurn> },
+}
+
+// joins the chatlog items gathered before a dataset item's output into its input. only
+// the last run of items from a single speaker is used, instead of combining all of the
+// previous items, to avoid possible confusion in training from multi-chat logs.
+fn join_dataset_input(previous_logitems: &[&ChatLogItem]) -> String {
+    let Some(last_item) = previous_logitems.last() else {
+        return String::new();
+    };
+    previous_logitems
+        .iter()
+        .rev()
+        .take_while(|item| item.entity.eq(&last_item.entity))
+        .map(|item| item.get_items_as_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_EMPTY_GREETING;
+
+    fn test_config(empty_greeting: Option<&str>) -> ConfigurationFile {
+        let mut config: ConfigurationFile = serde_yaml::from_str(
+            r#"
+version: 1
+display_name: "USER"
+stop_on_display_name: true
+parameters: []
+models: []
+"#,
+        )
+        .unwrap();
+        config.empty_greeting = empty_greeting.map(|g| g.to_owned());
+        config
+    }
+
+    #[test]
+    fn chatlog_for_a_character_without_a_greeting_is_well_formed() {
+        let character = CharacterFileYaml {
+            name: "Bot".to_owned(),
+            greeting: "  \n".to_owned(),
+            context: "A quiet library.".to_owned(),
+            ..Default::default()
+        };
+
+        let chatlog = ChatLog::new_with_greeting(&character, &test_config(None));
+        assert_eq!(chatlog.len(), 1);
+        assert_eq!(chatlog.current_context, "A quiet library.");
+        let item = chatlog.last().unwrap();
+        assert_eq!(item.entity, "Bot");
+        assert_eq!(
+            item.get_items_as_string(),
+            DEFAULT_EMPTY_GREETING
+                .replace("<|character_name|>: ", "")
+                .replace("<|character_name|>", "Bot")
+        );
+
+        // a configured empty greeting is used instead of the default one
+        let config = test_config(Some("<|character_name|>: Hello, <|user_name|>."));
+        let chatlog = ChatLog::new_with_greeting(&character, &config);
+        assert_eq!(chatlog.len(), 1);
+        let item = chatlog.last().unwrap();
+        assert_eq!(item.entity, "Bot");
+        assert_eq!(item.get_items_as_string(), "Hello, USER.");
+    }
+}
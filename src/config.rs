@@ -1,12 +1,92 @@
 use std::path::{Path, PathBuf};
 
+use anyhow::{anyhow, Context};
 use directories::BaseDirs;
-use ratatui::prelude::Alignment;
-use serde::Deserialize;
+use ratatui::{
+    prelude::Alignment,
+    style::{Color, Modifier, Style},
+};
+use serde::{Deserialize, Serialize};
 
 pub const CURRENT_VERSION: u16 = 1;
 pub const APPLICATION_CONFIG_FOLDER_NAME: &str = "sentinel_core";
 pub const LOG_FILE_NAME: &str = "log.json";
+pub const DEFAULT_SELECTION_HIGHLIGHT_SYMBOL: &str = ">> ";
+
+// the largest share of a model's context, in percent, that `maximum_new_tokens` can take up
+// so that there's always some room left over for the prompt.
+pub const MAX_NEW_TOKENS_CONTEXT_PERCENT: usize = 75;
+
+// the number of candidate responses generated at once when not configured, and the most that
+// can be asked for since each one is a full generation run one after another.
+pub const DEFAULT_CANDIDATE_COUNT: usize = 3;
+pub const MAX_CANDIDATE_COUNT: usize = 8;
+
+// the greeting used for new chatlogs of a character without one, unless configured otherwise
+pub const DEFAULT_EMPTY_GREETING: &str =
+    "<|character_name|>: *<|character_name|> looks at you expectantly.*";
+
+// an `api_key` starting with this names the environment variable holding the actual key
+pub const API_KEY_ENV_PREFIX: &str = "env:";
+
+// the `remote_api_style` values that are understood
+pub const REMOTE_API_STYLE_KOBOLD: &str = "kobold";
+pub const REMOTE_API_STYLE_OPENAI: &str = "openai";
+
+// the built-in prompt templates that a model's `template_preset` can name
+pub const TEMPLATE_PRESET_CHATML: &str = "<|im_start|>system
+Continue the chat dialogue below. Write a single reply for the character named \"<|character_name|>\".
+<|character_description|>
+<|user_description|>
+<|character_context|><|im_end|>
+<|im_start|>user
+<|chat_history|><|im_end|>
+<|im_start|>assistant
+<|character_name|>: ";
+pub const TEMPLATE_PRESET_LLAMA3: &str = "<|start_header_id|>system<|end_header_id|>
+
+Continue the chat dialogue below. Write a single reply for the character named \"<|character_name|>\".
+<|character_description|>
+<|user_description|>
+<|character_context|><|eot_id|><|start_header_id|>user<|end_header_id|>
+
+<|chat_history|><|eot_id|><|start_header_id|>assistant<|end_header_id|>
+
+<|character_name|>: ";
+pub const TEMPLATE_PRESET_ALPACA: &str = "### Instruction:
+Continue the chat dialogue below. Write a single reply for the character named \"<|character_name|>\".
+<|character_description|>
+<|user_description|>
+<|character_context|>
+<|chat_history|>
+
+### Response:
+<|character_name|>: ";
+pub const TEMPLATE_PRESET_VICUNA: &str = "Continue the chat dialogue below. Write a single reply for the character named \"<|character_name|>\".
+<|character_description|>
+<|user_description|>
+<|character_context|>
+
+USER:
+<|chat_history|>
+ASSISTANT:
+<|character_name|>: ";
+
+// returns the built-in prompt template for the `template_preset` name, if there is one.
+pub fn get_template_preset(name: &str) -> Option<&'static str> {
+    match name.to_ascii_lowercase().as_str() {
+        "chatml" => Some(TEMPLATE_PRESET_CHATML),
+        "llama3" => Some(TEMPLATE_PRESET_LLAMA3),
+        "alpaca" => Some(TEMPLATE_PRESET_ALPACA),
+        "vicuna" => Some(TEMPLATE_PRESET_VICUNA),
+        _ => None,
+    }
+}
+
+// returns the most new tokens that can be requested from a model with the given context size.
+pub fn max_new_tokens_limit(context_size: usize) -> usize {
+    context_size * MAX_NEW_TOKENS_CONTEXT_PERCENT / 100
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct CharacterFileYaml {
@@ -22,6 +102,10 @@ pub struct CharacterFileYaml {
     // the optional color for the regular, non-quoted text from the character in the chat UI
     pub text_rgb: Option<[u8; 3]>,
 
+    // the optional secondary color of the progress animation while waiting on the character;
+    // the primary color comes from `name_rgb`
+    pub progress_secondary_rgb: Option<[u8; 3]>,
+
     // the character description that gets substituted in the prompt template: <|character_description|>
     pub description: String,
 
@@ -31,8 +115,40 @@ pub struct CharacterFileYaml {
     // the starting context of the character, which gets copied to new logs;
     // after that, the chatlog current_context should be used.
     pub context: String,
+
+    // regular expressions whose matches are removed, in order, from each new response of
+    // the character to clean up unwanted artifacts like OOC notes.
+    pub response_trim_patterns: Option<Vec<String>>,
+
+    // extra strings that end the character's responses when generated, added to the
+    // `stop_sequences` from the configuration file.
+    pub stop_sequences: Option<Vec<String>>,
+
+    // the name of the parameter set in the configuration to start chats with the character on,
+    // unless the chatlog remembers another one that was used last.
+    pub default_parameters_name: Option<String>,
 }
 impl CharacterFileYaml {
+    // compiles the `response_trim_patterns`, skipping any that aren't valid with a warning.
+    pub fn compile_response_trim_patterns(&self) -> Vec<regex::Regex> {
+        self.response_trim_patterns
+            .iter()
+            .flatten()
+            .filter_map(|pattern| match regex::Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    log::warn!(
+                        "response_trim_patterns entry '{}' for {} isn't a valid regular expression and will be ignored: {}",
+                        pattern,
+                        self.name,
+                        err
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
     pub fn load_character(filepath: &PathBuf) -> CharacterFileYaml {
         // if we found a file, deserialize it as yaml
         match std::fs::read_to_string(filepath) {
@@ -58,18 +174,42 @@ impl CharacterFileYaml {
         return Default::default();
     }
 
-    // creates a new vector with the processed template from the character file
-    pub fn get_greeting(&self, user_name: &str) -> Vec<String> {
+    // creates a new vector with the processed template from the character file. unless
+    // `keep_greeting_whitespace` is configured, the blank lines around the greeting and the
+    // whitespace at the end of each line are dropped, since yaml block scalars easily pick those
+    // up; blank lines inside the greeting are kept as intentional breaks. a character without a
+    // greeting gets the configured `empty_greeting` instead so new logs don't start out empty.
+    pub fn get_greeting(&self, config: &ConfigurationFile) -> Vec<String> {
+        let greeting_text = if self.greeting.trim().is_empty() {
+            config
+                .empty_greeting
+                .as_deref()
+                .unwrap_or(DEFAULT_EMPTY_GREETING)
+        } else {
+            self.greeting.as_str()
+        };
+
+        let mut lines: Vec<&str> = greeting_text.lines().collect();
+        if !config.keep_greeting_whitespace.unwrap_or(false) {
+            lines = lines.iter().map(|line| line.trim_end()).collect();
+            while lines.first().is_some_and(|line| line.is_empty()) {
+                lines.remove(0);
+            }
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+        }
+
         let mut greeting = Vec::new();
-        for line in self.greeting.lines() {
-            greeting.push(self.process_string_templates(user_name, &line.to_owned()));
+        for line in lines {
+            greeting.push(self.process_string_templates(&config.display_name, line));
         }
         greeting
     }
 
     // replaces the associated tags in the character file with the actual values.
     // NOTE: currently supports `<|character_name|>` and `<|user_name|>`.
-    fn process_string_templates(&self, user_name: &str, input: &String) -> String {
+    pub fn process_string_templates(&self, user_name: &str, input: &str) -> String {
         input
             .replace("<|character_name|>", &self.name)
             .replace("<|user_name|>", user_name)
@@ -98,6 +238,7 @@ pub struct ConversationTurn {
 }
 
 #[derive(Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ConfiguredLlm {
     // a user-friendly name used to identify the model on the command-line
     pub name: String,
@@ -106,13 +247,30 @@ pub struct ConfiguredLlm {
     pub path: Option<String>,
 
     // the remote host name for a server that will perform the text
-    // inference instead of doing it locally; currently only Koboldcpp is supported
+    // inference instead of doing it locally; see `remote_api_style` for the supported servers
+    #[serde(alias = "remote_path")]
     pub remote_server: Option<String>,
 
+    // the kind of api the 'remote_server' speaks: KoboldCpp's by default, or "openai" for
+    // servers with OpenAI-compatible chat completions, like llama.cpp's server, vLLM or LM Studio.
+    pub remote_api_style: Option<String>,
+
     // the number of seconds to wait for a server to respond before erroring
     // only applies when using 'remote_server' and not 'path' to load locally
     pub remote_timeout_s: Option<u64>,
 
+    // how many more times to send a KoboldCpp request after a connection error or a status
+    // other than 200, waiting a little longer before each try; defaults to 0 for no retries.
+    pub remote_retry_count: Option<usize>,
+
+    // if true, KoboldCpp servers are asked to stream the generated text so it shows up as it's
+    // written, like it does for local models; the blocking endpoint is used if the stream fails.
+    pub remote_stream: Option<bool>,
+
+    // the key sent to the 'remote_server' to authenticate, such as KoboldCpp's --password;
+    // a value like "env:KOBOLD_API_KEY" reads it from that environment variable instead.
+    pub api_key: Option<String>,
+
     // how much room to budget for a complete context
     pub context_size: usize,
 
@@ -120,21 +278,127 @@ pub struct ConfiguredLlm {
     // which requires a configured vector embedding model in the configuration.
     pub similar_sentence_count: Option<usize>,
 
+    // the lowest similarity score a chat log item can have and still be pulled up by
+    // `similar_sentence_count`; weaker matches are left out of the prompt.
+    pub similarity_threshold: Option<f32>,
+
     // the number of layers to offload to the gpu.
     // applies only to locally hosted models
     pub gpu_layer_count: Option<usize>,
 
+    // the index of the gpu to use for the model, or the one that holds the scratch buffers
+    // when the model is split across gpus. applies only to locally hosted models.
+    pub gpu_device_index: Option<usize>,
+
+    // how much of the model to put on each gpu, in proportion, such as [3.0, 1.0] to put
+    // three quarters on the first. applies only to locally hosted models.
+    pub gpu_tensor_split: Option<Vec<f32>>,
+
     // the seed to use for this particular model when generating text
     // if not set, a random one will be chosen
     pub seed: Option<i32>,
 
     // the string used as the main template for text inference
     // with several tags that get replaced with content at
-    // inference time.
+    // inference time. it can be left out when `template_preset` is set.
+    #[serde(default)]
     pub prompt_instruct_template: String,
+
+    // the name of a built-in template to use when `prompt_instruct_template` isn't set:
+    // one of "chatml", "llama3", "alpaca" or "vicuna".
+    pub template_preset: Option<String>,
+
+    // the text put between the turns of the chat history in the prompt; defaults to a newline.
+    pub turn_separator: Option<String>,
+
+    // text to wrap each turn of the chat history in, such as "<|im_start|>" and "<|im_end|>"
+    // for ChatML models.
+    pub turn_prefix: Option<String>,
+    pub turn_suffix: Option<String>,
+
+    // a GBNF grammar the generated text has to follow, such as one that only allows a JSON
+    // object. only one of `grammar` and `grammar_file` should be set. the OpenAI-compatible
+    // backend doesn't support grammars, so they're ignored there.
+    pub grammar: Option<String>,
+
+    // the path to a file holding the GBNF grammar to use instead of an inline `grammar`;
+    // it's read each time the model configuration gets loaded.
+    pub grammar_file: Option<String>,
+}
+
+impl ConfiguredLlm {
+    // returns true if the remote server should be sent OpenAI-compatible requests.
+    pub fn uses_openai_api(&self) -> bool {
+        self.remote_api_style
+            .as_deref()
+            .is_some_and(|style| style.eq_ignore_ascii_case(REMOTE_API_STYLE_OPENAI))
+    }
+
+    // returns the api key to send to the remote server, reading it from the environment
+    // if configured that way. the error only names the variable, never the key.
+    pub fn resolve_api_key(&self) -> Result<Option<String>, String> {
+        let Some(api_key) = &self.api_key else {
+            return Ok(None);
+        };
+        match api_key.strip_prefix(API_KEY_ENV_PREFIX) {
+            Some(var_name) => match std::env::var(var_name.trim()) {
+                Ok(value) if !value.is_empty() => Ok(Some(value)),
+                _ => Err(format!(
+                    "The api_key for model '{}' should come from the {} environment variable, but it isn't set",
+                    self.name,
+                    var_name.trim()
+                )),
+            },
+            None => Ok(Some(api_key.to_owned())),
+        }
+    }
+
+    // returns the GBNF grammar for the model, reading `grammar_file` if that's how it's set.
+    // if both are set, the inline `grammar` is used.
+    pub fn load_grammar(&self) -> anyhow::Result<Option<String>> {
+        if let Some(grammar) = &self.grammar {
+            return Ok(Some(grammar.to_owned()));
+        }
+        match &self.grammar_file {
+            Some(grammar_file) => {
+                let grammar = std::fs::read_to_string(grammar_file).with_context(|| {
+                    format!("Attempting to read the grammar file '{}'", grammar_file)
+                })?;
+                Ok(Some(grammar))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // checks that the gpu device index and tensor split make sense together, returning
+    // a description of the problem if they don't.
+    pub fn validate_gpu_devices(&self) -> Result<(), String> {
+        if let Some(split) = &self.gpu_tensor_split {
+            if split.is_empty()
+                || split.iter().any(|s| !s.is_finite() || *s < 0.0)
+                || split.iter().sum::<f32>() <= 0.0
+            {
+                return Err(format!(
+                    "gpu_tensor_split ({:?}) needs at least one entry and only positive or zero amounts",
+                    split
+                ));
+            }
+            if let Some(index) = self.gpu_device_index {
+                if index >= split.len() {
+                    return Err(format!(
+                        "gpu_device_index ({}) is past the {} gpus in gpu_tensor_split",
+                        index,
+                        split.len()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ConfiguredEmbeddingModel {
     // the path to the model folder that should contain the 'config.json',
     // 'tokenizer.json' and 'model.safetensors' BERT model files to use
@@ -157,6 +421,13 @@ pub struct ConfiguredEmbeddingModel {
     // Optional pretext string to prepend to the text when using the embedding to
     // encode text for a vector store.
     pub encode_pretext: Option<String>,
+
+    // The index of the CUDA device to use when not running on the CPU. Defaults to 0.
+    pub gpu_device_index: Option<usize>,
+
+    // If true, the default, the embeddings built for a chatlog are saved to a file next
+    // to it so that they don't have to be built again when the log is opened next time.
+    pub cache_embeddings: Option<bool>,
 }
 
 #[derive(Deserialize, PartialEq, Debug, Clone)]
@@ -165,6 +436,28 @@ pub enum Justification {
     Right,
     Center,
 }
+// Controls where the speaker's name is drawn for each chatlog item.
+#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NameDisplayStyle {
+    // the name prefixes the first line of the message, e.g. "Name: Hello"
+    #[default]
+    Inline,
+    // the name is drawn on its own line above the message
+    Header,
+}
+
+// What happens to the sentences of a new response that match `user_narration_patterns`.
+#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UserNarrationAction {
+    // the sentences are removed from the response
+    #[default]
+    Remove,
+    // the sentences are kept, but the chat notes that they were found
+    Flag,
+}
+
 impl From<Justification> for Alignment {
     fn from(value: Justification) -> Self {
         match value {
@@ -175,22 +468,136 @@ impl From<Justification> for Alignment {
     }
 }
 
-#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct ConfiguredParameters {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<usize>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub top_p: Option<f32>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub min_p: Option<f32>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub repeat_penalty_range: Option<usize>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub temperature: Option<f32>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mirostat: Option<usize>, // 0=disabled, 1=mirostat1, 2=mirostat2
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub mirostat_eta: Option<f32>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_f32"
+    )]
     pub mirostat_tau: Option<f32>,
 }
+// f32 values get widened to f64 when written out as yaml, which turns 0.9 into 0.8999999761581421;
+// going through the shortest string representation keeps the saved values readable.
+fn serialize_optional_f32<S: serde::Serializer>(
+    value: &Option<f32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(v) => serializer.serialize_f64(v.to_string().parse::<f64>().unwrap_or(*v as f64)),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl ConfiguredParameters {
+    // checks the sampler values that are set for anything out of the range the
+    // backends will accept and returns a description of the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temp) = self.temperature {
+            if temp < 0.0 {
+                return Err(format!("temperature must not be negative (got {})", temp));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(format!("top_p must be between 0.0 and 1.0 (got {})", top_p));
+            }
+        }
+        if let Some(min_p) = self.min_p {
+            if !(0.0..=1.0).contains(&min_p) {
+                return Err(format!("min_p must be between 0.0 and 1.0 (got {})", min_p));
+            }
+        }
+        if let Some(rep_pen) = self.repeat_penalty {
+            if rep_pen <= 0.0 {
+                return Err(format!(
+                    "repeat_penalty must be greater than 0.0 (got {})",
+                    rep_pen
+                ));
+            }
+        }
+        if let Some(mirostat) = self.mirostat {
+            if mirostat > 2 {
+                return Err(format!("mirostat must be 0, 1 or 2 (got {})", mirostat));
+            }
+        }
+        Ok(())
+    }
+}
+
+// the parameter set and sampler settings given on the command line, which take precedence
+// over the parameter set a chat would otherwise start with.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ParameterOverrides {
+    // the name of the parameter set picked with -p, if one was
+    pub parameters_name: Option<String>,
+
+    pub temperature: Option<f32>,
+    pub top_k: Option<usize>,
+    pub top_p: Option<f32>,
+    pub min_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+    pub repeat_penalty_range: Option<usize>,
+}
+impl ParameterOverrides {
+    // replaces the sampler settings of the parameter set with the ones given.
+    pub fn apply(&self, params: &mut ConfiguredParameters) {
+        if let Some(temp) = self.temperature {
+            params.temperature = Some(temp);
+        }
+        if let Some(top_k) = self.top_k {
+            params.top_k = Some(top_k);
+        }
+        if let Some(top_p) = self.top_p {
+            params.top_p = Some(top_p);
+        }
+        if let Some(min_p) = self.min_p {
+            params.min_p = Some(min_p);
+        }
+        if let Some(rep_pen) = self.repeat_penalty {
+            params.repeat_penalty = Some(rep_pen);
+        }
+        if let Some(rep_range) = self.repeat_penalty_range {
+            params.repeat_penalty_range = Some(rep_range);
+        }
+    }
+}
 
 #[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigurationFile {
     // version number for the file which should be incremented on breaking changes
     pub version: u16,
@@ -214,15 +621,112 @@ pub struct ConfigurationFile {
     // the foreground RGB color of the 'secondary' element in the progress bar
     pub progress_secondary_rgb: Option<[u8; 3]>,
 
+    // the foreground RGB color of the selected item in the character and chatlog selectors;
+    // defaults to the terminal's light green.
+    pub selection_highlight_rgb: Option<[u8; 3]>,
+
+    // the text drawn in front of the selected item in the character and chatlog selectors.
+    pub selection_highlight_symbol: Option<String>,
+
     // optional setting to determine how the text should be justified.
     pub chat_text_justification: Option<Justification>,
 
     // optional setting to add a 'buffer' between chatlog items to aid in visually grouping them.
     pub add_visual_buffer_between_chatlog_items: Option<bool>,
 
+    // optional cap on the width, in columns, of the chat text; the text column is centered
+    // when the terminal is wider than this.
+    pub max_line_width: Option<u16>,
+
+    // optional cap on the width, in columns, of the box used to type a reply; it's centered in
+    // the text column, which already respects `max_line_width`.
+    pub max_reply_width: Option<u16>,
+
+    // optional setting to determine if the speaker's name is inlined or drawn as a header line.
+    pub name_display_style: Option<NameDisplayStyle>,
+
+    // if true, adjacent chatlog items from the same speaker are merged together when a log is opened.
+    pub merge_consecutive_speakers_on_load: Option<bool>,
+
+    // if set, runs of blank lines in new responses are shortened to this many lines.
+    pub collapse_blank_lines: Option<usize>,
+
+    // if true, `collapse_blank_lines` is also applied when drawing the chatlog, which tidies
+    // up older responses without changing the log file.
+    pub collapse_blank_lines_when_rendering: Option<bool>,
+
+    // if true, the character's greeting is used exactly as written when creating a new chatlog
+    // instead of dropping the blank lines around it and the whitespace at the end of each line.
+    pub keep_greeting_whitespace: Option<bool>,
+
+    // the greeting used for new chatlogs when the character file doesn't have one; the same
+    // tags as a character greeting can be used. an empty string starts those logs out empty.
+    pub empty_greeting: Option<String>,
+
+    // text the model can write to end the scene, such as "[END]"; it's cut out of the response,
+    // along with anything after it, and a notice shows that the scene has ended.
+    pub scene_end_marker: Option<String>,
+
+    // if true, sending a reply after the scene has ended doesn't start generating a response.
+    pub scene_end_stops_auto_generation: Option<bool>,
+
+    // if false, the estimate of the chat's token usage above the chatlog is hidden from the
+    // start; it can be toggled with 'ctrl-k' either way. defaults to true.
+    pub show_prompt_estimate: Option<bool>,
+
+    // if true, the chatlog scrolls back to the newest item when a response arrives,
+    // unless it was scrolled by hand in the last few seconds.
+    pub autoscroll_on_new_message: Option<bool>,
+
+    // the smallest terminal size, as [columns, rows], that the user interface is drawn for;
+    // smaller terminals just show a message asking for more room. defaults to [40, 10].
+    pub min_terminal_size: Option<[u16; 2]>,
+
+    // if false, clearing the chat with 'ctrl-l' leaves the chatlog empty instead of starting it
+    // over with the character's greeting.
+    pub clear_chat_keeps_greeting: Option<bool>,
+
+    // regular expressions for sentences in new responses that narrate for the user, such as
+    // "^You (feel|think)"; matches are handled according to `user_narration_action`.
+    pub user_narration_patterns: Option<Vec<String>>,
+
+    // whether sentences matching `user_narration_patterns` are removed (the default) or flagged.
+    pub user_narration_action: Option<UserNarrationAction>,
+
+    // if true, the chatlog items matched by sentence similarity for a new response are kept
+    // on that response in the log so they can be highlighted later.
+    pub record_similar_sentence_matches: Option<bool>,
+
+    // the number of backups of a chatlog to keep, made each time it's saved; 0 disables them.
+    // defaults to 10.
+    pub log_backup_count: Option<usize>,
+
+    // if set, new messages only go to the chatlog's journal as they arrive and the full log is
+    // saved once the chat has been idle for this many seconds (or when leaving the chat).
+    pub autosave_idle_seconds: Option<u64>,
+
+    // if false, sending a reply only adds it to the chatlog and a response has to be asked for
+    // with ctrl-y. defaults to true; can be toggled in the chat with the 'a' key.
+    pub auto_generate_on_reply: Option<bool>,
+
+    // if false, quitting from the main menu and leaving a chat while a response is still being
+    // generated happen right away instead of asking first. defaults to true.
+    pub confirm_on_quit: Option<bool>,
+
+    // if true, responses aren't shown as they stream in and only appear once they're done.
+    // defaults to false; can be toggled in the chat with the 'S' key.
+    pub disable_response_streaming: Option<bool>,
+
+    // if true, the pieces of a response streaming in are shaded in turns so that the token
+    // boundaries can be seen when debugging sampling. defaults to false.
+    pub shade_streamed_tokens: Option<bool>,
+
     // if true, this will trim the text inferrence to just before the first usage of " {display_name}:"
     pub stop_on_display_name: bool,
 
+    // extra strings that end a response when generated, in addition to the participant names.
+    pub stop_sequences: Option<Vec<String>>,
+
     // the current prediction multiplier representing the mount of text characters per token, on average,
     // after tokenization. used to predict how much can be added to the chat history buff and still keep
     // the requested token window size open.
@@ -231,6 +735,21 @@ pub struct ConfigurationFile {
     // a suggestion of the number of tokens that can be returned by the llm
     pub maximum_new_tokens: Option<usize>,
 
+    // if set, a locally hosted model is unloaded after the engine sits idle for this many
+    // seconds and loaded again for the next request, freeing memory in the meantime.
+    pub idle_unload_seconds: Option<u64>,
+
+    // the number of responses generated to choose from when asking for candidates.
+    // defaults to DEFAULT_CANDIDATE_COUNT and is limited to MAX_CANDIDATE_COUNT.
+    pub candidate_count: Option<usize>,
+
+    // the percentage of the model's context size to hold back when building the prompt so that
+    // mistakes in the text-to-token prediction don't overflow the context. defaults to 5.0.
+    pub context_headroom_percent: Option<f32>,
+
+    // if false, <|participants_descriptions|> is left empty in the prompt. defaults to true.
+    pub include_participants_descriptions: Option<bool>,
+
     // whether or not to use GPU accelleration; must also be configured right in Cargo.toml
     pub use_gpu: Option<bool>,
 
@@ -252,6 +771,15 @@ pub struct ConfigurationFile {
     pub models: Vec<ConfiguredLlm>,
 
     pub embedding_model: Option<ConfiguredEmbeddingModel>,
+
+    // the file this configuration was loaded from, if any; used when saving changes back out.
+    #[serde(skip)]
+    loaded_filepath: Option<PathBuf>,
+
+    // the profile merged on top of the configuration file when it was loaded, if any; the
+    // merged settings can't be saved back to the configuration file without mixing them in.
+    #[serde(skip)]
+    loaded_profile_filepath: Option<PathBuf>,
 }
 
 impl Default for ConfigurationFile {
@@ -265,17 +793,49 @@ impl Default for ConfigurationFile {
             chat_text_justification: None,
             progress_primary_rgb: None,
             progress_secondary_rgb: None,
+            selection_highlight_rgb: None,
+            selection_highlight_symbol: None,
             text_to_token_ratio_prediction: None,
             maximum_new_tokens: None,
+            idle_unload_seconds: None,
+            candidate_count: None,
+            context_headroom_percent: None,
+            include_participants_descriptions: None,
             use_gpu: Some(false),
             gpu_layer_count: None,
             thread_count: Some(8),
             batch_size: Some(512),
             add_visual_buffer_between_chatlog_items: None,
+            max_line_width: None,
+            max_reply_width: None,
+            name_display_style: None,
+            merge_consecutive_speakers_on_load: None,
+            collapse_blank_lines: None,
+            collapse_blank_lines_when_rendering: None,
+            keep_greeting_whitespace: None,
+            empty_greeting: None,
+            clear_chat_keeps_greeting: None,
+            scene_end_marker: None,
+            scene_end_stops_auto_generation: None,
+            show_prompt_estimate: None,
+            autoscroll_on_new_message: None,
+            min_terminal_size: None,
+            user_narration_patterns: None,
+            user_narration_action: None,
+            record_similar_sentence_matches: None,
+            log_backup_count: None,
+            autosave_idle_seconds: None,
+            auto_generate_on_reply: None,
+            confirm_on_quit: None,
+            disable_response_streaming: None,
+            shade_streamed_tokens: None,
             stop_on_display_name: true,
+            stop_sequences: None,
             parameters: Vec::new(),
             models: Vec::new(),
             embedding_model: None,
+            loaded_filepath: None,
+            loaded_profile_filepath: None,
         };
     }
 }
@@ -283,40 +843,256 @@ impl Default for ConfigurationFile {
 impl ConfigurationFile {
     // loads the configuration file by using the alternative path specified or by searching
     // common locations for the config file to load.
-    // if those fail to find a file, then a new configuration object is constructed with defaults and returned.
-    pub fn load_config(alt_config_filepath: Option<&String>) -> ConfigurationFile {
-        let filepath: Option<PathBuf> = locate_config_file("config.yaml", alt_config_filepath);
+    //
+    // if a profile file is given, its settings are merged on top of the configuration file
+    // before it gets deserialized; see `merge_yaml_values` for how.
+    //
+    // an error is returned if the file can't be found or read, has a setting that isn't
+    // understood, or has any of the problems found by `find_errors`, so that a typo doesn't
+    // quietly leave the app running on defaults.
+    pub fn load_config(
+        alt_config_filepath: Option<&String>,
+        profile_filepath: Option<&String>,
+    ) -> anyhow::Result<ConfigurationFile> {
+        let found_file: PathBuf = locate_config_file("config.yaml", alt_config_filepath)
+            .context("The configuration file couldn't be located")?;
 
-        // if we found a file, deserialize it as yaml
-        if let Some(found_file) = filepath {
-            match std::fs::read_to_string(&found_file) {
-                Ok(plain_string) => {
-                    match serde_yaml::from_str::<ConfigurationFile>(plain_string.as_str()) {
-                        Ok(cfg) => {
-                            return cfg;
-                        }
-                        Err(err) => {
-                            log::error!(
-                                "Failed to deserialize the configuration file ({:?}): {}",
-                                found_file,
-                                err
-                            );
-                        }
-                    };
-                }
-                Err(err) => log::error!(
-                    "Failed to load the configuration file ({:?}): {}",
+        let yaml = read_yaml_with_profile(&found_file, profile_filepath)
+            .with_context(|| format!("Failed to load the configuration file ({:?})", found_file))?;
+        let mut cfg = match serde_yaml::from_value::<ConfigurationFile>(yaml) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                // the merged yaml doesn't know the line numbers anymore, so see if the
+                // configuration file on its own has the problem to point out where it is
+                let located_err = std::fs::read_to_string(&found_file)
+                    .ok()
+                    .and_then(|s| serde_yaml::from_str::<ConfigurationFile>(&s).err());
+                return Err(anyhow!(
+                    "Failed to deserialize the configuration file ({:?}): {}",
                     found_file,
-                    err
-                ),
-            };
+                    located_err.unwrap_or(err)
+                ));
+            }
+        };
+        if let Some(config_dir) = found_file.parent() {
+            cfg.resolve_relative_paths(config_dir);
         }
+        cfg.resolve_template_presets();
 
-        // if we made it here, no config file was found, or if it was found, it could not be deserialized as yaml.
-        log::warn!(
-            "Using a default configuration file from memory since none were located to be read."
+        let errors = cfg.find_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "The configuration file ({:?}) has {} problem(s) to fix:\n  * {}",
+                found_file,
+                errors.len(),
+                errors.join("\n  * ")
+            ));
+        }
+
+        cfg.loaded_filepath = Some(found_file);
+        cfg.loaded_profile_filepath = profile_filepath.map(PathBuf::from);
+        for warning in cfg.validate() {
+            log::warn!("{}", warning);
+        }
+        Ok(cfg)
+    }
+
+    // checks the settings for problems that leave the app unable to work as configured and
+    // returns a description of each one, so that they can all be reported at once.
+    pub fn find_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for m in &self.models {
+            if m.path.is_none() && m.remote_server.is_none() {
+                errors.push(format!(
+                    "Model '{}': needs either a 'path' to a local model file or a 'remote_server' to use.",
+                    m.name
+                ));
+            }
+            if let Some(max_new_tokens) = self.maximum_new_tokens {
+                if m.context_size <= max_new_tokens {
+                    errors.push(format!(
+                        "Model '{}': context_size ({}) has to be larger than maximum_new_tokens ({}).",
+                        m.name, m.context_size, max_new_tokens
+                    ));
+                }
+            }
+        }
+        errors
+    }
+
+    // checks the settings for combinations that can't work as written and returns a
+    // description of each problem found. none of them are fatal, since the engine
+    // corrects for them when building each request.
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(max_new_tokens) = self.maximum_new_tokens {
+            for m in &self.models {
+                let limit = max_new_tokens_limit(m.context_size);
+                if max_new_tokens > limit {
+                    warnings.push(format!(
+                        "maximum_new_tokens ({}) leaves too little room for the prompt in the {} token context of model '{}'; it will be limited to {} tokens.",
+                        max_new_tokens, m.context_size, m.name, limit
+                    ));
+                }
+            }
+        }
+        for m in &self.models {
+            if let Some(style) = &m.remote_api_style {
+                if !style.eq_ignore_ascii_case(REMOTE_API_STYLE_KOBOLD)
+                    && !style.eq_ignore_ascii_case(REMOTE_API_STYLE_OPENAI)
+                {
+                    warnings.push(format!(
+                        "Model '{}': remote_api_style '{}' isn't one of '{}' or '{}'; the KoboldCpp api will be used.",
+                        m.name, style, REMOTE_API_STYLE_KOBOLD, REMOTE_API_STYLE_OPENAI
+                    ));
+                }
+            }
+            if let Some(preset) = &m.template_preset {
+                if get_template_preset(preset).is_none() {
+                    warnings.push(format!(
+                        "Model '{}': template_preset '{}' isn't one of 'chatml', 'llama3', 'alpaca' or 'vicuna'.",
+                        m.name, preset
+                    ));
+                }
+            }
+            if m.prompt_instruct_template.is_empty() {
+                warnings.push(format!(
+                    "Model '{}': there's no prompt_instruct_template or usable template_preset, so the prompt will be empty.",
+                    m.name
+                ));
+            }
+            if m.grammar.is_some() && m.grammar_file.is_some() {
+                warnings.push(format!(
+                    "Model '{}': both grammar and grammar_file are set; only the inline grammar will be used.",
+                    m.name
+                ));
+            }
+            if (m.grammar.is_some() || m.grammar_file.is_some())
+                && m.remote_server.is_some()
+                && m.uses_openai_api()
+            {
+                warnings.push(format!(
+                    "Model '{}': grammars aren't supported by the OpenAI-compatible backend, so it will be ignored.",
+                    m.name
+                ));
+            }
+            if let Err(problem) = m.validate_gpu_devices() {
+                warnings.push(format!(
+                    "Model '{}': {}; the gpu device settings will be ignored.",
+                    m.name, problem
+                ));
+            }
+        }
+        for pattern in self.user_narration_patterns.iter().flatten() {
+            if let Err(err) = regex::Regex::new(pattern) {
+                warnings.push(format!(
+                    "user_narration_patterns entry '{}' isn't a valid regular expression and will be ignored: {}",
+                    pattern, err
+                ));
+            }
+        }
+        if let Some(candidate_count) = self.candidate_count {
+            if candidate_count == 0 || candidate_count > MAX_CANDIDATE_COUNT {
+                warnings.push(format!(
+                    "candidate_count ({}) must be between 1 and {}; it will be limited to that range.",
+                    candidate_count, MAX_CANDIDATE_COUNT
+                ));
+            }
+        }
+        warnings
+    }
+
+    // writes the parameter sets back to the configuration file this was loaded from. only the
+    // `parameters` section is replaced; every other setting is kept as it is in the file on disk.
+    // since the yaml gets reserialized, comments are lost, so the previous file is first
+    // copied next to it with a '.bak' extension. nothing is saved while a profile is in use,
+    // since its settings would end up in the configuration file.
+    pub fn save_config(&self) -> anyhow::Result<()> {
+        let filepath = self.loaded_filepath.as_ref().context(
+            "The configuration wasn't loaded from a file, so there's nowhere to save it",
+        )?;
+        if let Some(profile_filepath) = &self.loaded_profile_filepath {
+            return Err(anyhow!(
+                "The profile {:?} is merged into the loaded configuration, so saving would write its settings into {:?}; edit the files by hand instead",
+                profile_filepath,
+                filepath
+            ));
+        }
+
+        let plain_string = std::fs::read_to_string(filepath)
+            .context("Attempting to read the configuration file before saving")?;
+        let mut yaml: serde_yaml::Value = serde_yaml::from_str(plain_string.as_str())
+            .context("Attempting to parse the configuration file before saving")?;
+        let mapping = yaml
+            .as_mapping_mut()
+            .context("The configuration file isn't a yaml mapping")?;
+        mapping.insert(
+            serde_yaml::Value::from("parameters"),
+            serde_yaml::to_value(&self.parameters)
+                .context("Attempting to serialize the parameter sets")?,
         );
-        return Default::default();
+        let new_contents =
+            serde_yaml::to_string(&yaml).context("Attempting to serialize the configuration")?;
+
+        let mut backup_filepath = filepath.as_os_str().to_owned();
+        backup_filepath.push(".bak");
+        std::fs::copy(filepath, &backup_filepath)
+            .context("Attempting to back up the configuration file")?;
+        std::fs::write(filepath, new_contents)
+            .context("Attempting to write the configuration file")?;
+
+        Ok(())
+    }
+
+    // rewrites the relative model and embedding model paths so that they're relative to the
+    // directory holding the configuration file instead of the current working directory.
+    // absolute paths are left untouched.
+    fn resolve_relative_paths(&mut self, config_dir: &Path) {
+        let resolve = |p: &str| -> String {
+            if p.is_empty() || Path::new(p).is_absolute() {
+                p.to_owned()
+            } else {
+                config_dir.join(p).to_string_lossy().into_owned()
+            }
+        };
+
+        for m in self.models.iter_mut() {
+            if let Some(local_path) = m.path.as_mut() {
+                *local_path = resolve(local_path);
+            }
+        }
+        if let Some(embedding_model) = self.embedding_model.as_mut() {
+            embedding_model.dir_path = resolve(&embedding_model.dir_path);
+        }
+    }
+
+    // fills in the prompt templates of the models that only name a `template_preset`. a
+    // `prompt_instruct_template` that's set is always kept.
+    fn resolve_template_presets(&mut self) {
+        for m in self.models.iter_mut() {
+            if !m.prompt_instruct_template.is_empty() {
+                continue;
+            }
+            if let Some(template) = m.template_preset.as_deref().and_then(get_template_preset) {
+                m.prompt_instruct_template = template.to_owned();
+            }
+        }
+    }
+
+    // returns the style used for the selected item in list selectors.
+    pub fn get_selection_highlight_style(&self) -> Style {
+        let color = match self.selection_highlight_rgb {
+            Some([r, g, b]) => Color::Rgb(r, g, b),
+            None => Color::LightGreen,
+        };
+        Style::default().fg(color).add_modifier(Modifier::BOLD)
+    }
+
+    // returns the text drawn in front of the selected item in list selectors.
+    pub fn get_selection_highlight_symbol(&self) -> &str {
+        self.selection_highlight_symbol
+            .as_deref()
+            .unwrap_or(DEFAULT_SELECTION_HIGHLIGHT_SYMBOL)
     }
 
     // This function takes in a string that should match a conifgured model or filepath and returns
@@ -340,6 +1116,84 @@ impl ConfigurationFile {
     }
 }
 
+// reads the configuration file as yaml and, if a profile file is given, merges it on top.
+// a relative profile path that doesn't exist from the working folder is looked for next to
+// the configuration file.
+fn read_yaml_with_profile(
+    config_filepath: &Path,
+    profile_filepath: Option<&String>,
+) -> anyhow::Result<serde_yaml::Value> {
+    let plain_string = std::fs::read_to_string(config_filepath)
+        .context("Attempting to read the configuration file")?;
+    let mut yaml: serde_yaml::Value = serde_yaml::from_str(plain_string.as_str())
+        .context("Attempting to parse the configuration file")?;
+
+    if let Some(profile) = profile_filepath {
+        let mut profile_path = PathBuf::from(profile);
+        if !profile_path.exists() && profile_path.is_relative() {
+            if let Some(config_dir) = config_filepath.parent() {
+                profile_path = config_dir.join(profile);
+            }
+        }
+        let profile_string = std::fs::read_to_string(&profile_path)
+            .with_context(|| format!("Attempting to read the profile file {:?}", profile_path))?;
+        let profile_yaml: serde_yaml::Value = serde_yaml::from_str(profile_string.as_str())
+            .with_context(|| format!("Attempting to parse the profile file {:?}", profile_path))?;
+        merge_yaml_values(&mut yaml, profile_yaml);
+    }
+
+    Ok(yaml)
+}
+
+// merges the `over` yaml on top of `base`, setting by setting:
+//  * mappings are merged key by key, so anything absent from `over` is kept from `base`
+//  * lists of mappings with a `name` key (like `models` and `parameters`) are merged by name,
+//    with entries whose name isn't in `base` added to the end
+//  * a null in `over` keeps the value from `base`
+//  * everything else in `over` replaces the value in `base`
+fn merge_yaml_values(base: &mut serde_yaml::Value, over: serde_yaml::Value) {
+    use serde_yaml::Value;
+
+    let is_named_list = |v: &Value| {
+        v.as_sequence().is_some_and(|seq| {
+            seq.iter()
+                .all(|item| item.get("name").and_then(Value::as_str).is_some())
+        })
+    };
+
+    match (base, over) {
+        (_, Value::Null) => {}
+        (Value::Mapping(base_map), Value::Mapping(over_map)) => {
+            for (key, over_value) in over_map {
+                match base_map.get_mut(&key) {
+                    Some(base_value) => merge_yaml_values(base_value, over_value),
+                    None => {
+                        base_map.insert(key, over_value);
+                    }
+                }
+            }
+        }
+        (base, over) if is_named_list(base) && is_named_list(&over) => {
+            let Value::Sequence(base_seq) = base else {
+                return;
+            };
+            let Value::Sequence(over_seq) = over else {
+                return;
+            };
+            for over_item in over_seq {
+                let matching = base_seq
+                    .iter_mut()
+                    .find(|base_item| base_item.get("name") == over_item.get("name"));
+                match matching {
+                    Some(base_item) => merge_yaml_values(base_item, over_item),
+                    None => base_seq.push(over_item),
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
 // loads a configuration file in the following order:
 //  1) alternate path provided as parameter
 //  2) 'platform' config folder (e.g. /home/alice/.config or C:\Users\Alice\AppData\Roaming or /Users/Alice/Library/Application Support)
@@ -386,3 +1240,136 @@ pub fn get_log_folder(char_name: &str) -> std::path::PathBuf {
 
     return log_path;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(maximum_new_tokens: usize, context_size: usize) -> ConfigurationFile {
+        serde_yaml::from_str(
+            format!(
+                r#"
+version: 1
+display_name: "USER"
+stop_on_display_name: true
+maximum_new_tokens: {}
+parameters: []
+models:
+  - name: "small"
+    path: "models/small.gguf"
+    context_size: {}
+    prompt_instruct_template: "<|chat_history|>"
+"#,
+                maximum_new_tokens, context_size
+            )
+            .as_str(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn max_new_tokens_limit_leaves_room_for_the_prompt() {
+        assert_eq!(max_new_tokens_limit(512), 384);
+        assert_eq!(max_new_tokens_limit(4096), 3072);
+        assert!(max_new_tokens_limit(100) < 100);
+    }
+
+    #[test]
+    fn validate_warns_when_maximum_new_tokens_gets_limited() {
+        let config = test_config(400, 512);
+        let warnings = config.validate();
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("maximum_new_tokens (400)") && w.contains("limited to 384")));
+        assert!(config.find_errors().is_empty());
+
+        let config = test_config(300, 512);
+        assert!(!config
+            .validate()
+            .iter()
+            .any(|w| w.contains("maximum_new_tokens")));
+    }
+
+    #[test]
+    fn find_errors_reports_context_size_not_above_maximum_new_tokens() {
+        let config = test_config(512, 512);
+        let errors = config.find_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].contains("context_size (512) has to be larger than maximum_new_tokens (512)")
+        );
+
+        let config = test_config(1024, 512);
+        assert_eq!(config.find_errors().len(), 1);
+    }
+
+    #[test]
+    fn template_presets_are_found_by_name() {
+        for (name, marker) in [
+            ("chatml", "<|im_start|>"),
+            ("llama3", "<|start_header_id|>"),
+            ("alpaca", "### Instruction:"),
+            ("vicuna", "USER:"),
+        ] {
+            let preset = get_template_preset(name).unwrap();
+            assert!(preset.contains(marker), "{} preset lacks {}", name, marker);
+            assert!(preset.contains("<|chat_history|>"));
+            assert_eq!(
+                get_template_preset(&name.to_ascii_uppercase()),
+                Some(preset)
+            );
+        }
+        assert_eq!(get_template_preset("ChatML"), Some(TEMPLATE_PRESET_CHATML));
+        assert_eq!(get_template_preset("Llama3"), Some(TEMPLATE_PRESET_LLAMA3));
+        assert_eq!(get_template_preset("mystery"), None);
+    }
+
+    #[test]
+    fn template_presets_fill_in_missing_templates() {
+        let mut config: ConfigurationFile = serde_yaml::from_str(
+            r#"
+version: 1
+display_name: "USER"
+stop_on_display_name: true
+parameters: []
+models:
+  - name: "known"
+    path: "models/known.gguf"
+    context_size: 4096
+    template_preset: "ChatML"
+  - name: "unknown"
+    path: "models/unknown.gguf"
+    context_size: 4096
+    template_preset: "mystery"
+  - name: "explicit"
+    path: "models/explicit.gguf"
+    context_size: 4096
+    template_preset: "llama3"
+    prompt_instruct_template: "Custom: <|chat_history|>"
+"#,
+        )
+        .unwrap();
+        config.resolve_template_presets();
+
+        let known = config.find_model_configuration("known").unwrap();
+        assert_eq!(known.prompt_instruct_template, TEMPLATE_PRESET_CHATML);
+
+        let unknown = config.find_model_configuration("unknown").unwrap();
+        assert!(unknown.prompt_instruct_template.is_empty());
+        let warnings = config.validate();
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("template_preset 'mystery' isn't one of")));
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("Model 'unknown'") && w.contains("the prompt will be empty")));
+
+        let explicit = config.find_model_configuration("explicit").unwrap();
+        assert_eq!(
+            explicit.prompt_instruct_template,
+            "Custom: <|chat_history|>"
+        );
+        assert!(!warnings.iter().any(|w| w.starts_with("Model 'explicit'")));
+        assert!(!warnings.iter().any(|w| w.starts_with("Model 'known'")));
+    }
+}
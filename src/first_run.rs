@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use ratatui::{
+    prelude::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::Paragraph,
+};
+use std::{
+    fs::DirBuilder,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::tui::{
+    Frame, MessageBoxModalWidget, ProcessInputResult, TerminalEvent, TerminalRenderable,
+    TextEditingBlockModalWidget, Tui,
+};
+
+const DEFAULT_CONTEXT_SIZE: usize = 4096;
+const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+// The questions asked by the first-run wizard, in the order they're asked.
+#[derive(Clone, Copy, PartialEq)]
+enum FirstRunStep {
+    DisplayName,
+    ModelPath,
+    ContextSize,
+    Temperature,
+}
+impl FirstRunStep {
+    fn number(&self) -> usize {
+        match self {
+            FirstRunStep::DisplayName => 1,
+            FirstRunStep::ModelPath => 2,
+            FirstRunStep::ContextSize => 3,
+            FirstRunStep::Temperature => 4,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            FirstRunStep::DisplayName => "Your name, as it should appear in the chatlog:",
+            FirstRunStep::ModelPath => "Path to a GGUF model file (or a KoboldCpp URL):",
+            FirstRunStep::ContextSize => "Context size of the model, in tokens:",
+            FirstRunStep::Temperature => "Sampling temperature to start with:",
+        }
+    }
+}
+const FIRST_RUN_STEP_COUNT: usize = 4;
+
+// A minimal scene shown when no configuration file exists that asks the user for the few
+// settings needed to get chatting and then writes a fresh configuration file.
+pub struct FirstRunWizardState {
+    step: FirstRunStep,
+
+    // the editor for the answer to the current step
+    editor: TextEditingBlockModalWidget,
+
+    // the answers collected so far
+    display_name: String,
+    model_path: String,
+    context_size: usize,
+    temperature: f32,
+
+    // set to true once every question has been answered
+    is_complete: bool,
+
+    // contains a modal dialog widget used to show a message or alert to the user
+    modal_messagebox: Option<MessageBoxModalWidget>,
+}
+impl TerminalRenderable for FirstRunWizardState {
+    fn process_input(&mut self, event: TerminalEvent) -> ProcessInputResult {
+        if let Some(modal) = self.modal_messagebox.as_mut() {
+            modal.process_input(event);
+            if modal.is_finished {
+                self.modal_messagebox = None;
+            }
+            return ProcessInputResult::None;
+        }
+
+        self.editor.process_input(event);
+        if !self.editor.is_finished {
+            return ProcessInputResult::None;
+        }
+
+        // backing out of any question abandons the wizard
+        if !self.editor.is_success {
+            return ProcessInputResult::Quit;
+        }
+
+        let answer = self.editor.text.trim().to_owned();
+        let next_step = match self.step {
+            FirstRunStep::DisplayName => {
+                if answer.is_empty() {
+                    self.show_invalid_answer("A display name is required.");
+                    return ProcessInputResult::None;
+                }
+                self.display_name = answer;
+                Some(FirstRunStep::ModelPath)
+            }
+            FirstRunStep::ModelPath => {
+                if answer.is_empty() {
+                    self.show_invalid_answer("A model file path or KoboldCpp URL is required.");
+                    return ProcessInputResult::None;
+                }
+                self.model_path = answer;
+                Some(FirstRunStep::ContextSize)
+            }
+            FirstRunStep::ContextSize => match answer.parse::<usize>() {
+                Ok(size) if size > 0 => {
+                    self.context_size = size;
+                    Some(FirstRunStep::Temperature)
+                }
+                _ => {
+                    self.show_invalid_answer("The context size must be a positive whole number.");
+                    return ProcessInputResult::None;
+                }
+            },
+            FirstRunStep::Temperature => match answer.parse::<f32>() {
+                Ok(temp) if temp >= 0.0 => {
+                    self.temperature = temp;
+                    None
+                }
+                _ => {
+                    self.show_invalid_answer("The temperature must be a number of 0.0 or more.");
+                    return ProcessInputResult::None;
+                }
+            },
+        };
+
+        match next_step {
+            Some(step) => {
+                self.step = step;
+                self.editor = TextEditingBlockModalWidget::new(
+                    step.title().to_owned(),
+                    self.default_answer(step),
+                );
+                ProcessInputResult::None
+            }
+            None => {
+                self.is_complete = true;
+                ProcessInputResult::Quit
+            }
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let vchunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(10), Constraint::Min(4)].as_ref())
+            .split(frame.size());
+
+        let intro_lines = vec![
+            Line::from("Welcome to sentient_core!".bold()),
+            Line::from("No configuration file was found, so let's create one."),
+            Line::from(Span::styled(
+                format!(
+                    "Step {} of {} (enter = accept, esc = quit)",
+                    self.step.number(),
+                    FIRST_RUN_STEP_COUNT
+                ),
+                Style::default().fg(Color::Rgb(100, 100, 100)),
+            )),
+        ];
+        let intro = Paragraph::new(intro_lines).alignment(Alignment::Center);
+        frame.render_widget(intro, vchunks[1]);
+
+        // only one of the modals gets drawn, mirroring the input processing order
+        if let Some(modal) = &self.modal_messagebox {
+            modal.render(frame);
+        } else {
+            self.editor.render(frame);
+        }
+    }
+}
+impl FirstRunWizardState {
+    pub fn new() -> Self {
+        let step = FirstRunStep::DisplayName;
+        Self {
+            step,
+            editor: TextEditingBlockModalWidget::new(step.title().to_owned(), "USER".to_owned()),
+            display_name: String::new(),
+            model_path: String::new(),
+            context_size: DEFAULT_CONTEXT_SIZE,
+            temperature: DEFAULT_TEMPERATURE,
+            is_complete: false,
+            modal_messagebox: None,
+        }
+    }
+
+    fn default_answer(&self, step: FirstRunStep) -> String {
+        match step {
+            FirstRunStep::DisplayName => self.display_name.clone(),
+            FirstRunStep::ModelPath => self.model_path.clone(),
+            FirstRunStep::ContextSize => self.context_size.to_string(),
+            FirstRunStep::Temperature => self.temperature.to_string(),
+        }
+    }
+
+    // shows the problem with the answer and reopens the editor with the rejected text
+    fn show_invalid_answer(&mut self, message: &str) {
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+            "Invalid Answer",
+            message,
+            50,
+            20,
+        ));
+        self.editor = TextEditingBlockModalWidget::new(
+            self.step.title().to_owned(),
+            self.editor.text.clone(),
+        );
+    }
+
+    // builds the text of the configuration file from the collected answers. the yaml is
+    // written by hand so that the explanatory comments survive for the user to read later.
+    fn build_config_yaml(&self) -> String {
+        // JSON strings are valid YAML double-quoted scalars, so use that for escaping
+        let quote = |s: &str| serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_owned());
+
+        let model_location =
+            if self.model_path.starts_with("http://") || self.model_path.starts_with("https://") {
+                format!("remote_server: {}", quote(&self.model_path))
+            } else {
+                format!("path: {}", quote(&self.model_path))
+            };
+
+        format!(
+            r#"version: {version}
+
+# Generated by the first-run setup. See the README for the rest of the available settings.
+display_name: {display_name}
+
+# Aligns the text: Left, Right, Center
+chat_text_justification: Left
+
+# Stops on finding " {{display_name}}:" and trims input to that.
+stop_on_display_name: true
+
+# To enable GPU accelleration, uncomment these lines.
+#use_gpu: true
+#gpu_layer_count: 100
+#batch_size: 512
+#thread_count: 1
+
+parameters:
+  - name: "Simple-1"
+    top_k: 20
+    top_p: 0.9
+    repeat_penalty: 1.15
+    temperature: {temperature}
+    repeat_penalty_range: 512
+
+models:
+  - name: "default"
+    {model_location}
+    context_size: {context_size}
+    prompt_instruct_template: |-
+      Continue the chat dialogue below. Write a single reply for the character named "<|character_name|>".
+      <|character_description|>
+      <|user_description|>
+      <|character_context|>
+      <|chat_history|>
+      <|character_name|>:
+"#,
+            version = crate::config::CURRENT_VERSION,
+            display_name = quote(&self.display_name),
+            temperature = self.temperature,
+            model_location = model_location,
+            context_size = self.context_size,
+        )
+    }
+}
+
+// Runs the first-run wizard on the terminal and, if the user finishes it, writes the
+// new configuration file to `config_filepath`. Returns false if the user backed out.
+// The terminal interface is enabled for the wizard and disabled again before returning.
+pub fn run_first_run_wizard(
+    tui: &mut Tui,
+    config_filepath: &Path,
+    ui_draw_tick_rate: u64,
+) -> Result<bool> {
+    Tui::enable().context("should have been able to start the terminal interface")?;
+
+    let draw_tick_rate = Duration::from_millis(ui_draw_tick_rate);
+    let mut draw_last_tick = Instant::now();
+    let mut wizard = FirstRunWizardState::new();
+    let loop_result = loop {
+        if draw_tick_rate < draw_last_tick.elapsed() {
+            if let Err(err) = tui.draw(&mut wizard) {
+                break Err(err);
+            }
+            draw_last_tick += draw_tick_rate;
+        }
+        if tui.process_input(&mut wizard) == ProcessInputResult::Quit {
+            break Ok(());
+        }
+    };
+
+    Tui::disable().context("failed to disable the terminal interface")?;
+    loop_result.context("failed to draw the first-run setup")?;
+
+    if !wizard.is_complete {
+        return Ok(false);
+    }
+
+    if let Some(parent) = config_filepath.parent() {
+        if !parent.as_os_str().is_empty() {
+            DirBuilder::new()
+                .recursive(true)
+                .create(parent)
+                .context("Attempting to create the directory for the new configuration file")?;
+        }
+    }
+    std::fs::write(config_filepath, wizard.build_config_yaml())
+        .context("Attempting to write the new configuration file")?;
+
+    Ok(true)
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    character_select::CHARACTERS_FOLDER_NAME,
+    chatlog::{ChatLog, ChatLogItem},
+    config::{CharacterFileYaml, ConfigurationFile, ConfiguredParameters},
+    llm_engine::{LlmEngine, LlmEngineRequest, LlmEngineResponse, TextInferenceContext},
+};
+
+// finds the yaml file for a character given either its path or its name in the characters folder.
+fn find_character_file(name_or_path: &str) -> Result<PathBuf> {
+    let fp = PathBuf::from(name_or_path);
+    if fp.is_file() {
+        return Ok(fp);
+    }
+    let fp = Path::new(CHARACTERS_FOLDER_NAME).join(format!("{}.yaml", name_or_path));
+    if fp.is_file() {
+        return Ok(fp);
+    }
+    Err(anyhow!(
+        "The character '{}' isn't a file or a character in the '{}' folder.",
+        name_or_path,
+        CHARACTERS_FOLDER_NAME
+    ))
+}
+
+// sends the prompt to the character as a message from the user, on top of the chatlog file if
+// one is given or an empty chatlog otherwise, and returns the character's response. nothing
+// gets written back to the chatlog file.
+pub fn run_one_shot_inference(
+    engine: &LlmEngine,
+    config: &ConfigurationFile,
+    parameters: &ConfiguredParameters,
+    prompt: &str,
+    character_name_or_path: &str,
+    log_filepath: Option<&String>,
+) -> Result<String> {
+    let character_filepath = find_character_file(character_name_or_path)?;
+    // a character file that can't be read shouldn't quietly turn into an empty character
+    let character_yaml = std::fs::read_to_string(&character_filepath).with_context(|| {
+        format!(
+            "Attempting to read the character file {:?}",
+            character_filepath
+        )
+    })?;
+    let character: CharacterFileYaml =
+        serde_yaml::from_str(&character_yaml).with_context(|| {
+            format!(
+                "Attempting to parse the character file {:?}",
+                character_filepath
+            )
+        })?;
+
+    let mut chatlog = match log_filepath {
+        Some(fp) => ChatLog::new_from_json(&PathBuf::from(fp))
+            .with_context(|| format!("Attempting to load the chatlog {:?}", fp))?,
+        None => {
+            let mut chatlog = ChatLog::new();
+            chatlog.current_context = character.context.clone();
+            chatlog
+        }
+    };
+    chatlog.push(ChatLogItem::new_from_str(
+        config.display_name.clone(),
+        prompt.trim(),
+    ));
+
+    let context = TextInferenceContext {
+        character: character.clone(),
+        model_config_override: None,
+        chatlog_owner: character,
+        other_participants: Vec::new(),
+        chatlog,
+        should_continue: false,
+        parameters: parameters.clone(),
+        nudge: None,
+        randomize_seed: false,
+        similar_matches: None,
+        skip_similar_sentences: false,
+        dropped_turns: 0,
+    };
+    engine
+        .send_to_server
+        .send(LlmEngineRequest::TextInference(context))
+        .context("Attempting to send the text inference request to the engine")?;
+
+    // the engine sends status updates and streamed text along the way, which aren't needed
+    // since only the finished response gets printed
+    loop {
+        match engine
+            .recv_on_client
+            .recv()
+            .context("The engine stopped before responding")?
+        {
+            LlmEngineResponse::NewText(Some(text), _) => return Ok(text.trim().to_owned()),
+            LlmEngineResponse::NewText(None, _) => {
+                return Err(anyhow!("The model didn't return a response."))
+            }
+            LlmEngineResponse::ModelLoadFailed(reason) => {
+                return Err(anyhow!("The model could not be loaded: {}", reason))
+            }
+            _ => {}
+        }
+    }
+}
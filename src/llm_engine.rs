@@ -1,4 +1,13 @@
-use std::thread;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 // these to uses are for logging debug files out for the prompt and the text inferrence result.
 #[cfg(debug_assertions)]
@@ -6,6 +15,8 @@ use std::fs::File;
 #[cfg(debug_assertions)]
 use std::io::Write;
 
+use std::io::{BufRead, BufReader};
+
 use crossbeam::channel::{bounded, Receiver, Sender};
 use llama_cpp_rs::{
     options::{ModelOptions, PredictOptions},
@@ -14,8 +25,11 @@ use llama_cpp_rs::{
 use rand::{rngs::ThreadRng, Rng};
 use serde::{Deserialize, Serialize};
 
-use crate::{chatlog::ChatLog, config::*};
-use anyhow::Context;
+use crate::{
+    chatlog::{ChatLog, ChatLogItem, SimilarSentenceMatch},
+    config::*,
+};
+use anyhow::{anyhow, Context};
 
 #[cfg(feature = "sentence_similarity")]
 use crate::vector_embedding_engine::VectorEmbeddingEngine;
@@ -25,29 +39,136 @@ pub const DEFAULT_NUM_OF_SENTENCE_MATCHES: usize = 3;
 
 pub const DEFAULT_TEXT_TO_TOKEN_RATIO: f32 = 3.0;
 pub const DEFAULT_MAX_NEW_TOKENS: usize = 150;
+pub const DEFAULT_CONTEXT_HEADROOM_PERCENT: f32 = 5.0;
+
+// the largest share of the prompt that <|participants_descriptions|> can take up
+const PARTICIPANTS_DESCRIPTIONS_MAX_PERCENT: usize = 25;
 pub const DEFAULT_BATCH_SIZE: usize = 8;
 pub const DEFAULT_THREAD_COUNT: usize = 8;
 
+// how often to check for a cancel while waiting on a remote server's generation, in milliseconds
+const REMOTE_CANCEL_POLL_MS: u64 = 50;
+
+// how long to wait before the first retry of a failed remote request, in milliseconds; the
+// wait doubles for each retry after that, up to `REMOTE_RETRY_MAX_DELAY_MS`
+const REMOTE_RETRY_BASE_DELAY_MS: u64 = 500;
+const REMOTE_RETRY_MAX_DELAY_MS: u64 = 30_000;
+
+// how long the engine waits for work before checking again if the model should be unloaded,
+// used when there's no loaded model to time out on
+const IDLE_UNLOAD_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Requests are the main unit of work for the engine thread and are processed one at a
+// time, in the order they were sent, on the `send_to_server` channel.
 #[derive(Clone, PartialEq)]
 pub enum LlmEngineRequest {
     TextInference(TextInferenceContext),
+
+    // runs the text inference for the context the given number of times, each with a
+    // different seed; responds with CandidateProgress after each one and then NewCandidates
+    TextInferenceCandidates(TextInferenceContext, usize),
+
     ImmediateShutdown,
 }
 
+// Commands are sent on their own `send_cmd_to_server` channel so that they can reach the
+// engine thread while a request is still being worked on.
+//
+// Ordering guarantees between the two channels:
+//  * there is no ordering between a command and a request; when the engine is idle it takes
+//    whichever message shows up first.
+//  * while a TextInference request is running, the command channel is polled. CancelTextInference
+//    applies to that in-flight request right away, while every other command is held and then
+//    run in the order it was received, after the response for the request has been sent.
+//  * a CancelTextInference that arrives while no request is running is dropped, so a stale
+//    cancel can never stop the next request.
+#[derive(Clone, PartialEq)]
+pub enum LlmEngineCommand {
+    // stops the text inference currently running; responds with TextInferenceCancelled
+    CancelTextInference,
+
+    // builds the prompt that would be sent for the context without running inference;
+    // responds with PromptPreview
+    PreviewPrompt(TextInferenceContext),
+
+    // estimates the size of the prompt that would be sent for the context; responds with
+    // PromptEstimate
+    EstimatePrompt(TextInferenceContext),
+
+    // replaces the configuration the engine uses; responds with ConfigReloaded
+    ReloadConfig(ConfigurationFile),
+
+    // asks for the instruct template of the active model configuration; responds with
+    // InstructTemplate
+    GetInstructTemplate,
+
+    // replaces the instruct template of the active model configuration until the engine is
+    // restarted; the configuration file isn't changed
+    SetInstructTemplate(String),
+}
+
 #[derive(Clone, PartialEq)]
 pub enum LlmEngineResponse {
     NewText(Option<String>, TextInferenceContext),
+
+    // a piece of newly generated text for a text inference request that's still running, sent
+    // as it's produced to be appended to the pieces before it; the NewText response that
+    // follows still carries the whole response.
+    NewTextFragment(String),
+    CandidateProgress(usize, usize),
+    NewCandidates(Vec<String>, TextInferenceContext),
+    TextInferenceCancelled(TextInferenceContext),
+    PromptPreview(String),
+
+    // the estimated tokens in the prompt for the context, the estimated tokens it would take if
+    // none of the chatlog was left out to fit, the context size of the active model and the
+    // number of chatlog items left out to fit.
+    PromptEstimate(usize, usize, usize, usize),
+
+    // the name of the active model configuration and the instruct template it's using
+    InstructTemplate(String, String),
+    ConfigReloaded,
+    ModelLoading(String),
     ModelLoaded,
+
+    // the model configuration couldn't be loaded, with the reason why. when it happens at
+    // startup the engine thread exits after sending it.
+    ModelLoadFailed(String),
+
+    // the name of the model configuration the engine switched to; only sent when a different
+    // configuration gets loaded, not when an idle model is brought back.
+    ModelSwitched(String),
+
+    // describes the settings in the parameter set that the active backend won't use; only
+    // sent the first time a parameter set is used with a model configuration.
+    IgnoredParameters(String),
+
+    // describes work other than generating text that the engine is busy with, such as building
+    // the sentence embeddings for a chatlog, so the client can show what it's waiting on;
+    // None once that work is done.
+    EngineStatus(Option<String>),
+
+    // warns that the model configuration asks for a bigger context than the model file was
+    // trained for; only sent the first time the model configuration is loaded.
+    ContextSizeWarning(String),
 }
 
 pub struct LlmEngine {
     pub send_to_server: Sender<LlmEngineRequest>,
+    pub send_cmd_to_server: Sender<LlmEngineCommand>,
     pub recv_on_client: Receiver<LlmEngineResponse>,
     pub handle: thread::JoinHandle<()>,
+
+    // the name of the model configuration the engine was started with
+    pub startup_model_name: String,
 }
 impl LlmEngine {
     pub fn spawn(config: ConfigurationFile, model_fileorname: String) -> LlmEngine {
+        let startup_model_name = config
+            .find_model_configuration(&model_fileorname)
+            .map_or_else(|| model_fileorname.clone(), |m| m.name);
         let (send_to_server, recv_on_server) = bounded::<LlmEngineRequest>(10);
+        let (send_cmd_to_server, recv_cmd_on_server) = bounded::<LlmEngineCommand>(10);
         let (send_to_client, recv_on_client) = bounded::<LlmEngineResponse>(10);
         let thread_handle = thread::spawn(move || {
             // failures should have been detected before this gets here
@@ -55,76 +176,88 @@ impl LlmEngine {
                 .find_model_configuration(&model_fileorname)
                 .context("Attempting to find the model name provided in the configuration")
                 .unwrap();
-            let mut llm_model = None;
-
-            // setup the thread rng
-            let mut rng = rand::thread_rng();
-
-            // if we're using a local model, load it up
-            if let Some(local_model_path) = &model_config.path {
-                // use a provided seed for the model or make a new one
-                let this_seed = match model_config.seed {
-                    Some(s) => s,
-                    None => rng.gen_range(0..i32::MAX),
-                };
-
-                let mut model_params = ModelOptions {
-                    context_size: model_config.context_size as i32,
-                    seed: this_seed,
-                    n_gpu_layers: 0,
-                    n_batch: config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as i32,
-                    ..Default::default()
-                };
-                
-                // offload layers to gpu if enabled.
-                if config.use_gpu.unwrap_or(false) {
-                    if let Some(model_specific_layer_count) =  model_config.gpu_layer_count {
-                        model_params.n_gpu_layers = model_specific_layer_count as i32;
-                    } else if let Some(config_layer_count) = config.gpu_layer_count  {
-                        model_params.n_gpu_layers = config_layer_count as i32;
-                    }
-                }
-
-                llm_model = match LLama::new(local_model_path.clone(), &model_params) {
-                    Ok(m) => Some(m),
-                    Err(err) => panic!("Failed to load model from {local_model_path}: {err}"),
-                };
-            }
 
             // now load the embedding model
             #[cfg(feature = "sentence_similarity")]
             let embedding_engine = match &config.embedding_model {
-                Some(embedding_config) => Some(
-                    VectorEmbeddingEngine::new(&embedding_config)
-                        .unwrap_or_else(|err| panic!("Failed to load the embedding model: {err}")),
-                ),
+                Some(embedding_config) => {
+                    let embedding_engine = VectorEmbeddingEngine::new(&embedding_config)
+                        .unwrap_or_else(|err| panic!("Failed to load the embedding model: {err}"));
+                    log::info!(
+                        "Loaded the embedding model on the {}.",
+                        embedding_engine.get_device_name()
+                    );
+                    Some(embedding_engine)
+                }
                 None => None,
             };
 
             // setup a state object
             let mut engine_state = EngineState {
-                model: llm_model,
+                model: None,
                 model_config: model_config.clone(),
-                default_model_config: model_config,
+                default_model_config: model_config.clone(),
                 config,
 
                 #[cfg(feature = "sentence_similarity")]
                 embedding_engine: embedding_engine,
 
                 rng: rand::thread_rng(),
+
+                send_to_client,
+                recv_cmd_on_server,
+                cancel_requested: Arc::new(AtomicBool::new(false)),
+                deferred_commands: Arc::new(Mutex::new(Vec::new())),
+                kobold_genkey: None,
+                stream_text_fragments: false,
+                last_activity: Instant::now(),
+                warned_ignored_parameters: HashSet::new(),
+                warned_context_sizes: HashSet::new(),
+                instruct_template_overrides: HashMap::new(),
+                grammar: None,
+                model_load_failed: false,
             };
 
+            // if we're using a local model, load it up; there's nothing to do without it.
+            if let Err(err) = engine_state.load_model(model_config) {
+                log::error!("Failed to load the startup model: {:#}", err);
+                engine_state
+                    .send_to_client
+                    .send(LlmEngineResponse::ModelLoadFailed(format!("{:#}", err)))
+                    .expect("Failed to report the initial model load failure.");
+                return;
+            }
+
             // tell the main thread that we've loaded.
-            send_to_client
+            engine_state
+                .send_to_client
                 .send(LlmEngineResponse::ModelLoaded)
                 .expect("Failed to acknowledge initial model load sucess.");
+            engine_state.warn_about_context_size();
 
             loop {
-                // BLOCK UNTIL NEW REQUEST
+                // BLOCK UNTIL NEW REQUEST OR COMMAND, or until it's time to unload an idle model
                 let result;
-                let request = recv_on_server.recv().unwrap_or_else(|err| {
-                    panic!("LlmEngine thread's recv failed: {}", err);
-                });
+                let idle_timeout = engine_state
+                    .time_until_idle_unload()
+                    .unwrap_or(IDLE_UNLOAD_CHECK_INTERVAL);
+                let request = crossbeam::select! {
+                    recv(recv_on_server) -> msg => msg.unwrap_or_else(|err| {
+                        panic!("LlmEngine thread's recv failed: {}", err);
+                    }),
+                    recv(engine_state.recv_cmd_on_server) -> msg => {
+                        let cmd = msg.unwrap_or_else(|err| {
+                            panic!("LlmEngine thread's command recv failed: {}", err);
+                        });
+                        engine_state.process_command(cmd);
+                        engine_state.last_activity = Instant::now();
+                        continue;
+                    },
+                    default(idle_timeout) => {
+                        engine_state.unload_model_if_idle();
+                        continue;
+                    },
+                };
 
                 match request {
                     LlmEngineRequest::ImmediateShutdown => {
@@ -132,97 +265,75 @@ impl LlmEngine {
                     }
                     LlmEngineRequest::TextInference(context) => {
                         let mut new_context = context;
-
-                        let cfg_to_load = match &new_context.model_config_override {
-                            Some(model_config_ovr)
-                                if !engine_state.model_config.name.eq(model_config_ovr) =>
+                        engine_state.cancel_requested.store(false, Ordering::SeqCst);
+                        engine_state.stream_text_fragments = true;
+                        let new_text = engine_state.run_text_inference(&mut new_context);
+                        engine_state.stream_text_fragments = false;
+
+                        if engine_state.cancel_requested.load(Ordering::SeqCst) {
+                            log::debug!("Text inference request was cancelled.");
+                            result = LlmEngineResponse::TextInferenceCancelled(new_context);
+                        } else {
+                            result = LlmEngineResponse::NewText(new_text, new_context);
+                        }
+                    }
+                    LlmEngineRequest::TextInferenceCandidates(context, count) => {
+                        engine_state.cancel_requested.store(false, Ordering::SeqCst);
+
+                        // every candidate starts from the same context but gets its own seed
+                        let mut candidates = Vec::with_capacity(count);
+                        let mut last_context = context.clone();
+                        for i in 0..count {
+                            let mut new_context = context.clone();
+                            new_context.randomize_seed = true;
+                            if let Some(new_text) =
+                                engine_state.run_text_inference(&mut new_context)
                             {
-                                Some(model_config_ovr.to_owned())
+                                candidates.push(new_text);
                             }
-                            None if !engine_state
-                                .model_config
-                                .name
-                                .eq(&engine_state.default_model_config.name) =>
+                            last_context = new_context;
+                            if engine_state.cancel_requested.load(Ordering::SeqCst)
+                                || engine_state.model_load_failed
                             {
-                                Some(engine_state.default_model_config.name.to_owned())
-                            }
-                            _ => None,
-                        };
-                        // need to load up a different model
-                        if let Some(cfg_name) = cfg_to_load {
-                            // TODO: this is a dupe of above logic, mostly; refactor at some point
-                            // failures should have been detected before this gets here
-                            let model_config = engine_state.config
-                                .find_model_configuration(&cfg_name)
-                                .context("Attempting to find the model name provided in the configuration on text inferrence request")
-                                .unwrap();
-
-                            // free the model so we got memory to load the next one
-                            if let Some(model) = engine_state.model.as_mut() {
-                                model.free_model();
-                                engine_state.model = None;
-                            }
-                            engine_state.model_config = model_config.clone();
-                            log::debug!(
-                                "Loading a different model for configuration: {}",
-                                cfg_name
-                            );
-
-                            if let Some(local_model_path) = &model_config.path {
-                                // use a provided seed for the model or make a new one
-                                let this_seed = match model_config.seed {
-                                    Some(s) => s,
-                                    None => engine_state.rng.gen_range(0..i32::MAX),
-                                };
-
-                                let model_params = ModelOptions {
-                                    context_size: model_config.context_size as i32,
-                                    seed: this_seed,
-                                    n_gpu_layers: if engine_state.config.use_gpu.unwrap_or(false) {
-                                        model_config.gpu_layer_count.unwrap_or(0) as i32
-                                    } else {
-                                        0
-                                    },
-                                    n_batch: engine_state
-                                        .config
-                                        .batch_size
-                                        .unwrap_or(DEFAULT_BATCH_SIZE)
-                                        as i32,
-                                    ..Default::default()
-                                };
-
-                                engine_state.model =
-                                    match LLama::new(local_model_path.clone(), &model_params) {
-                                        Ok(m) => Some(m),
-                                        Err(err) => panic!(
-                                            "Failed to load model from {local_model_path}: {err}"
-                                        ),
-                                    };
+                                break;
                             }
+                            engine_state
+                                .send_response(LlmEngineResponse::CandidateProgress(i + 1, count));
                         }
 
-                        // if we have a local llm model loaded use that, otherwise try remote API config
-                        let new_text = if !engine_state.model_config.path.is_none() {
-                            engine_state.text_infer(&mut new_context)
+                        if engine_state.cancel_requested.load(Ordering::SeqCst) {
+                            log::debug!("Candidate text inference request was cancelled.");
+                            result = LlmEngineResponse::TextInferenceCancelled(last_context);
                         } else {
-                            engine_state.text_infer_kobold(&mut new_context)
-                        };
-                        result = LlmEngineResponse::NewText(new_text, new_context);
+                            result = LlmEngineResponse::NewCandidates(candidates, last_context);
+                        }
                     }
                 };
 
                 // SEND THE RESULT FROM THE SERVER
-                if let Err(err) = send_to_client.send(result) {
-                    log::error!("LlmEngine thread's send failed: {}", err);
+                engine_state.send_response(result);
+
+                // now run any commands that showed up while the request was being processed
+                let deferred: Vec<LlmEngineCommand> = engine_state
+                    .deferred_commands
+                    .lock()
+                    .expect("Failed to lock the deferred engine commands.")
+                    .drain(..)
+                    .collect();
+                for cmd in deferred {
+                    engine_state.process_command(cmd);
                 }
+                engine_state.last_activity = Instant::now();
                 log::trace!("One job-cycle complete in the llm engine thread.");
             }
         });
 
         return LlmEngine {
             send_to_server,
+            send_cmd_to_server,
             recv_on_client,
             handle: thread_handle,
+            startup_model_name,
         };
     }
 }
@@ -246,6 +357,23 @@ pub struct TextInferenceContext {
     pub should_continue: bool,
 
     pub parameters: ConfiguredParameters,
+
+    // a one-off instruction used to steer only this generation; it never gets added to the chatlog.
+    // placed at the <|nudge|> tag if the template has one, otherwise after the chat history.
+    pub nudge: Option<String>,
+
+    // if true, a fresh random seed is used for this generation even if the model configures one.
+    pub randomize_seed: bool,
+
+    // set by the engine to the chatlog items matched by sentence similarity for the prompt.
+    pub similar_matches: Option<Vec<SimilarSentenceMatch>>,
+
+    // if true, <|similar_sentences|> is left empty for this generation.
+    pub skip_similar_sentences: bool,
+
+    // set by the engine to the number of the oldest chatlog items left out of the prompt so
+    // that it fits in the context.
+    pub dropped_turns: usize,
 }
 
 struct EngineState {
@@ -267,49 +395,671 @@ struct EngineState {
 
     // our thread random generator
     rng: ThreadRng,
+
+    // the sending end of the response channel back to the client
+    send_to_client: Sender<LlmEngineResponse>,
+
+    // the receiving end of the command channel for the engine
+    recv_cmd_on_server: Receiver<LlmEngineCommand>,
+
+    // set when a CancelTextInference command is received while a request is running
+    cancel_requested: Arc<AtomicBool>,
+
+    // commands received while a request was running that need to be handled after it's done
+    deferred_commands: Arc<Mutex<Vec<LlmEngineCommand>>>,
+
+    // the genkey sent with the KoboldCpp generation that's in flight, used to abort it
+    kobold_genkey: Option<String>,
+
+    // set while running a single text inference request so that local generation sends each
+    // token to the client as a NewTextFragment; candidates are generated without it.
+    stream_text_fragments: bool,
+
+    // when the engine last finished some work; used to unload the model after sitting idle
+    last_activity: Instant,
+
+    // the (model configuration, parameter set) name pairs already warned about ignored settings
+    warned_ignored_parameters: HashSet<(String, String)>,
+
+    // the model configuration names already warned about a context size the model wasn't trained for
+    warned_context_sizes: HashSet<String>,
+
+    // the GBNF grammar of the active model configuration, read when it was loaded
+    grammar: Option<String>,
+
+    // set when the last attempt to switch models failed, so that a request making several
+    // inferences can stop instead of trying the load again for each one
+    model_load_failed: bool,
+
+    // instruct templates set with SetInstructTemplate, by model configuration name, that
+    // replace the configured ones whenever that model configuration gets loaded
+    instruct_template_overrides: HashMap<String, String>,
 }
 impl EngineState {
-    // given the string a user inputs, turn that into the whole
-    // prompt that is given to the engine
-    fn create_prompt_for_chat_input(&self, context: &mut TextInferenceContext) -> String {
-        // and then create the system message with the context for the bot
-        let mut buf = String::new();
-        buf.push_str(self.model_config.prompt_instruct_template.as_str());
+    // frees any loaded model and makes the model configuration passed in the active one,
+    // loading the model file up if it's a locally hosted model. if the model file fails to
+    // load, the configuration stays active without a model and the error is returned.
+    fn load_model(&mut self, model_config: ConfiguredLlm) -> anyhow::Result<()> {
+        // free the model so we got memory to load the next one
+        if let Some(model) = self.model.as_mut() {
+            model.free_model();
+            self.model = None;
+        }
+        log::debug!("Loading the model for configuration: {}", model_config.name);
+        self.model_config = model_config.clone();
+        if let Some(template) = self.instruct_template_overrides.get(&model_config.name) {
+            self.model_config.prompt_instruct_template = template.clone();
+        }
+        self.grammar = match model_config.load_grammar() {
+            Ok(grammar) => grammar,
+            Err(err) => {
+                log::error!(
+                    "Ignoring the grammar for model '{}': {:#}",
+                    model_config.name,
+                    err
+                );
+                None
+            }
+        };
+
+        if let Some(local_model_path) = &model_config.path {
+            // use a provided seed for the model or make a new one
+            let this_seed = match model_config.seed {
+                Some(s) => s,
+                None => self.rng.gen_range(0..i32::MAX),
+            };
+
+            let mut model_params = ModelOptions {
+                context_size: model_config.context_size as i32,
+                seed: this_seed,
+                n_gpu_layers: 0,
+                n_batch: self.config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as i32,
+                ..Default::default()
+            };
+
+            // offload layers to gpu if enabled.
+            if self.config.use_gpu.unwrap_or(false) {
+                if let Some(model_specific_layer_count) = model_config.gpu_layer_count {
+                    model_params.n_gpu_layers = model_specific_layer_count as i32;
+                } else if let Some(config_layer_count) = self.config.gpu_layer_count {
+                    model_params.n_gpu_layers = config_layer_count as i32;
+                }
+
+                // pick the gpu, or split the model across several, if configured
+                match model_config.validate_gpu_devices() {
+                    Ok(()) => {
+                        if let Some(index) = model_config.gpu_device_index {
+                            model_params.main_gpu = index.to_string();
+                        }
+                        if let Some(split) = &model_config.gpu_tensor_split {
+                            model_params.tensor_split = split
+                                .iter()
+                                .map(|s| s.to_string())
+                                .collect::<Vec<_>>()
+                                .join(",");
+                        }
+                    }
+                    Err(problem) => {
+                        log::warn!(
+                            "Ignoring the gpu device settings for model '{}': {}",
+                            model_config.name,
+                            problem
+                        );
+                    }
+                }
+            }
+
+            self.model = match LLama::new(local_model_path.clone(), &model_params) {
+                Ok(m) => Some(m),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Failed to load model from {local_model_path}: {err}"
+                    ))
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    // picks up the active and default model configurations from a reloaded configuration.
+    // settings like the templates apply right away, but if the ones the local model was loaded
+    // with changed, it's unloaded so the next request loads it again with the new ones.
+    fn refresh_model_configs(&mut self) {
+        if let Some(model_config) = self
+            .config
+            .find_model_configuration(&self.default_model_config.name)
+        {
+            self.default_model_config = model_config;
+        }
+        let Some(model_config) = self
+            .config
+            .find_model_configuration(&self.model_config.name)
+        else {
+            log::warn!(
+                "The model configuration '{}' isn't in the reloaded configuration; it stays in use as it was.",
+                self.model_config.name
+            );
+            return;
+        };
+
+        let needs_reload = model_config.path != self.model_config.path
+            || model_config.context_size != self.model_config.context_size
+            || model_config.gpu_layer_count != self.model_config.gpu_layer_count
+            || model_config.gpu_device_index != self.model_config.gpu_device_index
+            || model_config.gpu_tensor_split != self.model_config.gpu_tensor_split
+            || model_config.seed != self.model_config.seed;
+        if needs_reload {
+            if let Some(model) = self.model.as_mut() {
+                log::info!(
+                    "Unloading the model for configuration {} so that it's loaded again with the reloaded settings.",
+                    self.model_config.name
+                );
+                model.free_model();
+                self.model = None;
+            }
+        }
+
+        self.model_config = model_config;
+        if let Some(template) = self
+            .instruct_template_overrides
+            .get(&self.model_config.name)
+        {
+            self.model_config.prompt_instruct_template = template.clone();
+        }
+        self.grammar = match self.model_config.load_grammar() {
+            Ok(grammar) => grammar,
+            Err(err) => {
+                log::error!(
+                    "Ignoring the grammar for model '{}': {:#}",
+                    self.model_config.name,
+                    err
+                );
+                None
+            }
+        };
+    }
+
+    // loads a different model while the client is waiting on the engine, letting it know
+    // with a ModelLoading response before the load starts and ModelLoaded once it's done,
+    // or ModelLoadFailed if it couldn't be loaded. returns true if the model loaded.
+    fn switch_model(&mut self, model_config: ConfiguredLlm) -> bool {
+        let switched = !self.model_config.name.eq(&model_config.name);
+        self.send_response(LlmEngineResponse::ModelLoading(model_config.name.clone()));
+        if let Err(err) = self.load_model(model_config) {
+            log::error!("Failed to switch models: {:#}", err);
+            self.model_load_failed = true;
+            self.send_response(LlmEngineResponse::ModelLoadFailed(format!("{:#}", err)));
+            return false;
+        }
+        self.send_response(LlmEngineResponse::ModelLoaded);
+        if switched {
+            self.send_response(LlmEngineResponse::ModelSwitched(
+                self.model_config.name.clone(),
+            ));
+        }
+        self.warn_about_context_size();
+        true
+    }
+
+    // returns how much longer the engine can sit idle before the local model gets unloaded,
+    // or None if there's no model loaded or idle unloading isn't configured.
+    fn time_until_idle_unload(&self) -> Option<Duration> {
+        let idle_seconds = self.config.idle_unload_seconds.filter(|s| *s > 0)?;
+        self.model.as_ref()?;
+        Some(Duration::from_secs(idle_seconds).saturating_sub(self.last_activity.elapsed()))
+    }
+
+    // frees the local model if the engine has been idle for longer than configured. the model
+    // configuration stays active so the model gets loaded again for the next request.
+    fn unload_model_if_idle(&mut self) {
+        if self.time_until_idle_unload() == Some(Duration::ZERO) {
+            if let Some(model) = self.model.as_mut() {
+                log::info!(
+                    "Unloading the model for configuration {} after being idle.",
+                    self.model_config.name
+                );
+                model.free_model();
+                self.model = None;
+            }
+        }
+    }
+
+    // sends a response back to the client, logging any failure.
+    fn send_response(&self, response: LlmEngineResponse) {
+        if let Err(err) = self.send_to_client.send(response) {
+            log::error!("LlmEngine thread's send failed: {}", err);
+        }
+    }
 
-        // order of operations is important here so that the names are replaced last.
-        buf = buf.replace("<|character_description|>", &context.character.description);
-        buf = buf.replace("<|current_context|>", &context.chatlog.current_context);
-        if let Some(user_desc) = &context.chatlog.user_description {
-            buf = buf.replace("<|user_description|>", user_desc);
+    // runs a command sent on the command channel while no request is in flight and
+    // sends any response back to the client.
+    fn process_command(&mut self, cmd: LlmEngineCommand) {
+        match cmd {
+            LlmEngineCommand::CancelTextInference => {
+                log::debug!("Ignoring a cancel command since no text inference is running.");
+            }
+            LlmEngineCommand::PreviewPrompt(mut context) => {
+                let prompt = self.create_prompt_for_chat_input(&mut context);
+                self.send_response(LlmEngineResponse::PromptPreview(prompt));
+            }
+            LlmEngineCommand::EstimatePrompt(mut context) => {
+                // the similar sentences would need the embeddings built for the whole log
+                // each time, so they're left out of the estimate
+                context.skip_similar_sentences = true;
+                let prompt = self.create_prompt_for_chat_input(&mut context);
+                let text2token_ratio = self
+                    .config
+                    .text_to_token_ratio_prediction
+                    .unwrap_or(DEFAULT_TEXT_TO_TOKEN_RATIO);
+                // the history is added from the newest item back, so the items left out to
+                // fit are the oldest ones
+                let left_out_len: usize = context
+                    .chatlog
+                    .iter()
+                    .take(context.dropped_turns)
+                    .map(|item| {
+                        self.format_history_turn(item).len() + self.get_turn_separator().len()
+                    })
+                    .sum();
+                self.send_response(LlmEngineResponse::PromptEstimate(
+                    (prompt.len() as f32 / text2token_ratio) as usize,
+                    ((prompt.len() + left_out_len) as f32 / text2token_ratio) as usize,
+                    self.model_config.context_size,
+                    context.dropped_turns,
+                ));
+            }
+            LlmEngineCommand::ReloadConfig(new_config) => {
+                self.send_response(LlmEngineResponse::EngineStatus(Some(
+                    "Reloading the configuration...".to_owned(),
+                )));
+                self.config = new_config;
+                self.refresh_model_configs();
+                self.send_response(LlmEngineResponse::ConfigReloaded);
+            }
+            LlmEngineCommand::GetInstructTemplate => {
+                self.send_response(LlmEngineResponse::InstructTemplate(
+                    self.model_config.name.clone(),
+                    self.model_config.prompt_instruct_template.clone(),
+                ));
+            }
+            LlmEngineCommand::SetInstructTemplate(template) => {
+                log::info!(
+                    "Using an edited instruct template for the model configuration '{}' until restarted.",
+                    self.model_config.name
+                );
+                self.instruct_template_overrides
+                    .insert(self.model_config.name.clone(), template.clone());
+                self.model_config.prompt_instruct_template = template;
+            }
         }
+    }
+
+    // checks the command channel without blocking while a request is being worked on.
+    // a cancel gets flagged right away and everything else is saved for later.
+    fn poll_commands_during_request(&self) {
+        poll_command_channel(
+            &self.recv_cmd_on_server,
+            &self.cancel_requested,
+            &self.deferred_commands,
+        );
+    }
 
-        // test to see if this template wants the vector embedding support as well
+    // switches to the model the context asks for, if it isn't already loaded, and then generates
+    // the text for it. the command channel gets checked around the work so a cancel can be
+    // honored; the caller should check `cancel_requested` afterwards.
+    fn run_text_inference(&mut self, context: &mut TextInferenceContext) -> Option<String> {
+        self.model_load_failed = false;
+        let cfg_to_load = match &context.model_config_override {
+            Some(model_config_ovr) if !self.model_config.name.eq(model_config_ovr) => {
+                Some(model_config_ovr.to_owned())
+            }
+            None if !self.model_config.name.eq(&self.default_model_config.name) => {
+                Some(self.default_model_config.name.to_owned())
+            }
+            _ => None,
+        };
+        // need to load up a different model; if that fails there's nothing to generate with
+        if let Some(cfg_name) = cfg_to_load {
+            let Some(model_config) = self.config.find_model_configuration(&cfg_name) else {
+                log::error!(
+                    "Unable to load model '{}' since it isn't in the configuration.",
+                    cfg_name
+                );
+                self.model_load_failed = true;
+                self.send_response(LlmEngineResponse::ModelLoadFailed(format!(
+                    "The model configuration '{}' isn't in the configuration file.",
+                    cfg_name
+                )));
+                return None;
+            };
+            if !self.switch_model(model_config) {
+                return None;
+            }
+        } else if self.model.is_none() && self.model_config.path.is_some() {
+            // the model was unloaded after sitting idle, or failed to load, so bring it back
+            if !self.switch_model(self.model_config.clone()) {
+                return None;
+            }
+        }
+
+        self.warn_about_ignored_parameters(&context.parameters);
+
+        // if we have a local llm model loaded use that, otherwise try remote API config
+        self.poll_commands_during_request();
+        let new_text = if self.cancel_requested.load(Ordering::SeqCst) {
+            None
+        } else {
+            match InferenceBackend::for_model(&self.model_config) {
+                InferenceBackend::LlamaCpp => self.text_infer(context),
+                InferenceBackend::KoboldCpp => self.text_infer_kobold(context),
+                InferenceBackend::OpenAi => self.text_infer_openai(context),
+            }
+        };
+        self.poll_commands_during_request();
+
+        new_text
+    }
+
+    // tells the client which settings of the parameter set the active backend won't use, but
+    // only the first time each parameter set is used with each model configuration.
+    fn warn_about_ignored_parameters(&mut self, parameters: &ConfiguredParameters) {
+        let backend = InferenceBackend::for_model(&self.model_config);
+        let ignored = get_ignored_parameters(parameters, backend);
+        if ignored.is_empty() {
+            return;
+        }
+
+        let key = (self.model_config.name.clone(), parameters.name.clone());
+        if !self.warned_ignored_parameters.insert(key) {
+            return;
+        }
+
+        let message = format!(
+            "The '{}' parameter set has settings that the '{}' model ({}) won't use:\n\n{}",
+            parameters.name,
+            self.model_config.name,
+            backend.name(),
+            ignored.join("\n")
+        );
+        log::warn!("{}", message);
+        self.send_response(LlmEngineResponse::IgnoredParameters(message));
+    }
+
+    // tells the client when the active model configuration's context size is bigger than the
+    // context length the local model file was trained for, but only once for each configuration.
+    // the length is read from the gguf file's metadata since llama.cpp doesn't hand it back.
+    fn warn_about_context_size(&mut self) {
+        let Some(model_path) = &self.model_config.path else {
+            return;
+        };
+        if self.warned_context_sizes.contains(&self.model_config.name) {
+            return;
+        }
+
+        let trained_size = match read_gguf_context_length(model_path) {
+            Ok(Some(size)) => size,
+            Ok(None) => {
+                log::debug!(
+                    "No trained context length was found in the metadata of {}",
+                    model_path
+                );
+                return;
+            }
+            Err(err) => {
+                log::debug!(
+                    "Unable to read the trained context length from {}: {}",
+                    model_path,
+                    err
+                );
+                return;
+            }
+        };
+        self.warned_context_sizes
+            .insert(self.model_config.name.clone());
+        if self.model_config.context_size <= trained_size {
+            return;
+        }
+
+        let message = format!(
+            "The '{}' model configuration asks for a context size of {} tokens, but the model was trained for {} tokens. Responses may get noticeably worse once the chat grows past that, so consider lowering `context_size`.",
+            self.model_config.name, self.model_config.context_size, trained_size
+        );
+        log::warn!("{}", message);
+        self.send_response(LlmEngineResponse::ContextSizeWarning(message));
+    }
+
+    // returns the separator to put between the turns of the chat history in the prompt.
+    fn get_turn_separator(&self) -> &str {
+        self.model_config.turn_separator.as_deref().unwrap_or("\n")
+    }
+
+    // returns the chatlog item as it goes into the chat history of the prompt, wrapped in
+    // the model's `turn_prefix` and `turn_suffix` if it has them.
+    fn format_history_turn(&self, item: &ChatLogItem) -> String {
+        format!(
+            "{}{}{}",
+            self.model_config.turn_prefix.as_deref().unwrap_or_default(),
+            item.get_name_and_items_as_string(),
+            self.model_config.turn_suffix.as_deref().unwrap_or_default()
+        )
+    }
+
+    // returns the configured maximum number of new tokens, limited so that it can't crowd
+    // the prompt out of the active model's context.
+    fn get_max_new_tokens(&self) -> usize {
+        let requested = self
+            .config
+            .maximum_new_tokens
+            .unwrap_or(DEFAULT_MAX_NEW_TOKENS);
+        let limit = max_new_tokens_limit(self.model_config.context_size);
+        if requested > limit {
+            log::warn!(
+                "maximum_new_tokens ({}) is too large for the {} token context of model '{}'; limiting it to {}.",
+                requested,
+                self.model_config.context_size,
+                self.model_config.name,
+                limit
+            );
+            limit
+        } else {
+            requested
+        }
+    }
+
+    // finds the chatlog items most similar to the last one, with vector embeddings, and
+    // returns them as the text for the <|similar_sentences|> tag.
+    #[cfg(feature = "sentence_similarity")]
+    fn find_similar_sentences(&self, context: &mut TextInferenceContext) -> String {
         // only works with non-empty chat logs.
-        #[cfg(feature = "sentence_similarity")]
-        if buf.contains("<|similar_sentences|>") && context.chatlog.len() > 0 {
-            if let Some(embedding_engine) = &self.embedding_engine {
-                // make sure all the chat log has their embeddings calculated
+        if context.chatlog.len() == 0 {
+            return String::new();
+        }
+
+        if let Some(embedding_engine) = &self.embedding_engine {
+            // make sure all the chat log has their embeddings calculated, letting the client
+            // know when there's a batch of them to build, such as for a freshly opened log
+            let mut missing_count = context
+                .chatlog
+                .iter()
+                .filter(|item| item.embeddings.is_empty())
+                .count();
+            if missing_count > 0 {
+                missing_count -= embedding_engine.load_cached_embeddings(&mut context.chatlog);
+            }
+            if missing_count > 1 {
+                self.send_response(LlmEngineResponse::EngineStatus(Some(format!(
+                    "Building sentence embeddings for {} chatlog items...",
+                    missing_count
+                ))));
+            }
+            let built_count =
                 embedding_engine.build_all_vector_embeddings(&mut context.chatlog, false);
+            if missing_count > 1 {
+                self.send_response(LlmEngineResponse::EngineStatus(None));
+            }
+            if built_count > 0 {
+                embedding_engine.save_cached_embeddings(&context.chatlog);
+            }
+
+            let requested_match_count = self
+                .model_config
+                .similar_sentence_count
+                .unwrap_or(DEFAULT_NUM_OF_SENTENCE_MATCHES);
+            let end_offset = if context.should_continue { 1 } else { 0 };
+            let mut matches = embedding_engine.get_sentence_similarity_for_last(
+                &context.chatlog,
+                end_offset,
+                requested_match_count,
+            );
 
-                let requested_match_count = self
-                    .model_config
-                    .similar_sentence_count
-                    .unwrap_or(DEFAULT_NUM_OF_SENTENCE_MATCHES);
-                let end_offset = if context.should_continue { 1 } else { 0 };
-                let matches = embedding_engine.get_sentence_similarity_for_last(
-                    &context.chatlog,
-                    end_offset,
-                    requested_match_count,
+            // drop weak matches so unrelated chatlog items don't end up in the prompt
+            if let Some(threshold) = self.model_config.similarity_threshold {
+                let match_count = matches.len();
+                matches.retain(|m| m.1 >= threshold);
+                log::debug!(
+                    "Filtered out {} of {} similar sentence matches scoring below {:.2}",
+                    match_count - matches.len(),
+                    match_count,
+                    threshold
                 );
-                let matched_strings: Vec<String> = matches.iter().map(|m| m.2.to_owned()).collect();
-                let joined_matches = matched_strings.join("\n");
-                buf = buf.replace("<|similar_sentences|>", joined_matches.as_str());
-            } else {
-                log::warn!("The LLM prompt includes <|similar_sentences|> but an embedding model wasn't configured, so it's being skipped.");
-                buf = buf.replace("<|similar_sentences|>", "");
             }
+            let matched_strings: Vec<String> = matches.iter().map(|m| m.2.to_owned()).collect();
+            context.similar_matches = Some(
+                matches
+                    .iter()
+                    .map(|m| SimilarSentenceMatch {
+                        index: m.0,
+                        score: m.1,
+                    })
+                    .collect(),
+            );
+            matched_strings.join("\n")
+        } else {
+            log::warn!("The LLM prompt includes <|similar_sentences|> but an embedding model wasn't configured, so it's being skipped.");
+            String::new()
+        }
+    }
+
+    #[cfg(not(feature = "sentence_similarity"))]
+    fn find_similar_sentences(&self, _context: &mut TextInferenceContext) -> String {
+        log::warn!("The LLM prompt includes <|similar_sentences|> but the sentence_similarity feature isn't enabled, so it's being skipped.");
+        String::new()
+    }
+
+    // builds the name and description of everyone in the chat besides the character generating
+    // the response. descriptions are added until they'd take up more than
+    // PARTICIPANTS_DESCRIPTIONS_MAX_PERCENT of the prompt, so the chat history keeps its room.
+    fn build_participants_descriptions(&self, context: &TextInferenceContext) -> String {
+        if !self
+            .config
+            .include_participants_descriptions
+            .unwrap_or(true)
+        {
+            return String::new();
+        }
+
+        let text2token_ratio: f32 = self
+            .config
+            .text_to_token_ratio_prediction
+            .unwrap_or(DEFAULT_TEXT_TO_TOKEN_RATIO);
+        let available_tokens = self
+            .model_config
+            .context_size
+            .saturating_sub(self.get_max_new_tokens());
+        let limit = (available_tokens as f32 * text2token_ratio) as usize
+            * PARTICIPANTS_DESCRIPTIONS_MAX_PERCENT
+            / 100;
+
+        let mut descriptions = String::new();
+        let others = std::iter::once(&context.chatlog_owner)
+            .chain(context.other_participants.iter().map(|(c, _)| c))
+            .filter(|c| c.name != context.character.name);
+        for other in others {
+            let description =
+                other.process_string_templates(&self.config.display_name, other.description.trim());
+            let entry = format!("{}: {}\n", other.name, description);
+            if descriptions.len() + entry.len() > limit {
+                log::info!(
+                    "Leaving the description of {} out of <|participants_descriptions|> to keep room for the chat history.",
+                    other.name
+                );
+                break;
+            }
+            descriptions.push_str(&entry);
         }
+        descriptions.trim_end().to_owned()
+    }
+
+    // given the string a user inputs, turn that into the whole
+    // prompt that is given to the engine
+    fn create_prompt_for_chat_input(&self, context: &mut TextInferenceContext) -> String {
+        let template = self.model_config.prompt_instruct_template.as_str();
+
+        // the similar sentences can be left out, such as to check if they're behind a bad response
+        let similar_sentences = if !template.contains("<|similar_sentences|>") {
+            String::new()
+        } else if context.skip_similar_sentences {
+            log::debug!("Leaving <|similar_sentences|> out of the prompt as requested.");
+            String::new()
+        } else {
+            self.find_similar_sentences(context)
+        };
 
+        let participants_descriptions = if template.contains("<|participants_descriptions|>") {
+            self.build_participants_descriptions(context)
+        } else {
+            String::new()
+        };
+
+        // put any one-off instruction in the template's spot for it, or hold onto it so it can
+        // follow the chat history instead.
+        let nudge_text = context
+            .nudge
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| format!("[Instruction for the next reply: {}]", n));
+        let mut trailing_nudge = String::new();
+        if !template.contains("<|nudge|>") {
+            if let Some(nudge) = nudge_text.as_deref() {
+                trailing_nudge = format!("\n{}", nudge);
+            }
+        }
+
+        // every section goes wherever its tag is in the template, so the template alone controls
+        // the layout. the chat history is filled in last since it has to fit the space left over.
+        let sections = [
+            (
+                "<|character_description|>",
+                context.character.description.as_str(),
+            ),
+            (
+                "<|current_context|>",
+                context.chatlog.current_context.as_str(),
+            ),
+            (
+                "<|character_context|>",
+                context.chatlog.current_context.as_str(),
+            ),
+            (
+                "<|user_description|>",
+                context
+                    .chatlog
+                    .user_description
+                    .as_deref()
+                    .unwrap_or_default(),
+            ),
+            ("<|similar_sentences|>", similar_sentences.as_str()),
+            (
+                "<|participants_descriptions|>",
+                participants_descriptions.as_str(),
+            ),
+            ("<|nudge|>", nudge_text.as_deref().unwrap_or_default()),
+        ];
+        let mut buf = fill_prompt_sections(template, &sections);
+
+        // the names are replaced last so that they can be used inside the sections too.
         buf = buf.replace("<|character_name|>", &context.character.name);
         buf = buf.replace("<|user_name|>", &self.config.display_name);
 
@@ -327,16 +1077,28 @@ impl EngineState {
             .unwrap_or(DEFAULT_TEXT_TO_TOKEN_RATIO);
 
         // pull the requested max new token count from the configuration
-        let token_count = self
+        let token_count = self.get_max_new_tokens();
+
+        // reserve a slice of the context as headroom so that errors in the text-to-token
+        // estimate don't push the real prompt past the context size.
+        let headroom_percent = self
             .config
-            .maximum_new_tokens
-            .unwrap_or(DEFAULT_MAX_NEW_TOKENS);
+            .context_headroom_percent
+            .unwrap_or(DEFAULT_CONTEXT_HEADROOM_PERCENT)
+            .clamp(0.0, 100.0);
+        let headroom_tokens =
+            (self.model_config.context_size as f32 * headroom_percent / 100.0) as usize;
 
         // figure out our remaining token budget in text characters and build a history log based on that.
-        let prompt_limit: usize = ((self.model_config.context_size - token_count) as f32
-            * text2token_ratio) as usize
-            - buf.len();
-        for conv_turn in context.chatlog.iter().rev() {
+        let available_tokens = self.model_config.context_size.saturating_sub(token_count);
+        let unreserved_limit: usize = ((available_tokens as f32 * text2token_ratio) as usize)
+            .saturating_sub(buf.len() + trailing_nudge.len());
+        let prompt_limit: usize = ((available_tokens.saturating_sub(headroom_tokens) as f32
+            * text2token_ratio) as usize)
+            .saturating_sub(buf.len() + trailing_nudge.len());
+        let turn_separator = self.get_turn_separator();
+        let mut dropped_turns = 0;
+        for (turn_index, conv_turn) in context.chatlog.iter().rev().enumerate() {
             let turn_str = conv_turn.get_name_and_items_as_string();
 
             // if we're continuing a response and haven't pulled the log item to continue
@@ -349,15 +1111,40 @@ impl EngineState {
                     continue_line = turn_str.to_owned();
                 }
             } else {
-                let new_history = format!("{}\n{}", turn_str, history_log);
+                // the continued item above is left unwrapped, since the template already
+                // starts the response it gets appended to
+                let new_history = format!(
+                    "{}{}{}",
+                    self.format_history_turn(conv_turn),
+                    turn_separator,
+                    history_log
+                );
                 if new_history.len() + continue_line.len() >= prompt_limit {
+                    if new_history.len() + continue_line.len() < unreserved_limit {
+                        log::info!(
+                            "The context headroom of {} tokens ({}%) kept older chat history out of the prompt.",
+                            headroom_tokens,
+                            headroom_percent
+                        );
+                    }
+                    dropped_turns = context.chatlog.len() - turn_index;
+                    log::debug!(
+                        "Left the oldest {} chatlog items out of the prompt to fit the context.",
+                        dropped_turns
+                    );
                     break;
                 }
                 history_log = new_history;
             }
         }
 
-        buf = buf.replace("<|chat_history|>", history_log.trim_end());
+        context.dropped_turns = dropped_turns;
+
+        let history_log = history_log
+            .strip_suffix(turn_separator)
+            .unwrap_or(history_log.as_str());
+        let history_log = format!("{}{}", history_log.trim_end(), trailing_nudge);
+        buf = buf.replace("<|chat_history|>", history_log.as_str());
 
         // This theoretically should be the last thing added since it's the line getting continued
         if !continue_line.is_empty() {
@@ -378,37 +1165,32 @@ impl EngineState {
             let _ = raw_file.write_all(prompt.as_bytes());
         }
 
-        // Use a default 120 minute timeout, unless configured otherwise
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(
-                self.model_config.remote_timeout_s.unwrap_or(60 * 120),
-            ))
-            .build()
-            .expect("Failed to create the blocking reqwest client for KoboldAPI.");
+        let client = self.build_remote_client("KoboldAPI")?;
 
         // If not supplied we try to use the localhost
         let api_host = match self.model_config.remote_server.as_ref() {
-            Some(s) => s,
+            Some(s) => s.to_owned(),
             None => {
                 log::warn!("KoboldAPI: currently selected model didn't specify 'remote_server'; defaulting to 'http://localhost:5001'");
-                "http://localhost:5001"
+                "http://localhost:5001".to_owned()
             }
         };
 
-        // build an array of character names to stop on for everyone
-        let mut stop_seqs = vec![format!("{}: ", self.config.display_name)];
-        stop_seqs.push(format!("{}: ", context.chatlog_owner.name));
-        if !context.other_participants.is_empty() {
-            for other in &context.other_participants {
-                stop_seqs.push(format!("{}: ", other.0.name));
-            }
-        }
+        let stop_seqs = self.build_stop_sequences(context);
+
+        // a unique key for this generation so that an abort only stops this request,
+        // even if other clients are using the same server
+        let genkey = format!("KCPP{:010}", self.rng.gen_range(0..u32::MAX));
+        self.kobold_genkey = Some(genkey.clone());
 
         let textgen_url = format!("{}{}", api_host, "/api/v1/generate");
         let textgen_request = TextgenRemoteRequestKobold {
             prompt,
             max_context_length: Some(self.model_config.context_size),
-            max_length: self.config.maximum_new_tokens,
+            max_length: self
+                .config
+                .maximum_new_tokens
+                .map(|_| self.get_max_new_tokens()),
             temperature: context.parameters.temperature,
             top_k: context.parameters.top_k,
             top_p: context.parameters.top_p,
@@ -416,52 +1198,427 @@ impl EngineState {
             rep_pen: context.parameters.repeat_penalty,
             rep_pen_range: context.parameters.repeat_penalty_range,
             typical: None,
-            sampler_seed: None,
+            sampler_seed: if context.randomize_seed {
+                Some(self.rng.gen_range(0..i64::from(i32::MAX)))
+            } else {
+                None
+            },
             mirostat: context.parameters.mirostat,
             mirostat_eta: context.parameters.mirostat_eta,
             mirostat_tau: context.parameters.mirostat_tau,
+            genkey: Some(genkey),
+            grammar: self.grammar.clone(),
             trim_stop: Some(true),
-            stop_sequence: if self.config.stop_on_display_name {
-                Some(stop_seqs)
-            } else {
+            stop_sequence: if stop_seqs.is_empty() {
                 None
+            } else {
+                Some(stop_seqs.clone())
             },
         };
 
-        // serialize the request to JSON and send it to the server; blocking because this is all
-        // done on a separate thread from the UI anyways, and that usage pattern mirrors how
-        // locally hosted generation works.
-        let textgen_request_json = serde_json::to_string(&textgen_request).expect(
-            "Failed to serialize the KoboldAPI parameters for the text generation request.",
-        );
-        let textgen_resp = client
-            .post(&textgen_url)
-            .body(textgen_request_json)
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .header(reqwest::header::ACCEPT, "application/json")
-            .send()
-            .expect("KoboldAPI call failed for generating text from a prompt");
-        if textgen_resp.status() != reqwest::StatusCode::OK {
-            log::error!(
-                "KoboldAPI: Failed to generate text for the given prompt. Status: {}",
-                textgen_resp.status()
-            );
-            return None;
+        // serialize the request to JSON to send to the server
+        let textgen_request_json = match serde_json::to_string(&textgen_request) {
+            Ok(json) => json,
+            Err(err) => {
+                log::error!(
+                    "KoboldAPI: Failed to serialize the text generation request: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        // stream the generation if configured, falling back to the blocking endpoint if the
+        // stream can't be read; a cancelled stream keeps whatever text arrived before it.
+        let mut streamed_text = None;
+        if self.model_config.remote_stream.unwrap_or(false) {
+            let stream_url = format!("{}{}", api_host, "/api/extra/generate/stream");
+            match self.stream_kobold_request(&client, &api_host, &stream_url, &textgen_request_json)
+            {
+                Ok(text) => streamed_text = Some(text),
+                Err(err) => {
+                    log::warn!(
+                        "KoboldAPI: Streaming the generation failed, so the blocking endpoint will be used: {}",
+                        err
+                    );
+                }
+            }
         }
+        let generated_text = match streamed_text {
+            Some(text) => Some(text),
+            None if self.cancel_requested.load(Ordering::SeqCst) => None,
+            None => {
+                self.generate_kobold_text(&client, &api_host, &textgen_url, &textgen_request_json)
+            }
+        };
+        self.kobold_genkey = None;
+        let mut inferred_string = generated_text?;
 
-        let textgen_resp_text = textgen_resp
-            .text()
-            .expect("KoboldAPI: Failed to get the JSON from the text generation response body.");
-        let textgen_resp: TextgenResponseBodyKobold = serde_json::from_str(&textgen_resp_text)
-            .expect(
-                "KoboldAPI: Failed to deserialize the JSON from the text generation response body.",
+        // DEBUG WRITE OUT THE PROMPT TO A FILE.
+        #[cfg(debug_assertions)]
+        {
+            let mut raw_file = File::create(".debug.result.txt").unwrap();
+            let _ = raw_file.write_all(inferred_string.as_bytes());
+        }
+
+        // the server should have stopped already, but trim here too in case it didn't.
+        truncate_at_stop_sequences(&stop_seqs, &mut inferred_string);
+
+        Some(inferred_string)
+    }
+
+    // sends the text generation request to the KoboldAPI's blocking endpoint, trying again
+    // after connection errors or error statuses if retries are configured, and returns the
+    // generated text.
+    fn generate_kobold_text(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        api_host: &str,
+        textgen_url: &str,
+        textgen_request_json: &str,
+    ) -> Option<String> {
+        let retry_count = self.model_config.remote_retry_count.unwrap_or(0);
+        let mut attempt = 0;
+        let textgen_resp = loop {
+            let result =
+                self.send_kobold_request(client, api_host, textgen_url, textgen_request_json);
+            match result {
+                Some(Ok(resp)) if resp.status() == reqwest::StatusCode::OK => break resp,
+                Some(Ok(resp)) => log::error!(
+                    "KoboldAPI: Failed to generate text for the given prompt. Status: {}",
+                    resp.status()
+                ),
+                Some(Err(err)) => {
+                    log::error!("KoboldAPI: The text generation request failed: {}", err)
+                }
+                None => log::error!("KoboldAPI: The text generation request thread panicked."),
+            }
+
+            // a cancelled generation isn't worth trying again
+            if attempt >= retry_count || self.cancel_requested.load(Ordering::SeqCst) {
+                return None;
+            }
+            let delay_ms = REMOTE_RETRY_BASE_DELAY_MS
+                .saturating_mul(1 << attempt.min(16))
+                .min(REMOTE_RETRY_MAX_DELAY_MS);
+            attempt += 1;
+            log::warn!(
+                "KoboldAPI: Retrying the request in {}ms (retry {} of {}).",
+                delay_ms,
+                attempt,
+                retry_count
             );
+            if !self.wait_before_retry(Duration::from_millis(delay_ms)) {
+                return None;
+            }
+        };
+
+        let textgen_resp_text = match textgen_resp.text() {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!(
+                    "KoboldAPI: Failed to get the JSON from the text generation response body: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        let textgen_resp: TextgenResponseBodyKobold = match serde_json::from_str(&textgen_resp_text)
+        {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                log::error!(
+                    "KoboldAPI: Failed to deserialize the JSON from the text generation response body: {}",
+                    err
+                );
+                return None;
+            }
+        };
         if textgen_resp.results.is_empty() {
             log::error!("KoboldAPI: Failed to generate text for the given prompt. Empty result was returned.");
             return None;
         }
 
-        let mut inferred_string = textgen_resp.results[0].text.clone();
+        Some(textgen_resp.results[0].text.clone())
+    }
+
+    // posts one text generation request to the KoboldAPI server. the blocking call is made on
+    // a helper thread so that this one can keep checking for a cancel while it waits. returns
+    // None if the helper thread panicked.
+    fn send_kobold_request(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        api_host: &str,
+        textgen_url: &str,
+        textgen_request_json: &str,
+    ) -> Option<reqwest::Result<reqwest::blocking::Response>> {
+        let send_client = client.clone();
+        let textgen_url = textgen_url.to_owned();
+        let textgen_request_json = textgen_request_json.to_owned();
+        let send_handle = thread::spawn(move || {
+            send_client
+                .post(&textgen_url)
+                .body(textgen_request_json)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "application/json")
+                .send()
+        });
+        self.wait_for_kobold_thread(&send_handle, client, api_host);
+        send_handle.join().ok()
+    }
+
+    // posts the text generation request to the KoboldAPI's streaming endpoint and reads the
+    // server-sent events as they arrive, passing each token on to the client when streaming.
+    // the text generated once the stream ends gets returned; an error means the stream
+    // couldn't be started or read.
+    fn stream_kobold_request(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        api_host: &str,
+        stream_url: &str,
+        textgen_request_json: &str,
+    ) -> Result<String, String> {
+        let send_client = client.clone();
+        let stream_url = stream_url.to_owned();
+        let textgen_request_json = textgen_request_json.to_owned();
+
+        // the send can't hold up reading the stream, so tokens that don't fit in the channel
+        // are held and sent along with the next one.
+        let fragment_sender = self
+            .stream_text_fragments
+            .then(|| self.send_to_client.clone());
+        let stream_handle = thread::spawn(move || -> Result<String, String> {
+            let resp = send_client
+                .post(&stream_url)
+                .body(textgen_request_json)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "text/event-stream")
+                .send()
+                .map_err(|err| err.to_string())?;
+            if resp.status() != reqwest::StatusCode::OK {
+                return Err(format!("the server returned the status {}", resp.status()));
+            }
+
+            let mut generated_text = String::new();
+            let mut unsent = String::new();
+            for line in BufReader::new(resp).lines() {
+                let line = line.map_err(|err| err.to_string())?;
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                let event: KoboldStreamEvent = serde_json::from_str(data.trim())
+                    .map_err(|err| format!("couldn't read a streamed event: {}", err))?;
+                generated_text.push_str(&event.token);
+                if let Some(sender) = &fragment_sender {
+                    unsent.push_str(&event.token);
+                    let fragment = LlmEngineResponse::NewTextFragment(unsent.clone());
+                    if sender.try_send(fragment).is_ok() {
+                        unsent.clear();
+                    }
+                }
+            }
+            Ok(generated_text)
+        });
+        self.wait_for_kobold_thread(&stream_handle, client, api_host);
+        stream_handle
+            .join()
+            .unwrap_or_else(|_| Err("the streaming thread panicked".to_owned()))
+    }
+
+    // waits for a helper thread making a KoboldAPI request to finish, checking for a cancel
+    // while it does, which gets passed on to the server as an abort of this generation.
+    fn wait_for_kobold_thread<T>(
+        &mut self,
+        handle: &thread::JoinHandle<T>,
+        client: &reqwest::blocking::Client,
+        api_host: &str,
+    ) {
+        let mut abort_sent = false;
+        while !handle.is_finished() {
+            self.poll_commands_during_request();
+            if !abort_sent && self.cancel_requested.load(Ordering::SeqCst) {
+                self.abort_kobold_generation(client, api_host);
+                abort_sent = true;
+            }
+            thread::sleep(Duration::from_millis(REMOTE_CANCEL_POLL_MS));
+        }
+    }
+
+    // waits before a remote request gets sent again, checking for a cancel while it does.
+    // returns false if the request was cancelled during the wait.
+    fn wait_before_retry(&mut self, delay: Duration) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < delay {
+            self.poll_commands_during_request();
+            if self.cancel_requested.load(Ordering::SeqCst) {
+                log::debug!("Stopped waiting to retry a remote request after a cancel.");
+                return false;
+            }
+            thread::sleep(Duration::from_millis(REMOTE_CANCEL_POLL_MS));
+        }
+        true
+    }
+
+    // builds the http client for a request to the remote server, sending the api key if one is
+    // configured. `api_label` prefixes the logged errors. returns None if the key can't be used.
+    fn build_remote_client(&self, api_label: &str) -> Option<reqwest::blocking::Client> {
+        // the api key is looked up for each request so a changed environment variable gets used
+        let api_key = match self.model_config.resolve_api_key() {
+            Ok(api_key) => api_key,
+            Err(problem) => {
+                log::error!("{}: {}", api_label, problem);
+                return None;
+            }
+        };
+        let mut default_headers = reqwest::header::HeaderMap::new();
+        if let Some(api_key) = api_key {
+            match reqwest::header::HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+                Ok(mut auth_value) => {
+                    auth_value.set_sensitive(true);
+                    default_headers.insert(reqwest::header::AUTHORIZATION, auth_value);
+                }
+                Err(_) => {
+                    log::error!("{}: The configured api_key has characters that can't be sent in a request header.", api_label);
+                    return None;
+                }
+            }
+        }
+
+        // Use a default 120 minute timeout, unless configured otherwise
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(
+                self.model_config.remote_timeout_s.unwrap_or(60 * 120),
+            ))
+            .default_headers(default_headers)
+            .build()
+            .expect("Failed to create the blocking reqwest client for the remote server.");
+        Some(client)
+    }
+
+    // sends the prompt to a server with an OpenAI-compatible chat completions endpoint as a
+    // single user message. these servers have no way to abort a generation, so a cancel just
+    // stops waiting on the response and leaves the server to finish on its own.
+    fn text_infer_openai(&mut self, context: &mut TextInferenceContext) -> Option<String> {
+        // build the prompt
+        let prompt = self.create_prompt_for_chat_input(context);
+
+        // DEBUG WRITE OUT THE PROMPT TO A FILE.
+        #[cfg(debug_assertions)]
+        {
+            let mut raw_file = File::create(".debug.prompt.txt").unwrap();
+            let _ = raw_file.write_all(prompt.as_bytes());
+        }
+
+        let client = self.build_remote_client("OpenAI API")?;
+
+        // If not supplied we try to use the localhost with llama.cpp's server port
+        let api_host = match self.model_config.remote_server.as_ref() {
+            Some(s) => s.to_owned(),
+            None => {
+                log::warn!("OpenAI API: currently selected model didn't specify 'remote_server'; defaulting to 'http://localhost:8080'");
+                "http://localhost:8080".to_owned()
+            }
+        };
+
+        let stop_seqs = self.build_stop_sequences(context);
+
+        let chat_url = format!("{}{}", api_host, "/v1/chat/completions");
+        let chat_request = OpenAiChatRequest {
+            messages: vec![OpenAiChatMessage {
+                role: "user".to_owned(),
+                content: prompt,
+            }],
+            max_tokens: Some(self.get_max_new_tokens()),
+            temperature: context.parameters.temperature,
+            top_p: context.parameters.top_p,
+            // the multiplicative repeat penalty has no direct match, so the part over 1.0
+            // becomes the additive frequency penalty, which OpenAI limits to -2.0 to 2.0.
+            frequency_penalty: context
+                .parameters
+                .repeat_penalty
+                .map(|penalty| (penalty - 1.0).clamp(-2.0, 2.0)),
+            seed: if context.randomize_seed {
+                Some(self.rng.gen_range(0..i64::from(i32::MAX)))
+            } else {
+                None
+            },
+            stop: if stop_seqs.is_empty() {
+                None
+            } else {
+                Some(stop_seqs.clone())
+            },
+        };
+
+        // the blocking call is made on a helper thread so that this one can keep checking for a cancel
+        let chat_request_json = match serde_json::to_string(&chat_request) {
+            Ok(json) => json,
+            Err(err) => {
+                log::error!(
+                    "OpenAI API: Failed to serialize the chat completion request: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        let send_handle = thread::spawn(move || {
+            client
+                .post(&chat_url)
+                .body(chat_request_json)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "application/json")
+                .send()
+        });
+        while !send_handle.is_finished() {
+            self.poll_commands_during_request();
+            if self.cancel_requested.load(Ordering::SeqCst) {
+                log::debug!("OpenAI API: stopped waiting on the chat completion after a cancel.");
+                return None;
+            }
+            thread::sleep(Duration::from_millis(REMOTE_CANCEL_POLL_MS));
+        }
+        let chat_resp = match send_handle.join() {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(err)) => {
+                log::error!("OpenAI API: The chat completion request failed: {}", err);
+                return None;
+            }
+            Err(_) => {
+                log::error!("OpenAI API: The chat completion request thread panicked.");
+                return None;
+            }
+        };
+        if chat_resp.status() != reqwest::StatusCode::OK {
+            log::error!(
+                "OpenAI API: Failed to generate text for the given prompt. Status: {}",
+                chat_resp.status()
+            );
+            return None;
+        }
+
+        let chat_resp_text = match chat_resp.text() {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!(
+                    "OpenAI API: Failed to get the JSON from the chat completion response body: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        let chat_resp: OpenAiChatResponse = match serde_json::from_str(&chat_resp_text) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                log::error!(
+                    "OpenAI API: Failed to deserialize the JSON from the chat completion response: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        let Some(choice) = chat_resp.choices.into_iter().next() else {
+            log::error!("OpenAI API: Failed to generate text for the given prompt. No choices were returned.");
+            return None;
+        };
+        let mut inferred_string = choice.message.content;
 
         // DEBUG WRITE OUT THE PROMPT TO A FILE.
         #[cfg(debug_assertions)]
@@ -470,28 +1627,72 @@ impl EngineState {
             let _ = raw_file.write_all(inferred_string.as_bytes());
         }
 
-        // if enabled, stop the inferred string at any detected name of a participant.
-        if self.config.stop_on_display_name {
-            self.split_inference_at_display_names(context, &mut inferred_string);
-        }
+        // the server should have stopped already, but trim here too in case it didn't.
+        truncate_at_stop_sequences(&stop_seqs, &mut inferred_string);
 
         Some(inferred_string)
     }
 
+    // asks the KoboldCpp server to stop the generation started with the in-flight genkey.
+    // the server still answers the original request with whatever text it had so far.
+    fn abort_kobold_generation(&self, client: &reqwest::blocking::Client, api_host: &str) {
+        let Some(genkey) = self.kobold_genkey.as_ref() else {
+            return;
+        };
+        let abort_url = format!("{}{}", api_host, "/api/extra/abort");
+        let abort_request = TextgenAbortRequestKobold {
+            genkey: genkey.to_owned(),
+        };
+        let abort_request_json = match serde_json::to_string(&abort_request) {
+            Ok(json) => json,
+            Err(err) => {
+                log::error!("KoboldAPI: Failed to serialize the abort request: {}", err);
+                return;
+            }
+        };
+        let abort_resp = client
+            .post(&abort_url)
+            .body(abort_request_json)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .send();
+        match abort_resp {
+            Ok(resp) if resp.status() == reqwest::StatusCode::OK => {
+                log::debug!("KoboldAPI: aborted the generation for genkey {}", genkey);
+            }
+            Ok(resp) => {
+                log::warn!(
+                    "KoboldAPI: Failed to abort the generation for genkey {}. Status: {}",
+                    genkey,
+                    resp.status()
+                );
+            }
+            Err(err) => {
+                log::warn!(
+                    "KoboldAPI: Failed to abort the generation for genkey {}: {}",
+                    genkey,
+                    err
+                );
+            }
+        }
+    }
+
     fn text_infer(&mut self, context: &mut TextInferenceContext) -> Option<String> {
-        let this_seed = match self.model_config.seed {
-            Some(s) => s,
-            None => -1, // this should make llama.cpp make a random seed
+        let this_seed = if context.randomize_seed {
+            self.rng.gen_range(0..i32::MAX)
+        } else {
+            // -1 should make llama.cpp make a random seed
+            self.model_config.seed.unwrap_or(-1)
         };
 
+        let stop_seqs = self.build_stop_sequences(context);
         let mut predict_options = PredictOptions {
             seed: this_seed,
             batch: self.config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as i32,
             threads: self.config.thread_count.unwrap_or(DEFAULT_THREAD_COUNT) as i32,
-            tokens: self
-                .config
-                .maximum_new_tokens
-                .unwrap_or(DEFAULT_MAX_NEW_TOKENS) as i32,
+            tokens: self.get_max_new_tokens() as i32,
+            stop_prompts: stop_seqs.clone(),
+            grammar: self.grammar.clone().unwrap_or_default(),
             ..Default::default()
         };
 
@@ -535,6 +1736,31 @@ impl EngineState {
             predict_options.repeat = rep_range as i32;
         }
 
+        // check the command channel as tokens get generated so that the
+        // inference can be stopped early if a cancel shows up.
+        let recv_cmd_on_server = self.recv_cmd_on_server.clone();
+        let cancel_requested = self.cancel_requested.clone();
+        let deferred_commands = self.deferred_commands.clone();
+
+        // pass each token on to the client, if streaming. the send can't block generation, so
+        // tokens that don't fit in the channel are held and sent along with the next one.
+        let fragment_sender = self
+            .stream_text_fragments
+            .then(|| self.send_to_client.clone());
+        let unsent_fragment = RefCell::new(String::new());
+        predict_options.token_callback = Some(Box::new(move |token| {
+            if let Some(sender) = &fragment_sender {
+                let mut unsent = unsent_fragment.borrow_mut();
+                unsent.push_str(&token);
+                let fragment = LlmEngineResponse::NewTextFragment(unsent.clone());
+                if sender.try_send(fragment).is_ok() {
+                    unsent.clear();
+                }
+            }
+            poll_command_channel(&recv_cmd_on_server, &cancel_requested, &deferred_commands);
+            !cancel_requested.load(Ordering::SeqCst)
+        }));
+
         let prompt = self.create_prompt_for_chat_input(context);
 
         // DEBUG WRITE OUT THE PROMPT TO A FILE.
@@ -572,78 +1798,320 @@ impl EngineState {
             let _ = raw_file.write_all(inferred_string.as_bytes());
         }
 
-        // TODO: Actually do the stopping of the token generation in the above loop instead.
-        // if enabled, stop the inferred string at any detected name of a participant.
-        if self.config.stop_on_display_name {
-            self.split_inference_at_display_names(context, &mut inferred_string);
-        }
+        // llama.cpp leaves the stop prompt it found on the end, so trim the response at it.
+        truncate_at_stop_sequences(&stop_seqs, &mut inferred_string);
 
         return Some(inferred_string);
     }
 
-    // the purpose of this function is to split the response away from the part where
-    // it might try to generate a response for another participant.
-    fn split_inference_at_display_names(
-        &self,
-        context: &TextInferenceContext,
-        inferred_string: &mut String,
-    ) {
-        let mut earliest = None;
+    // builds the list of strings that should end a response, shared by every backend so that
+    // they all stop in the same places: the configured stop sequences and, if enabled, the name
+    // of each participant followed by a colon, which is the start of a response for someone else.
+    fn build_stop_sequences(&self, context: &TextInferenceContext) -> Vec<String> {
+        let mut stop_seqs: Vec<String> = Vec::new();
+        if self.config.stop_on_display_name {
+            // the main character wont be listed as an 'other_participant' when the text
+            // inference request is created, so the chatlog owner is added explicitly in case
+            // it's different than the current character generating text.
+            let names = [
+                self.config.display_name.as_str(),
+                context.character.name.as_str(),
+                context.chatlog_owner.name.as_str(),
+            ]
+            .into_iter()
+            .chain(context.other_participants.iter().map(|o| o.0.name.as_str()));
+            stop_seqs = build_participant_name_stops(names);
+        }
 
-        // this is a little sloppy but should work. check user first
-        let stop_phrase = format!("{}:", self.config.display_name);
-        if let Some(found) = inferred_string.find(&stop_phrase) {
-            let prev_earliest = earliest.unwrap_or(inferred_string.len());
-            if found < prev_earliest {
-                earliest = Some(found);
+        // the configured stops apply to everyone, then the generating character's own are added
+        let configured = self
+            .config
+            .stop_sequences
+            .iter()
+            .flatten()
+            .chain(context.character.stop_sequences.iter().flatten());
+        for stop_phrase in configured {
+            if !stop_phrase.is_empty() && !stop_seqs.contains(stop_phrase) {
+                stop_seqs.push(stop_phrase.clone());
             }
         }
 
-        // check the character name that's doing the generation
-        let stop_phrase = format!("{}:", context.character.name);
-        if let Some(found) = inferred_string.find(&stop_phrase) {
-            let prev_earliest = earliest.unwrap_or(inferred_string.len());
-            if found < prev_earliest {
-                earliest = Some(found);
-            }
+        stop_seqs
+    }
+}
+
+// builds the stop sequences that mark the start of a turn for each of the named participants:
+// the name followed by a colon, without duplicates.
+pub fn build_participant_name_stops<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut stops: Vec<String> = Vec::new();
+    for name in names {
+        let stop_phrase = format!("{}:", name);
+        if !stops.contains(&stop_phrase) {
+            stops.push(stop_phrase);
         }
+    }
+    stops
+}
 
-        // the main character wont be listed as an 'other_participant' when the text
-        // inference request is created, so we check here to see if the chatlog
-        // owner is different than the current character generating text and if so
-        // we look to find the original owner's name too
-        if !context
-            .character
-            .name
-            .eq_ignore_ascii_case(&context.chatlog_owner.name)
-        {
-            let stop_phrase = format!("{}:", context.chatlog_owner.name);
-            if let Some(found) = inferred_string.find(&stop_phrase) {
-                let prev_earliest = earliest.unwrap_or(inferred_string.len());
-                if found < prev_earliest {
-                    earliest = Some(found);
+// replaces each of the section tags in the template with its text in a single pass, so the text
+// of one section is never searched for the tags of another. when a section is empty, a line
+// holding nothing but its tag is dropped instead of being left blank.
+fn fill_prompt_sections(template: &str, sections: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    for line in template.split_inclusive('\n') {
+        let is_empty_section = sections
+            .iter()
+            .any(|(tag, text)| text.is_empty() && line.trim() == *tag);
+        if is_empty_section {
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(tag_start) = rest.find("<|") {
+            filled.push_str(&rest[..tag_start]);
+            rest = &rest[tag_start..];
+            match sections.iter().find(|(tag, _)| rest.starts_with(tag)) {
+                Some((tag, text)) => {
+                    filled.push_str(text);
+                    rest = &rest[tag.len()..];
+                }
+                None => {
+                    // not a section tag, so leave it for the rest of the prompt building
+                    filled.push_str("<|");
+                    rest = &rest[2..];
                 }
             }
         }
+        filled.push_str(rest);
+    }
+    filled
+}
 
-        // check for the name of any other participants
-        for other in context.other_participants.iter() {
-            let stop_phrase = format!("{}:", other.0.name);
-            if let Some(found) = inferred_string.find(&stop_phrase) {
-                let prev_earliest = earliest.unwrap_or(inferred_string.len());
-                if found < prev_earliest {
-                    earliest = Some(found);
+// reads the context length the model was trained for out of the metadata at the start of a gguf
+// file, stored under the `<architecture>.context_length` key. returns None for files that aren't
+// gguf (version 2 or later) or that don't have the key.
+fn read_gguf_context_length(model_path: &str) -> anyhow::Result<Option<usize>> {
+    use std::io::{BufReader, Read};
+
+    const GGUF_TYPE_UINT8: u32 = 0;
+    const GGUF_TYPE_INT8: u32 = 1;
+    const GGUF_TYPE_UINT16: u32 = 2;
+    const GGUF_TYPE_INT16: u32 = 3;
+    const GGUF_TYPE_UINT32: u32 = 4;
+    const GGUF_TYPE_INT32: u32 = 5;
+    const GGUF_TYPE_FLOAT32: u32 = 6;
+    const GGUF_TYPE_BOOL: u32 = 7;
+    const GGUF_TYPE_STRING: u32 = 8;
+    const GGUF_TYPE_ARRAY: u32 = 9;
+    const GGUF_TYPE_UINT64: u32 = 10;
+    const GGUF_TYPE_INT64: u32 = 11;
+    const GGUF_TYPE_FLOAT64: u32 = 12;
+
+    fn read_u32(r: &mut impl Read) -> anyhow::Result<u32> {
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+    fn read_u64(r: &mut impl Read) -> anyhow::Result<u64> {
+        let mut buf = [0u8; 8];
+        r.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+    fn read_string(r: &mut impl Read) -> anyhow::Result<String> {
+        let len = read_u64(r)?;
+        let mut buf = Vec::new();
+        r.take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            anyhow::bail!("The gguf metadata ended in the middle of a string");
+        }
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+    fn skip_bytes(r: &mut impl Read, count: u64) -> anyhow::Result<()> {
+        let skipped = std::io::copy(&mut r.take(count), &mut std::io::sink())?;
+        if skipped != count {
+            anyhow::bail!("The gguf metadata ended in the middle of a value");
+        }
+        Ok(())
+    }
+    // reads an integer value, or skips over any other type of value and returns None
+    fn read_value(r: &mut impl Read, value_type: u32) -> anyhow::Result<Option<u64>> {
+        let fixed_size = match value_type {
+            GGUF_TYPE_UINT32 => return Ok(Some(read_u32(r)? as u64)),
+            GGUF_TYPE_INT32 => return Ok(Some((read_u32(r)? as i32).max(0) as u64)),
+            GGUF_TYPE_UINT64 => return Ok(Some(read_u64(r)?)),
+            GGUF_TYPE_INT64 => return Ok(Some((read_u64(r)? as i64).max(0) as u64)),
+            GGUF_TYPE_UINT8 | GGUF_TYPE_INT8 | GGUF_TYPE_BOOL => 1,
+            GGUF_TYPE_UINT16 | GGUF_TYPE_INT16 => 2,
+            GGUF_TYPE_FLOAT32 => 4,
+            GGUF_TYPE_FLOAT64 => 8,
+            GGUF_TYPE_STRING => {
+                let len = read_u64(r)?;
+                skip_bytes(r, len)?;
+                return Ok(None);
+            }
+            GGUF_TYPE_ARRAY => {
+                let item_type = read_u32(r)?;
+                let count = read_u64(r)?;
+                for _ in 0..count {
+                    read_value(r, item_type)?;
                 }
+                return Ok(None);
             }
+            _ => anyhow::bail!("Unknown gguf metadata value type {}", value_type),
+        };
+        skip_bytes(r, fixed_size)?;
+        Ok(None)
+    }
+
+    let file = std::fs::File::open(model_path)?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"GGUF" || read_u32(&mut reader)? < 2 {
+        return Ok(None);
+    }
+    let _tensor_count = read_u64(&mut reader)?;
+    let metadata_count = read_u64(&mut reader)?;
+
+    for _ in 0..metadata_count {
+        let key = read_string(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        let value = read_value(&mut reader, value_type)?;
+        if key.ends_with(".context_length") {
+            return Ok(value.map(|v| v as usize));
         }
+    }
+    Ok(None)
+}
 
-        if let Some(earliest) = earliest {
-            log::debug!(
-                "Splitting off response at {}\n{}",
-                earliest,
-                inferred_string
-            );
-            let _ = inferred_string.split_off(earliest); // we discard the rest
+// the backends that can run text inference for a model configuration
+#[derive(Clone, Copy, PartialEq)]
+enum InferenceBackend {
+    LlamaCpp,
+    KoboldCpp,
+    OpenAi,
+}
+impl InferenceBackend {
+    // local model files run on llama.cpp; everything else goes to the remote server.
+    fn for_model(model_config: &ConfiguredLlm) -> Self {
+        if model_config.path.is_some() {
+            InferenceBackend::LlamaCpp
+        } else if model_config.uses_openai_api() {
+            InferenceBackend::OpenAi
+        } else {
+            InferenceBackend::KoboldCpp
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            InferenceBackend::LlamaCpp => "llama.cpp",
+            InferenceBackend::KoboldCpp => "KoboldCpp",
+            InferenceBackend::OpenAi => "OpenAI API",
+        }
+    }
+}
+
+// returns a description of each setting in the parameter set that won't have an effect on the backend.
+fn get_ignored_parameters(
+    parameters: &ConfiguredParameters,
+    backend: InferenceBackend,
+) -> Vec<String> {
+    let mut ignored = Vec::new();
+
+    // the chat completions api only has temperature, top_p and a frequency penalty
+    if backend == InferenceBackend::OpenAi {
+        let unsupported = [
+            ("top_k", parameters.top_k.is_some()),
+            ("min_p", parameters.min_p.is_some()),
+            (
+                "repeat_penalty_range",
+                parameters.repeat_penalty_range.is_some(),
+            ),
+            ("mirostat", parameters.mirostat.is_some_and(|m| m != 0)),
+            ("mirostat_eta", parameters.mirostat_eta.is_some()),
+            ("mirostat_tau", parameters.mirostat_tau.is_some()),
+        ];
+        for (name, is_set) in unsupported {
+            if is_set {
+                ignored.push(format!(
+                    "{}: not supported by OpenAI-compatible servers",
+                    name
+                ));
+            }
+        }
+        return ignored;
+    }
+
+    let mirostat_enabled = matches!(parameters.mirostat, Some(1) | Some(2));
+    if let Some(mirostat) = parameters.mirostat {
+        if mirostat > 2 {
+            ignored.push(format!(
+                "mirostat: {} isn't a mirostat version; use 1 or 2, or 0 to disable it",
+                mirostat
+            ));
+        }
+    }
+
+    if !mirostat_enabled {
+        if parameters.mirostat_eta.is_some() {
+            ignored.push("mirostat_eta: mirostat isn't enabled".to_owned());
+        }
+        if parameters.mirostat_tau.is_some() {
+            ignored.push("mirostat_tau: mirostat isn't enabled".to_owned());
+        }
+    } else if backend == InferenceBackend::LlamaCpp {
+        // llama.cpp turns off the other samplers when sampling with mirostat
+        let replaced_samplers = [
+            ("top_k", parameters.top_k.is_some()),
+            ("top_p", parameters.top_p.is_some()),
+            ("min_p", parameters.min_p.is_some()),
+            ("temperature", parameters.temperature.is_some()),
+        ];
+        for (name, is_set) in replaced_samplers {
+            if is_set {
+                ignored.push(format!("{}: disabled while mirostat is enabled", name));
+            }
+        }
+    }
+
+    ignored
+}
+
+// the purpose of this function is to split the response away from the part where
+// it might try to generate a response for another participant, or continue on past
+// any of the other stop sequences. everything from the earliest match on is discarded.
+fn truncate_at_stop_sequences(stop_seqs: &[String], inferred_string: &mut String) {
+    let earliest = stop_seqs
+        .iter()
+        .filter_map(|stop_phrase| inferred_string.find(stop_phrase.as_str()))
+        .min();
+
+    if let Some(earliest) = earliest {
+        log::debug!(
+            "Splitting off response at {}\n{}",
+            earliest,
+            inferred_string
+        );
+        let _ = inferred_string.split_off(earliest); // we discard the rest
+    }
+}
+
+// drains the command channel without blocking. CancelTextInference sets the cancel flag and any
+// other command is pushed onto the deferred list so it can run after the current request.
+// this is a free function so that it can also be called from the llama.cpp token callback.
+fn poll_command_channel(
+    recv_cmd_on_server: &Receiver<LlmEngineCommand>,
+    cancel_requested: &AtomicBool,
+    deferred_commands: &Mutex<Vec<LlmEngineCommand>>,
+) {
+    while let Ok(cmd) = recv_cmd_on_server.try_recv() {
+        match cmd {
+            LlmEngineCommand::CancelTextInference => cancel_requested.store(true, Ordering::SeqCst),
+            other => match deferred_commands.lock() {
+                Ok(mut deferred) => deferred.push(other),
+                Err(err) => log::error!("Failed to lock the deferred engine commands: {}", err),
+            },
         }
     }
 }
@@ -682,8 +2150,10 @@ pub struct TextgenRemoteRequestKobold {
     mirostat_tau: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     mirostat_eta: Option<f32>,
-    // genkey
-    // grammar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    genkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grammar: Option<String>,
     // grammar_retain_state
     // memory
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -692,6 +2162,11 @@ pub struct TextgenRemoteRequestKobold {
     stop_sequence: Option<Vec<String>>,
 }
 
+#[derive(Serialize, Debug, Clone)]
+struct TextgenAbortRequestKobold {
+    genkey: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct TextgenResponseBodyKobold {
     results: Vec<TextgenResponseBodyResultKobold>,
@@ -701,3 +2176,43 @@ pub struct TextgenResponseBodyKobold {
 pub struct TextgenResponseBodyResultKobold {
     text: String,
 }
+
+// one server-sent event from the KoboldAPI's streaming endpoint
+#[derive(Deserialize, Debug, Clone)]
+pub struct KoboldStreamEvent {
+    #[serde(default)]
+    token: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OpenAiChatRequest {
+    pub messages: Vec<OpenAiChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAiChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAiChatResponse {
+    choices: Vec<OpenAiChatChoice>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAiChatChoice {
+    message: OpenAiChatMessage,
+}
@@ -7,17 +7,20 @@ use anyhow::Context;
 use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
     prelude::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Modifier, Style, Stylize},
-    text::Line,
-    widgets::{List, ListItem, Paragraph},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
 };
 
 use crate::{
-    chatlog::ChatLog,
+    chatlog::{
+        backup_json_file, clear_journal, get_journal_filepath, has_unsaved_journal,
+        list_json_file_backups, ChatLog, DEFAULT_LOG_BACKUP_COUNT,
+    },
     config::{get_log_folder, CharacterFileYaml, ConfigurationFile, LOG_FILE_NAME},
     tui::{
-        Frame, MessageBoxModalWidget, ProcessInputResult, StatefulList, TerminalEvent,
-        TerminalRenderable, TextEditingBlockModalWidget,
+        centered_rect, Frame, MessageBoxModalWidget, ProcessInputResult, StatefulList,
+        TerminalEvent, TerminalRenderable, TextEditingBlockModalWidget,
     },
 };
 
@@ -25,6 +28,9 @@ enum LogSelectEditorState {
     NewLogFilename,
     DupeLogFilename,
     ExportDatasetFilename,
+    ExportMarkdownFilename,
+    ExportShareGptFilename,
+    ImportDatasetFilename,
 }
 
 pub struct LogSelectState {
@@ -34,16 +40,33 @@ pub struct LogSelectState {
     // the character to locate logs for
     character: CharacterFileYaml,
 
-    // Log files detected in a tuple representing: (log folder, log file)
-    logs_found: Vec<(PathBuf, PathBuf)>,
+    // Log files detected in a tuple representing: (log folder, log file, log tags)
+    logs_found: Vec<(PathBuf, PathBuf, Vec<String>)>,
+
+    // the indexes into `logs_found` for the logs that match the current filter
+    visible_logs: Vec<usize>,
+
+    // the text used to filter the logs by folder name or tag
+    filter_text: String,
+
+    // set to true while the user is typing in the filter text
+    editing_filter: bool,
 
     // stores the state of the list item to select the log to load
     list_state: StatefulList<String>,
 
+    // the log files with journal entries that never made it into a saved log, which
+    // happens when the application goes down in the middle of a chat
+    unsaved_journals: Vec<PathBuf>,
+
     // contains the modal dialog widget used to prompt the user for a variety of tasks
     // and the enum value indicating what is being edited
     log_basic_editor: Option<(LogSelectEditorState, TextEditingBlockModalWidget)>,
 
+    // when browsing backups, holds the index into `logs_found` for the log being restored,
+    // the backup files found for it (newest first) and the list state used to pick one
+    backup_browser: Option<(usize, Vec<PathBuf>, StatefulList<String>)>,
+
     // contains a modal dialog widget used to show a message or alert to the user
     modal_messagebox: Option<MessageBoxModalWidget>,
 }
@@ -61,7 +84,7 @@ impl TerminalRenderable for LogSelectState {
                     match editor_type {
                         LogSelectEditorState::ExportDatasetFilename => {
                             let export_filename = editor.text.to_owned();
-                            if let Some(sel_index) = self.list_state.state.selected() {
+                            if let Some(sel_index) = self.get_selected_log_index() {
                                 let log_file = &self.logs_found[sel_index].1;
                                 let chatlog_res = ChatLog::new_from_json(&log_file);
                                 let export_filepath = log_file.with_file_name(export_filename);
@@ -90,6 +113,60 @@ impl TerminalRenderable for LogSelectState {
                             }
                         }
 
+                        LogSelectEditorState::ExportMarkdownFilename => {
+                            let export_filename = editor.text.trim().to_owned();
+                            if let Some(sel_index) = self.get_selected_log_index() {
+                                let log_file = &self.logs_found[sel_index].1;
+                                let export_filepath = log_file.with_file_name(export_filename);
+                                match ChatLog::new_from_json(log_file) {
+                                    Ok(chatlog) => {
+                                        if let Err(e) = chatlog.export_markdown(&export_filepath) {
+                                            log::error!(
+                                                "Failed to export the chatlog ({:?}) to markdown: {}",
+                                                log_file,
+                                                e
+                                            )
+                                        }
+                                    }
+                                    Err(err) => {
+                                        log::error!(
+                                            "Failed to load the chatlog ({:?}): {}",
+                                            log_file,
+                                            err
+                                        )
+                                    }
+                                };
+                            }
+                        }
+
+                        LogSelectEditorState::ExportShareGptFilename => {
+                            let export_filename = editor.text.trim().to_owned();
+                            if let Some(sel_index) = self.get_selected_log_index() {
+                                let log_file = &self.logs_found[sel_index].1;
+                                let export_filepath = log_file.with_file_name(export_filename);
+                                match ChatLog::new_from_json(log_file) {
+                                    Ok(chatlog) => {
+                                        if let Err(e) = chatlog
+                                            .export_sharegpt(&export_filepath, &self.character.name)
+                                        {
+                                            log::error!(
+                                                "Failed to export the chatlog ({:?}) as ShareGPT: {}",
+                                                log_file,
+                                                e
+                                            )
+                                        }
+                                    }
+                                    Err(err) => {
+                                        log::error!(
+                                            "Failed to load the chatlog ({:?}): {}",
+                                            log_file,
+                                            err
+                                        )
+                                    }
+                                };
+                            }
+                        }
+
                         LogSelectEditorState::NewLogFilename => {
                             // create the new log
                             let newlog_name = editor.text.to_owned();
@@ -109,10 +186,8 @@ impl TerminalRenderable for LogSelectState {
                                         "Attempting to create the directory for the new chatlog",
                                     );
                                 if made_dir.is_ok() {
-                                    let mut new_log = ChatLog::new_with_greeting(
-                                        &self.character,
-                                        &self.config.display_name,
-                                    );
+                                    let mut new_log =
+                                        ChatLog::new_with_greeting(&self.character, &self.config);
                                     if let Err(err) = new_log.save_to_json_file(&new_log_file_path)
                                     {
                                         log::error!(
@@ -132,14 +207,19 @@ impl TerminalRenderable for LogSelectState {
                             }
                         }
 
+                        LogSelectEditorState::ImportDatasetFilename => {
+                            let import_filename = editor.text.trim().to_owned();
+                            self.import_dataset(&import_filename);
+                        }
+
                         LogSelectEditorState::DupeLogFilename => {
-                            if let Some(sel_index) = self.list_state.state.selected() {
+                            let new_log_dir = editor.text.to_owned();
+                            if let Some(sel_index) = self.get_selected_log_index() {
                                 let source_log_dir = &self.logs_found[sel_index]
                                     .0
                                     .file_name()
                                     .context("Attempting to get the source dir name to duplicate.")
                                     .unwrap();
-                                let new_log_dir = editor.text.to_owned();
 
                                 let log_folder_path = get_log_folder(self.character.name.as_str());
                                 let src_log_folder_path = log_folder_path.join(source_log_dir);
@@ -162,8 +242,8 @@ impl TerminalRenderable for LogSelectState {
                                         self.character.clone(),
                                         self.config.clone(),
                                     );
-                                    self.list_state = new_lss.list_state;
                                     self.logs_found = new_lss.logs_found;
+                                    self.apply_filter();
                                 }
                             }
                         }
@@ -171,6 +251,51 @@ impl TerminalRenderable for LogSelectState {
                 }
                 self.log_basic_editor = None;
             }
+        } else if let Some((log_index, backups, backup_list)) = self.backup_browser.as_mut() {
+            if let TerminalEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Esc => {
+                        self.backup_browser = None;
+                    }
+                    KeyCode::Char('k') => backup_list.previous(),
+                    KeyCode::Char('j') => backup_list.next(),
+                    KeyCode::Enter => {
+                        if let Some(backup_fp) = backup_list
+                            .state
+                            .selected()
+                            .and_then(|i| backups.get(i))
+                            .cloned()
+                        {
+                            let log_index = *log_index;
+                            self.backup_browser = None;
+                            self.restore_log_backup(log_index, &backup_fp);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        } else if self.editing_filter {
+            if let TerminalEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Esc => {
+                        self.filter_text.clear();
+                        self.editing_filter = false;
+                        self.apply_filter();
+                    }
+                    KeyCode::Enter => {
+                        self.editing_filter = false;
+                    }
+                    KeyCode::Backspace => {
+                        self.filter_text.pop();
+                        self.apply_filter();
+                    }
+                    KeyCode::Char(c) => {
+                        self.filter_text.push(c);
+                        self.apply_filter();
+                    }
+                    _ => {}
+                }
+            }
         } else {
             if let TerminalEvent::Key(key) = event {
                 if key.code == KeyCode::Esc {
@@ -181,10 +306,37 @@ impl TerminalRenderable for LogSelectState {
                     self.list_state.previous()
                 } else if key.code == KeyCode::Char('j') {
                     self.list_state.next()
+                } else if key.code == KeyCode::Char('/') {
+                    self.editing_filter = true;
+                } else if key.code == KeyCode::Char('b') {
+                    self.open_backup_browser();
+                } else if key.code == KeyCode::Char('r') {
+                    self.recover_journal_for_selected_log();
+                } else if key.code == KeyCode::Char('i') {
+                    // show the dialog to pick a dataset file to import as a new chatlog
+                    let ce = TextEditingBlockModalWidget::new(
+                        "Enter the JSONL dataset file to import:".to_owned(),
+                        String::new(),
+                    );
+                    self.log_basic_editor = Some((LogSelectEditorState::ImportDatasetFilename, ce));
                 } else if key.code == KeyCode::Enter {
                     // load the chatlog up and pass it to the chat interface
-                    if let Some(sel_index) = self.list_state.state.selected() {
+                    if let Some(sel_index) = self.get_selected_log_index() {
                         let log_file = &self.logs_found[sel_index].1;
+
+                        // give one chance to recover unsaved messages before they get
+                        // thrown away by the next save in the chat
+                        if let Some(i) = self.unsaved_journals.iter().position(|j| j == log_file) {
+                            self.unsaved_journals.remove(i);
+                            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                                "Unsaved Messages",
+                                "This chatlog has messages from an earlier session that were never saved. Press 'r' to recover them into the log, or enter again to open the log without them.",
+                                60,
+                                30,
+                            ));
+                            return ProcessInputResult::None;
+                        }
+
                         let chatlog_res = ChatLog::new_from_json(&log_file);
                         match chatlog_res {
                             Ok(chatlog) => {
@@ -219,10 +371,30 @@ impl TerminalRenderable for LogSelectState {
                         self.log_basic_editor =
                             Some((LogSelectEditorState::ExportDatasetFilename, ce));
                     }
+                } else if key.code == KeyCode::Char('g') {
+                    if key.modifiers.contains(KeyModifiers::CONTROL) {
+                        // show the dialog to export the log as a ShareGPT conversation
+                        let ce = TextEditingBlockModalWidget::new(
+                            "Enter a name for the exported ShareGPT dataset:".to_owned(),
+                            "sharegpt.jsonl".to_owned(),
+                        );
+                        self.log_basic_editor =
+                            Some((LogSelectEditorState::ExportShareGptFilename, ce));
+                    }
+                } else if key.code == KeyCode::Char('t') {
+                    if key.modifiers.contains(KeyModifiers::CONTROL) {
+                        // show the dialog to export a readable transcript; ctrl-m can't be
+                        // told apart from enter in most terminals, so this is ctrl-t
+                        let ce = TextEditingBlockModalWidget::new(
+                            "Enter a name for the exported markdown transcript:".to_owned(),
+                            "transcript.md".to_owned(),
+                        );
+                        self.log_basic_editor =
+                            Some((LogSelectEditorState::ExportMarkdownFilename, ce));
+                    }
                 } else if key.code == KeyCode::Char('d') {
                     if key.modifiers.contains(KeyModifiers::CONTROL) {
-                        let starting_value = if let Some(sel_index) =
-                            self.list_state.state.selected()
+                        let starting_value = if let Some(sel_index) = self.get_selected_log_index()
                         {
                             self.logs_found[sel_index]
                                     .0
@@ -249,9 +421,15 @@ impl TerminalRenderable for LogSelectState {
                                         k      = move up\n\
                                         enter  = load selected chatlog\n\
                                         esc    = go back to character select\n\
+                                        /      = filter logs by name or tag (esc clears)\n\
+                                        b      = restore the selected chatlog from a backup\n\
+                                        r      = recover unsaved messages into the selected chatlog\n\
+                                        i      = import a JSONL dataset as a new chatlog\n\
                                         ctrl-n = create a new chatlog\n\
                                         ctrl-d = duplicate existing chatlog with a new name\n\
-                                        ctrl-o = export selected chatlog as a training dataset\n";
+                                        ctrl-o = export selected chatlog as a training dataset\n\
+                                        ctrl-g = export selected chatlog as a ShareGPT dataset\n\
+                                        ctrl-t = export selected chatlog as a markdown transcript\n";
 
                     // show the dialog to create a new log
                     let modal =
@@ -267,12 +445,19 @@ impl TerminalRenderable for LogSelectState {
     fn render(&mut self, frame: &mut Frame) {
         let divider = "------------";
         let divider_len = divider.len();
-        let menu_lines = vec![Line::from("Select a Log".bold()), Line::from(divider)];
+        let mut menu_lines = vec![Line::from("Select a Log".bold()), Line::from(divider)];
+        if self.editing_filter || !self.filter_text.is_empty() {
+            menu_lines.push(Line::from(vec![
+                Span::styled("filter: ", Style::default().fg(Color::Rgb(100, 100, 100))),
+                Span::raw(self.filter_text.as_str()),
+            ]));
+        }
 
         let items: Vec<ListItem> = self
-            .logs_found
+            .visible_logs
             .iter()
-            .map(|(d, _)| {
+            .map(|i| {
+                let (d, f, tags) = &self.logs_found[*i];
                 let dir_name = d
                     .file_name()
                     .context("Accessing log directory file_name.")
@@ -280,7 +465,20 @@ impl TerminalRenderable for LogSelectState {
                     .to_str()
                     .context("Converting log directory name to a string.")
                     .unwrap();
-                let lines = vec![Line::from(dir_name)];
+                let mut spans = vec![Span::raw(dir_name)];
+                if !tags.is_empty() {
+                    spans.push(Span::styled(
+                        format!(" [{}]", tags.join(", ")),
+                        Style::default().fg(Color::Rgb(100, 100, 100)),
+                    ));
+                }
+                if self.unsaved_journals.contains(f) {
+                    spans.push(Span::styled(
+                        " (unsaved messages)",
+                        Style::default().fg(Color::Yellow),
+                    ));
+                }
+                let lines = vec![Line::from(spans)];
                 ListItem::new(lines).style(Style::default())
             })
             .collect();
@@ -295,18 +493,13 @@ impl TerminalRenderable for LogSelectState {
                 .width();
         }
 
-        // TODO: allow customization of 'highlight color'
         let items = List::new(items)
-            .highlight_style(
-                Style::default()
-                    .fg(Color::LightGreen)
-                    .add_modifier(Modifier::BOLD),
-            )
-            .highlight_symbol(">> ");
+            .highlight_style(self.config.get_selection_highlight_style())
+            .highlight_symbol(self.config.get_selection_highlight_symbol());
 
         // break things up horizontally to create some padding
         let middle_column_size = 3 + max_width.max(divider_len) as u16;
-        let padding_size = (frame.size().width - middle_column_size) / 2;
+        let padding_size = frame.size().width.saturating_sub(middle_column_size) / 2;
         let hchunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints(
@@ -325,7 +518,7 @@ impl TerminalRenderable for LogSelectState {
             .constraints(
                 [
                     Constraint::Percentage(20),
-                    Constraint::Min(2),
+                    Constraint::Length(menu_lines.len() as u16),
                     Constraint::Min(4),
                 ]
                 .as_ref(),
@@ -350,13 +543,32 @@ impl TerminalRenderable for LogSelectState {
         else if let Some((_, editor)) = &self.log_basic_editor {
             editor.render(frame);
         }
+        // user is picking a backup to restore?
+        else if let Some((_, _, backup_list)) = self.backup_browser.as_mut() {
+            let area = centered_rect(60, 60, frame.size());
+            let items: Vec<ListItem> = backup_list
+                .items
+                .iter()
+                .map(|b| ListItem::new(b.as_str()))
+                .collect();
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .title("Restore a Backup (enter = restore, esc = cancel)")
+                        .borders(Borders::ALL),
+                )
+                .highlight_style(self.config.get_selection_highlight_style())
+                .highlight_symbol(self.config.get_selection_highlight_symbol());
+
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(list, area, &mut backup_list.state);
+        }
     }
 }
 impl LogSelectState {
     pub fn new(character: CharacterFileYaml, config: ConfigurationFile) -> Self {
         // build a list of potential log files
-        let mut logs_found: Vec<(PathBuf, PathBuf)> = Vec::new();
-        let mut list_items = vec![];
+        let mut logs_found: Vec<(PathBuf, PathBuf, Vec<String>)> = Vec::new();
         let log_folder = get_log_folder(character.name.as_str());
 
         // if this is a new character, the log folder might not exist.
@@ -368,7 +580,7 @@ impl LogSelectState {
                 .recursive(true)
                 .create(&default_log_dir)
                 .unwrap();
-            let mut new_chatlog = ChatLog::new_with_greeting(&character, &config.display_name);
+            let mut new_chatlog = ChatLog::new_with_greeting(&character, &config);
             new_chatlog
                 .save_to_json_file(&default_log_file)
                 .context("Attempting to create a default chatlog for the character")
@@ -386,34 +598,330 @@ impl LogSelectState {
                         let log_folder_path = entry.path();
                         let file_path = log_folder_path.join(crate::config::LOG_FILE_NAME);
                         if file_path.exists() {
-                            let dir_name = log_folder_path
-                                .file_name()
-                                .context("Accessing log directory file_name.")
-                                .unwrap()
-                                .to_str()
-                                .context("Converting log directory name to a string.")
-                                .unwrap();
-                            list_items.push(dir_name.to_string());
-                            logs_found.push((log_folder_path, file_path));
+                            let tags =
+                                ChatLog::read_tags_from_json(&file_path).unwrap_or_else(|err| {
+                                    log::error!(
+                                        "Failed to read the tags from the chatlog ({:?}): {}",
+                                        file_path,
+                                        err
+                                    );
+                                    Vec::new()
+                                });
+                            logs_found.push((log_folder_path, file_path, tags));
                         }
                     }
                 }
             }
         }
 
-        let mut list_state = StatefulList::with_items(list_items);
-        if !list_state.items.is_empty() {
-            list_state.state.select(Some(0));
-        }
+        // look for logs that have messages in their journal that never got saved
+        let unsaved_journals: Vec<PathBuf> = logs_found
+            .iter()
+            .filter(|(_, f, _)| has_unsaved_journal(f))
+            .map(|(_, f, _)| f.clone())
+            .collect();
+        let modal_messagebox = if unsaved_journals.is_empty() {
+            None
+        } else {
+            Some(MessageBoxModalWidget::new(
+                "Unsaved Messages",
+                format!("{} chatlog(s) have messages from an earlier session that were never saved. Select one and press 'r' to recover them.", unsaved_journals.len()).as_str(),
+                60,
+                30,
+            ))
+        };
 
-        Self {
+        let mut new_state = Self {
             config,
             character,
             logs_found,
-            list_state,
+            visible_logs: Vec::new(),
+            filter_text: String::new(),
+            editing_filter: false,
+            list_state: StatefulList::with_items(Vec::new()),
+            unsaved_journals,
             log_basic_editor: None,
-            modal_messagebox: None,
+            backup_browser: None,
+            modal_messagebox,
+        };
+        new_state.apply_filter();
+        new_state
+    }
+
+    // rebuilds the list of visible logs using the filter text, which is matched
+    // case-insensitively against both the log folder name and the log's tags.
+    fn apply_filter(&mut self) {
+        let filter = self.filter_text.to_lowercase();
+        let mut list_items = vec![];
+        self.visible_logs.clear();
+        for (i, (log_folder_path, _, tags)) in self.logs_found.iter().enumerate() {
+            let dir_name = log_folder_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let is_match = filter.is_empty()
+                || dir_name.to_lowercase().contains(&filter)
+                || tags.iter().any(|t| t.to_lowercase().contains(&filter));
+            if is_match {
+                self.visible_logs.push(i);
+                list_items.push(dir_name.to_string());
+            }
+        }
+
+        self.list_state = StatefulList::with_items(list_items);
+        if !self.list_state.items.is_empty() {
+            self.list_state.state.select(Some(0));
+        }
+    }
+
+    // shows the backups available for the selected log so that one can be restored.
+    fn open_backup_browser(&mut self) {
+        let Some(sel_index) = self.get_selected_log_index() else {
+            return;
+        };
+        let log_file = &self.logs_found[sel_index].1;
+        let backups = match list_json_file_backups(log_file) {
+            Ok(backups) => backups,
+            Err(err) => {
+                log::error!(
+                    "Failed to list the backups for the chatlog ({:?}): {}",
+                    log_file,
+                    err
+                );
+                Vec::new()
+            }
+        };
+        if backups.is_empty() {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "No Backups",
+                "There are no backups for the selected chatlog yet.",
+                50,
+                20,
+            ));
+            return;
         }
+
+        // the backup file names carry the timestamp of when they were made
+        let names = backups
+            .iter()
+            .map(|b| {
+                b.file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("<Unknown>")
+                    .to_string()
+            })
+            .collect();
+        let mut backup_list = StatefulList::with_items(names);
+        backup_list.state.select(Some(0));
+        self.backup_browser = Some((sel_index, backups, backup_list));
+    }
+
+    // copies the backup over the log file, backing up the current log file first so that
+    // restoring can itself be undone.
+    fn restore_log_backup(&mut self, log_index: usize, backup_fp: &Path) {
+        let log_file = self.logs_found[log_index].1.clone();
+        let result = std::fs::read(backup_fp)
+            .context("Attempting to read the backup file")
+            .and_then(|contents| {
+                let backup_count = self
+                    .config
+                    .log_backup_count
+                    .unwrap_or(DEFAULT_LOG_BACKUP_COUNT);
+                if backup_count > 0 {
+                    backup_json_file(&log_file, backup_count)?;
+                }
+                std::fs::write(&log_file, contents)
+                    .context("Attempting to write the backup over the chatlog")
+            });
+
+        let message = match result {
+            Ok(_) => {
+                let tags = ChatLog::read_tags_from_json(&log_file).unwrap_or_default();
+                self.logs_found[log_index].2 = tags;
+                self.apply_filter();
+                "The chatlog was restored from the backup.".to_owned()
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to restore the chatlog ({:?}) from {:?}: {}",
+                    log_file,
+                    backup_fp,
+                    err
+                );
+                format!("The chatlog could not be restored: {}", err)
+            }
+        };
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+            "Restore Backup",
+            &message,
+            50,
+            20,
+        ));
+    }
+
+    // replays the journal of the selected log onto it and saves the result, so that messages
+    // from a session that ended before it could save aren't lost.
+    fn recover_journal_for_selected_log(&mut self) {
+        let Some(sel_index) = self.get_selected_log_index() else {
+            return;
+        };
+        let log_file = self.logs_found[sel_index].1.clone();
+        let journal_file = get_journal_filepath(&log_file);
+        if !has_unsaved_journal(&log_file) {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Recover Messages",
+                "There are no unsaved messages to recover for the selected chatlog.",
+                50,
+                20,
+            ));
+            return;
+        }
+
+        let result = ChatLog::new_from_json(&log_file).and_then(|mut chatlog| {
+            let replayed = chatlog.replay_journal(&journal_file)?;
+            let backup_count = self
+                .config
+                .log_backup_count
+                .unwrap_or(DEFAULT_LOG_BACKUP_COUNT);
+            if backup_count > 0 {
+                backup_json_file(&log_file, backup_count)?;
+            }
+            chatlog.save_to_json_file(&log_file)?;
+            clear_journal(&log_file)?;
+            Ok(replayed)
+        });
+
+        let message = match result {
+            Ok(replayed) => {
+                self.unsaved_journals.retain(|j| j != &log_file);
+                format!("Recovered {} message(s) into the chatlog.", replayed)
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to recover the journal for the chatlog ({:?}): {}",
+                    log_file,
+                    err
+                );
+                format!("The unsaved messages could not be recovered: {}", err)
+            }
+        };
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+            "Recover Messages",
+            &message,
+            50,
+            20,
+        ));
+    }
+
+    // imports the JSONL dataset file into a new chatlog folder named after the file. a
+    // relative file name is looked for in the selected chatlog's folder, which is where
+    // exported datasets get written, or the character's log folder if nothing is selected.
+    fn import_dataset(&mut self, import_filename: &str) {
+        if import_filename.is_empty() {
+            return;
+        }
+        let log_folder_path = get_log_folder(self.character.name.as_str());
+        let import_filepath = match self.get_selected_log_index() {
+            Some(sel_index) => self.logs_found[sel_index].0.join(import_filename),
+            None => log_folder_path.join(import_filename),
+        };
+
+        let (mut new_log, skipped) = match ChatLog::new_from_dataset_jsonl(
+            &import_filepath,
+            &self.config.display_name,
+            &self.character.name,
+        ) {
+            Ok(imported) => imported,
+            Err(err) => {
+                log::error!(
+                    "Failed to import the dataset ({:?}): {}",
+                    import_filepath,
+                    err
+                );
+                self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                    "Import Failed",
+                    format!("The dataset couldn't be read: {}", err).as_str(),
+                    50,
+                    20,
+                ));
+                return;
+            }
+        };
+        if new_log.len() == 0 {
+            self.modal_messagebox = Some(MessageBoxModalWidget::new(
+                "Import Failed",
+                format!(
+                    "Nothing could be imported from the dataset; {} lines were skipped.",
+                    skipped
+                )
+                .as_str(),
+                50,
+                20,
+            ));
+            return;
+        }
+
+        // pick a folder name for the new log that isn't already in use
+        let stem = import_filepath
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("dataset");
+        let mut new_log_dir = format!("imported-{}", stem);
+        let mut suffix = 2;
+        while log_folder_path.join(&new_log_dir).exists() {
+            new_log_dir = format!("imported-{}-{}", stem, suffix);
+            suffix += 1;
+        }
+        let new_log_folder_path = log_folder_path.join(&new_log_dir);
+        let new_log_file_path = new_log_folder_path.join(LOG_FILE_NAME);
+
+        let saved = DirBuilder::new()
+            .recursive(true)
+            .create(&new_log_folder_path)
+            .context("Attempting to create the directory for the imported chatlog")
+            .and_then(|_| new_log.save_to_json_file(&new_log_file_path));
+        if let Err(err) = saved {
+            log::error!(
+                "Failed to save the imported log file to {:?}: {}",
+                new_log_file_path,
+                err
+            );
+            return;
+        }
+
+        // refresh the list of logs so the imported one shows up
+        let new_lss = LogSelectState::new(self.character.clone(), self.config.clone());
+        self.logs_found = new_lss.logs_found;
+        self.apply_filter();
+
+        let mut message = format!(
+            "Imported {} chatlog items into '{}'.",
+            new_log.len(),
+            new_log_dir
+        );
+        if skipped > 0 {
+            message.push_str(
+                format!(
+                    " {} malformed lines were skipped; see the log for details.",
+                    skipped
+                )
+                .as_str(),
+            );
+        }
+        self.modal_messagebox = Some(MessageBoxModalWidget::new(
+            "Dataset Imported",
+            message.as_str(),
+            50,
+            20,
+        ));
+    }
+
+    // returns the index into `logs_found` for the log selected in the list, if any.
+    fn get_selected_log_index(&self) -> Option<usize> {
+        self.list_state
+            .state
+            .selected()
+            .and_then(|i| self.visible_logs.get(i).copied())
     }
 }
 
@@ -1,15 +1,19 @@
 use anyhow::{Context, Result};
 use application::Application;
+use std::io::IsTerminal;
 
 use llm_engine::{LlmEngine, LlmEngineResponse};
 use simple_logger::SimpleLogger;
 use tui::Tui;
 
 mod application;
+mod character_edit;
 mod character_select;
 mod chat;
 mod chatlog;
 mod config;
+mod first_run;
+mod headless;
 mod llm_engine;
 mod log_select;
 mod main_menu;
@@ -33,6 +37,13 @@ fn main() -> Result<()> {
             .action(clap::ArgAction::Set)
                 .value_name("FILE")
                 .help("Specifies the configuration file to load instead of config.yaml."))
+        .arg(
+            clap::Arg::new("profile")
+                .long("profile")
+                .action(clap::ArgAction::Set)
+                .value_name("FILE")
+                .help("A yaml file of settings merged on top of the configuration file, such as machine-specific overrides."),
+        )
         .arg(
             clap::Arg::new("model-file-or-name")
                 .short('m')
@@ -41,6 +52,86 @@ fn main() -> Result<()> {
                 .value_name("FILE")
                 .help("The model to load to chat with. Either configured name or filepath of the model are acceptable."),
         )
+        .arg(
+            clap::Arg::new("parameters")
+                .short('p')
+                .long("parameters")
+                .action(clap::ArgAction::Set)
+                .value_name("NAME")
+                .help("The name of the configured parameter set to start the chat with."),
+        )
+        .arg(
+            clap::Arg::new("temperature")
+                .long("temperature")
+                .action(clap::ArgAction::Set)
+                .value_name("FLOAT")
+                .value_parser(clap::value_parser!(f32))
+                .help("Overrides the temperature of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("top-k")
+                .long("top-k")
+                .action(clap::ArgAction::Set)
+                .value_name("INT")
+                .value_parser(clap::value_parser!(usize))
+                .help("Overrides the top_k of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("top-p")
+                .long("top-p")
+                .action(clap::ArgAction::Set)
+                .value_name("FLOAT")
+                .value_parser(clap::value_parser!(f32))
+                .help("Overrides the top_p of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("min-p")
+                .long("min-p")
+                .action(clap::ArgAction::Set)
+                .value_name("FLOAT")
+                .value_parser(clap::value_parser!(f32))
+                .help("Overrides the min_p of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("repeat-penalty")
+                .long("repeat-penalty")
+                .action(clap::ArgAction::Set)
+                .value_name("FLOAT")
+                .value_parser(clap::value_parser!(f32))
+                .help("Overrides the repeat_penalty of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("repeat-penalty-range")
+                .long("repeat-penalty-range")
+                .action(clap::ArgAction::Set)
+                .value_name("INT")
+                .value_parser(clap::value_parser!(usize))
+                .help("Overrides the repeat_penalty_range of the starting parameter set."),
+        )
+        .arg(
+            clap::Arg::new("prompt")
+                .long("prompt")
+                .action(clap::ArgAction::Set)
+                .value_name("TEXT")
+                .requires("character")
+                .help("Sends the text to the --character as a message, prints the response and exits without starting the interface."),
+        )
+        .arg(
+            clap::Arg::new("character")
+                .long("character")
+                .action(clap::ArgAction::Set)
+                .value_name("NAME")
+                .requires("prompt")
+                .help("The character, by name or file, that answers the --prompt."),
+        )
+        .arg(
+            clap::Arg::new("log")
+                .long("log")
+                .action(clap::ArgAction::Set)
+                .value_name("FILE")
+                .requires("prompt")
+                .help("The chatlog to send the --prompt on top of instead of an empty one; it isn't changed."),
+        )
         .arg_required_else_help(true)
         .get_matches();
 
@@ -54,12 +145,75 @@ fn main() -> Result<()> {
     // ***********************************************************************
     // load the configuration file for the application.
     let custom_config_filename: Option<&String> = cmd_arg_matches.get_one::<String>("config-file");
-    if !std::path::Path::new(custom_config_filename.unwrap()).exists() {
-        println!("The 'config.yaml' configuration file is missing. Place it next to the application or specify its location with the -c argument.");
-        std::process::exit(1);
+    let config_filepath = std::path::Path::new(custom_config_filename.unwrap());
+
+    // if there's no configuration file, walk the user through making one when running
+    // interactively. the terminal interface gets reused later so only one input thread exists.
+    let mut first_run_tui: Option<Tui> = None;
+    if !config_filepath.exists() {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            println!("The 'config.yaml' configuration file is missing. Place it next to the application or specify its location with the -c argument.");
+            std::process::exit(1);
+        }
+
+        let mut tui = Tui::new(INPUT_THREAD_READ_TIMEOUT_MS)
+            .context("failed to create the terminal interface")?;
+        let created = first_run::run_first_run_wizard(&mut tui, config_filepath, UI_DRAW_TICK_RATE)
+            .context("the first-run setup failed")?;
+        if !created {
+            println!("The first-run setup was cancelled so no configuration file was written.");
+            std::process::exit(1);
+        }
+        println!("Wrote a new configuration file to {:?}.", config_filepath);
+        first_run_tui = Some(tui);
     }
 
-    let config = config::ConfigurationFile::load_config(custom_config_filename);
+    let profile_filename: Option<&String> = cmd_arg_matches.get_one::<String>("profile");
+    let config =
+        match config::ConfigurationFile::load_config(custom_config_filename, profile_filename) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("{:#}", err);
+                println!("{:#}", err);
+                std::process::exit(1);
+            }
+        };
+
+    // ***********************************************************************
+    // pick the parameter set to start with and apply any overrides from the command line.
+    let mut startup_parameters = match cmd_arg_matches.get_one::<String>("parameters") {
+        Some(pset_name) => match config
+            .parameters
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(pset_name))
+        {
+            Some(pset) => pset.clone(),
+            None => {
+                println!(
+                    "The parameter set '{}' is not configured in 'config.yaml'.",
+                    pset_name
+                );
+                std::process::exit(1);
+            }
+        },
+        None => config.parameters.first().cloned().unwrap_or_default(),
+    };
+    let parameter_overrides = config::ParameterOverrides {
+        parameters_name: cmd_arg_matches.get_one::<String>("parameters").cloned(),
+        temperature: cmd_arg_matches.get_one::<f32>("temperature").copied(),
+        top_k: cmd_arg_matches.get_one::<usize>("top-k").copied(),
+        top_p: cmd_arg_matches.get_one::<f32>("top-p").copied(),
+        min_p: cmd_arg_matches.get_one::<f32>("min-p").copied(),
+        repeat_penalty: cmd_arg_matches.get_one::<f32>("repeat-penalty").copied(),
+        repeat_penalty_range: cmd_arg_matches
+            .get_one::<usize>("repeat-penalty-range")
+            .copied(),
+    };
+    parameter_overrides.apply(&mut startup_parameters);
+    if let Err(err) = startup_parameters.validate() {
+        println!("Invalid inference parameters were specified: {}", err);
+        std::process::exit(1);
+    }
 
     // ***********************************************************************
     // Spawn the LLM Engine thread.
@@ -70,10 +224,13 @@ fn main() -> Result<()> {
             println!("A model must be configured in 'config.yaml' before running the application.");
             std::process::exit(1);
         }
-        println!("Consider specifying a model name on the command line with the -m argument.");
-        println!(
-            "Since no model was specified, the application will load the first configured model."
-        );
+        // a one-shot prompt's output may be piped elsewhere, so it only gets the response
+        if !cmd_arg_matches.contains_id("prompt") {
+            println!("Consider specifying a model name on the command line with the -m argument.");
+            println!(
+                "Since no model was specified, the application will load the first configured model."
+            );
+        }
         config.models[0].name.as_str()
     } else {
         model_fileorname_p.unwrap()
@@ -85,42 +242,94 @@ fn main() -> Result<()> {
         .recv_on_client
         .recv()
         .expect("Main thread didn't like recv attempt for llm engine channels.");
-    if res != LlmEngineResponse::ModelLoaded {
+    if let LlmEngineResponse::ModelLoadFailed(reason) = res {
+        // the engine thread has already exited and the terminal interface isn't enabled yet,
+        // so there's nothing to clean up before quitting
+        log::error!("The startup model failed to load: {}", reason);
+        println!(
+            "The model '{}' could not be loaded: {}",
+            model_fileorname, reason
+        );
+        std::process::exit(1);
+    } else if res != LlmEngineResponse::ModelLoaded {
         log::error!(
             "First LlmEngineResponse wasn't model loaded. Suspect problems if that wasn't planned"
         )
     }
 
+    // ***********************************************************************
+    // a one-shot prompt is answered without ever starting the terminal interface.
+    if let Some(prompt) = cmd_arg_matches.get_one::<String>("prompt") {
+        let result = headless::run_one_shot_inference(
+            &engine,
+            &config,
+            &startup_parameters,
+            prompt,
+            cmd_arg_matches
+                .get_one::<String>("character")
+                .map_or("", |c| c.as_str()),
+            cmd_arg_matches.get_one::<String>("log"),
+        );
+        shutdown_engine(engine);
+        match result {
+            Ok(response) => println!("{}", response),
+            Err(err) => {
+                log::error!("The one-shot prompt failed: {:#}", err);
+                println!("{:#}", err);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
     // ***********************************************************************
     // setup the terminal and run the loop, hoping to restore terminal on exit.
-    let mut tui = Tui::new(INPUT_THREAD_READ_TIMEOUT_MS)
-        .context("failed to create the terminal interface")?;
+    let mut tui = match first_run_tui {
+        Some(tui) => tui,
+        None => Tui::new(INPUT_THREAD_READ_TIMEOUT_MS)
+            .context("failed to create the terminal interface")?,
+    };
+    if let Some([min_width, min_height]) = config.min_terminal_size {
+        tui.set_min_size(min_width, min_height);
+    }
     Tui::enable().context("should have been able to start the terminal interface")?;
 
     // **********************************************************************
     // run the actual app
-    let mut app = Application::new(&mut tui, config.clone(), engine);
+    let mut app = Application::new(
+        &mut tui,
+        config.clone(),
+        custom_config_filename.cloned(),
+        profile_filename.cloned(),
+        startup_parameters,
+        parameter_overrides,
+        engine,
+    );
     if let Err(err) = app.run(UI_DRAW_TICK_RATE) {
         log::error!("Application loop failed: {err}")
     }
 
     // *******************************************************************
     // tell the server to shut down ... and try to wait for it to happen.
-    let shutdown_req_result = app
-        .engine
+    shutdown_engine(app.engine);
+
+    // ***************************************************************
+    // restore the terminal now that the application is quitting.
+    Tui::disable().context("failed to disable the terminal interface")?;
+
+    Ok(())
+}
+
+// tells the LLM engine thread to shut down and waits for it to happen.
+fn shutdown_engine(engine: LlmEngine) {
+    let shutdown_req_result = engine
         .send_to_server
         .try_send(llm_engine::LlmEngineRequest::ImmediateShutdown);
     if shutdown_req_result.is_ok() {
         // the request went through so wait for the thread to close.
         // this avoids segfaulting on exit.
-        let _ = app.engine.handle.join();
+        let _ = engine.handle.join();
     } else if let Err(err) = shutdown_req_result {
         log::error!("Failed to shutdown the LLM server thread: {err}");
     }
-
-    // ***************************************************************
-    // restore the terminal now that the application is quitting.
-    Tui::disable().context("failed to disable the terminal interface")?;
-
-    Ok(())
 }
@@ -6,22 +6,70 @@ use ratatui::{
     widgets::Paragraph,
 };
 
-use crate::tui::{Frame, ProcessInputResult, TerminalEvent, TerminalRenderable};
+use crate::tui::{
+    ConfirmModalWidget, Frame, ProcessInputResult, TerminalEvent, TerminalRenderable,
+};
+
+pub struct MainMenuState {
+    // if true, quitting asks for a confirmation first
+    confirm_on_quit: bool,
+
+    // the open confirmation for quitting the app
+    quit_confirm: Option<ConfirmModalWidget>,
 
-#[derive(Default)]
-pub struct MainMenuState {}
+    // a line shown under the menu, such as the result of reloading the configuration
+    status: Option<String>,
+}
+impl MainMenuState {
+    pub fn new(confirm_on_quit: bool) -> Self {
+        Self {
+            confirm_on_quit,
+            quit_confirm: None,
+            status: None,
+        }
+    }
+
+    // picks up the settings the main menu uses from a reloaded configuration
+    pub fn apply_config(&mut self, confirm_on_quit: bool) {
+        self.confirm_on_quit = confirm_on_quit;
+    }
+
+    pub fn show_status(&mut self, text: &str) {
+        self.status = Some(text.to_owned());
+    }
+}
 impl TerminalRenderable for MainMenuState {
     fn process_input(&mut self, event: TerminalEvent) -> ProcessInputResult {
+        if let Some(confirm) = self.quit_confirm.as_mut() {
+            confirm.process_input(event);
+            if confirm.is_finished {
+                let confirmed = confirm.is_confirmed;
+                self.quit_confirm = None;
+                if confirmed {
+                    return ProcessInputResult::Quit;
+                }
+            }
+            return ProcessInputResult::None;
+        }
+
         match event {
             TerminalEvent::Key(key) => {
+                self.status = None;
                 if key.code == KeyCode::Char('q') {
-                    return ProcessInputResult::Quit;
+                    if !self.confirm_on_quit {
+                        return ProcessInputResult::Quit;
+                    }
+                    self.quit_confirm =
+                        Some(ConfirmModalWidget::new("Quit", "Quit Sentient Core?", 40));
                 }
                 if key.code == KeyCode::Char('c') {
                     return ProcessInputResult::ChangeScene(
                         crate::application::ApplicationState::CharacterSelect,
                     );
                 }
+                if key.code == KeyCode::Char('r') {
+                    return ProcessInputResult::ReloadConfig;
+                }
             }
             _ => {}
         }
@@ -30,13 +78,18 @@ impl TerminalRenderable for MainMenuState {
     }
 
     fn render(&mut self, frame: &mut Frame) {
-        let main_title_seq = vec![
+        let mut main_title_seq = vec![
             Line::from("Sentient Core".bold()),
             Line::from("-------------"),
             Line::from("(c)hat"),
+            Line::from("(r)eload config"),
             Line::from(""),
             Line::from("(q)uit"),
         ];
+        if let Some(status) = &self.status {
+            main_title_seq.push(Line::from(""));
+            main_title_seq.push(Line::from(status.as_str().italic()));
+        }
 
         let hchunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -57,5 +110,9 @@ impl TerminalRenderable for MainMenuState {
 
         let title = Paragraph::new(main_title_seq).alignment(Alignment::Center);
         frame.render_widget(title, vchunks[1]);
+
+        if let Some(confirm) = &self.quit_confirm {
+            confirm.render(frame);
+        }
     }
 }
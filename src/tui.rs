@@ -9,13 +9,14 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
-    prelude::{Constraint, CrosstermBackend, Direction, Layout, Rect},
+    prelude::{Alignment, Constraint, CrosstermBackend, Direction, Layout, Rect},
     style::{Color, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, ListState, Paragraph},
+    widgets::{Block, Borders, Clear, ListState, Paragraph, Wrap},
     Terminal,
 };
 use std::{
+    cell::Cell,
     io, panic, thread,
     time::{Duration, Instant},
 };
@@ -36,6 +37,9 @@ pub enum ProcessInputResult {
 
     // user has requested a scene change
     ChangeScene(ApplicationState),
+
+    // user has requested the configuration file be read again
+    ReloadConfig,
 }
 
 /// Both the event pump and the thin wrapper are reskins of the code fround in the Ratatui Book:
@@ -54,12 +58,14 @@ pub trait TerminalRenderable {
 }
 
 // A type encapsulating all the terminal events we wish to capture and report.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum TerminalEvent {
     // terminal tick
     Tick,
     // key press
     Key(CrosstermKeyEvent),
+    // text pasted into the terminal all at once, with bracketed paste enabled
+    Paste(String),
     // mouse click or scroll,
     Mouse(CrosstermMouseEvent),
     // terminal resize
@@ -107,11 +113,11 @@ impl TerminalEventHandler {
                             CrosstermEvent::Resize(w, h) => {
                                 sender.send(TerminalEvent::Resize(w, h))
                             }
+                            CrosstermEvent::Paste(text) => sender.send(TerminalEvent::Paste(text)),
 
                             // ignore the rest
                             CrosstermEvent::FocusGained => Ok(()),
                             CrosstermEvent::FocusLost => Ok(()),
-                            CrosstermEvent::Paste(_) => Ok(()),
                         }
                         .expect("failed to pass on the detected terminal event")
                     }
@@ -160,6 +166,10 @@ pub struct Tui {
 
     // how frequently the input should be polled
     input_tick_rate_ms: u64,
+
+    // the smallest (width, height) the terminal can be before only a message asking for
+    // a bigger terminal gets drawn instead of the user interface
+    min_size: (u16, u16),
 }
 impl Tui {
     // creates a new terminal interface that encapsulates the terminal ui backend
@@ -179,14 +189,22 @@ impl Tui {
             terminal,
             events,
             input_tick_rate_ms,
+            min_size: (DEFAULT_MIN_TERMINAL_WIDTH, DEFAULT_MIN_TERMINAL_HEIGHT),
         })
     }
 
+    // changes the smallest terminal size the user interface gets drawn for
+    pub fn set_min_size(&mut self, width: u16, height: u16) {
+        self.min_size = (width, height);
+    }
+
     // enables the terminal interface
     pub fn enable() -> Result<()> {
         enable_raw_mode().context("Failed to enable raw mode")?;
         execute!(io::stdout(), crossterm::terminal::EnterAlternateScreen)
             .context("unable to enter alternate screen")?;
+        execute!(io::stdout(), crossterm::event::EnableBracketedPaste)
+            .context("unable to enable bracketed paste")?;
 
         Ok(())
     }
@@ -194,6 +212,8 @@ impl Tui {
     // disables the terminal interface
     pub fn disable() -> Result<()> {
         disable_raw_mode().context("failed to disable raw mode")?;
+        execute!(io::stdout(), crossterm::event::DisableBracketedPaste)
+            .context("unable to disable bracketed paste")?;
         execute!(io::stdout(), crossterm::terminal::LeaveAlternateScreen)
             .context("unable to switch to main screen")?;
 
@@ -202,7 +222,23 @@ impl Tui {
 
     // draws the given frame to the terminal backend
     pub fn draw<T: TerminalRenderable>(&mut self, b: &mut T) -> Result<()> {
-        self.terminal.draw(|frame| b.render(frame))?;
+        let (min_width, min_height) = self.min_size;
+        self.terminal.draw(|frame| {
+            // the layout math falls apart in tiny areas, so just ask for more room
+            let area = frame.size();
+            if area.width < min_width || area.height < min_height {
+                let message = format!(
+                    "The terminal is too small ({}x{}). Please make it at least {}x{}.",
+                    area.width, area.height, min_width, min_height
+                );
+                let p = Paragraph::new(message)
+                    .alignment(Alignment::Center)
+                    .wrap(Wrap { trim: true });
+                frame.render_widget(p, area);
+            } else {
+                b.render(frame);
+            }
+        })?;
         Ok(())
     }
 
@@ -220,7 +256,23 @@ impl Tui {
     }
 }
 
+// The default smallest terminal size, in columns and rows, that the user interface gets drawn for.
+pub const DEFAULT_MIN_TERMINAL_WIDTH: u16 = 40;
+pub const DEFAULT_MIN_TERMINAL_HEIGHT: u16 = 10;
+
+// Terminals narrower than this many columns get the compact layout, where the chatlog
+// and modal dialogs use the full width instead of leaving margins around them.
+pub const COMPACT_LAYOUT_MAX_WIDTH: u16 = 100;
+
+// returns true if the area is narrow enough that the compact layout should be used.
+pub fn is_compact_layout(r: Rect) -> bool {
+    r.width < COMPACT_LAYOUT_MAX_WIDTH
+}
+
+// returns a rectangle centered in `r` that takes up the given percentages of it.
+// in the compact layout the rectangle always gets the full width.
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let percent_x = if is_compact_layout(r) { 100 } else { percent_x };
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -340,6 +392,9 @@ impl TextEditingBlockModalWidget {
                 }
                 _ => {}
             }
+        } else if let TerminalEvent::Paste(pasted) = event {
+            self.text
+                .push_str(&pasted.replace("\r\n", "\n").replace('\r', "\n"));
         }
     }
 
@@ -347,13 +402,15 @@ impl TextEditingBlockModalWidget {
         let mut area = centered_rect(80, 60, frame.size());
 
         // get the width to split the text by so that there's nice word wrapping
-        let split_width = (area.width - 2) as usize;
+        let split_width = area.width.saturating_sub(2) as usize;
 
         let mut editing_lines = vec![];
         if !self.text.is_empty() {
-            let split_lines = slice_up_string(&self.text, split_width, 0);
-            for split_line in split_lines {
-                editing_lines.push(Line::from(split_line));
+            for text_line in self.text.split('\n') {
+                let split_lines = slice_up_string(text_line, split_width, 0);
+                for split_line in split_lines {
+                    editing_lines.push(Line::from(split_line));
+                }
             }
         } else {
             editing_lines.push(Line::from(vec![Span::styled(
@@ -377,7 +434,8 @@ impl TextEditingBlockModalWidget {
     }
 }
 
-// A basic modal dialog box with a configurable title and body text.
+// A basic modal dialog box with a configurable title and body text. Text that doesn't fit
+// can be scrolled with 'j'/'k' or the arrow keys.
 pub struct MessageBoxModalWidget {
     // the title of the border on the modal box
     pub title: String,
@@ -393,6 +451,12 @@ pub struct MessageBoxModalWidget {
 
     // the percentage of screen height to take up at max
     pub height_pct: u16,
+
+    // the number of lines scrolled down from the top of the text
+    scroll: u16,
+
+    // the furthest the text can be scrolled, as of the last render
+    max_scroll: Cell<u16>,
 }
 impl MessageBoxModalWidget {
     pub fn new(title: &str, text: &str, width_pct: u16, height_pct: u16) -> Self {
@@ -402,6 +466,8 @@ impl MessageBoxModalWidget {
             is_finished: false,
             width_pct,
             height_pct,
+            scroll: 0,
+            max_scroll: Cell::new(0),
         }
     }
 
@@ -414,6 +480,12 @@ impl MessageBoxModalWidget {
                 KeyCode::Enter => {
                     self.is_finished = true;
                 }
+                KeyCode::Char('j') | KeyCode::Down => {
+                    self.scroll = (self.scroll + 1).min(self.max_scroll.get());
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    self.scroll = self.scroll.saturating_sub(1);
+                }
                 _ => {}
             }
         }
@@ -423,7 +495,7 @@ impl MessageBoxModalWidget {
         let mut area = centered_rect(self.width_pct, self.height_pct, frame.size());
 
         // get the width to split the text by so that there's nice word wrapping
-        let split_width = (area.width - 2) as usize;
+        let split_width = area.width.saturating_sub(2) as usize;
 
         let mut msgbox_lines = vec![];
         if !self.text.is_empty() {
@@ -439,13 +511,93 @@ impl MessageBoxModalWidget {
 
         // make size the box to the number of lines + 1, accounting for the border
         area.height = std::cmp::min(area.height, 2 + msgbox_lines.len() as u16);
+        let max_scroll = (msgbox_lines.len() as u16).saturating_sub(area.height.saturating_sub(2));
+        self.max_scroll.set(max_scroll);
+
+        let textarea = Paragraph::new(msgbox_lines)
+            .style(Style::default())
+            .scroll((self.scroll.min(max_scroll), 0))
+            .block(
+                Block::default()
+                    .border_style(Style::default().fg(Color::Cyan))
+                    .title(self.title.as_str())
+                    .borders(Borders::ALL),
+            );
 
-        let textarea = Paragraph::new(msgbox_lines).style(Style::default()).block(
-            Block::default()
-                .border_style(Style::default().fg(Color::Cyan))
-                .title(self.title.as_str())
-                .borders(Borders::ALL),
-        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(textarea, area);
+    }
+}
+
+// a modal box asking a yes or no question. 'y' or enter confirms, 'n' or esc declines.
+pub struct ConfirmModalWidget {
+    // the title of the border on the modal box
+    pub title: String,
+
+    // the question being asked
+    pub text: String,
+
+    // should be set to true after `process_input()` when the user has answered
+    pub is_finished: bool,
+
+    // true if the user answered yes
+    pub is_confirmed: bool,
+
+    // the percentage of screen width to take up at max
+    pub width_pct: u16,
+}
+impl ConfirmModalWidget {
+    pub fn new(title: &str, text: &str, width_pct: u16) -> Self {
+        Self {
+            title: title.to_string(),
+            text: text.to_string(),
+            is_finished: false,
+            is_confirmed: false,
+            width_pct,
+        }
+    }
+
+    pub fn process_input(&mut self, event: TerminalEvent) {
+        if let TerminalEvent::Key(key) = event {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                    self.is_confirmed = true;
+                    self.is_finished = true;
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    self.is_confirmed = false;
+                    self.is_finished = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let mut area = centered_rect(self.width_pct, 50, frame.size());
+        let split_width = area.width.saturating_sub(2) as usize;
+
+        let mut confirm_lines: Vec<Line> = slice_up_string(&self.text, split_width, 0)
+            .into_iter()
+            .map(Line::from)
+            .collect();
+        confirm_lines.push(Line::from(""));
+        confirm_lines.push(Line::from(Span::styled(
+            "(y)es / (n)o",
+            Style::default().fg(Color::Rgb(100, 100, 100)),
+        )));
+
+        // size the box to the lines, accounting for the border
+        area.height = std::cmp::min(area.height, 2 + confirm_lines.len() as u16);
+
+        let textarea = Paragraph::new(confirm_lines)
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .border_style(Style::default().fg(Color::Cyan))
+                    .title(self.title.as_str())
+                    .borders(Borders::ALL),
+            );
 
         frame.render_widget(Clear, area);
         frame.render_widget(textarea, area);
@@ -466,7 +618,8 @@ pub fn slice_up_string(
 ) -> Vec<String> {
     // we start the current max_limit off lower, potentially, and then
     // return it to max_width after the first string has been split.
-    let mut current_max_limit = max_width - leading_space_reserve;
+    let max_width = max_width.max(1);
+    let mut current_max_limit = max_width.saturating_sub(leading_space_reserve).max(1);
 
     // return the slice if not necessary to split
     let source_width = UnicodeWidthStr::width(source);
@@ -561,3 +714,34 @@ pub fn slice_up_string(
 
     result
 }
+
+// finds where the byte offset `source_offset` into `source` ends up in `split_lines`, the
+// result of `slice_up_string()` for it, as the index of the split line and the byte offset
+// into it. whitespace dropped where the string was wrapped maps to the start of the next line
+// and an offset past the end of `source` maps to the end of the last line.
+pub fn find_wrapped_position(
+    source: &str,
+    split_lines: &[String],
+    source_offset: usize,
+) -> (usize, usize) {
+    let mut source_chars = source.char_indices();
+    for (split_index, split_line) in split_lines.iter().enumerate() {
+        for (split_offset, split_char) in split_line.char_indices() {
+            // skip past the characters that didn't make it into the split lines
+            loop {
+                match source_chars.next() {
+                    Some((offset, _)) if offset >= source_offset => {
+                        return (split_index, split_offset)
+                    }
+                    Some((_, c)) if c == split_char => break,
+                    Some(_) => {}
+                    None => return (split_index, split_offset),
+                }
+            }
+        }
+    }
+    match split_lines.last() {
+        Some(last) => (split_lines.len() - 1, last.len()),
+        None => (0, 0),
+    }
+}
@@ -1,5 +1,11 @@
 use anyhow::{Context, Error as E, Result};
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::File,
+    hash::{Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use candle_core::Tensor;
 use candle_transformers::models::bert::{BertModel, Config, DTYPE};
@@ -10,10 +16,19 @@ use crate::{
     config::ConfiguredEmbeddingModel,
 };
 
+// the most chunks of chatlog text that get run through the embedding model at once
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+// the extension given to the file kept next to a chatlog that caches its vector embeddings
+const EMBEDDINGS_CACHE_EXTENSION: &str = "embeddings.safetensors";
+
 pub struct VectorEmbeddingEngine {
     model: BertModel,
     tokenizer: Tokenizer,
     config: ConfiguredEmbeddingModel,
+
+    // describes the device the model ended up on, such as "CUDA device 0" or "CPU"
+    device_name: String,
 }
 impl VectorEmbeddingEngine {
     // creates a new VectorEmbedingEngine and gets it ready to generate embeddings.
@@ -26,10 +41,37 @@ impl VectorEmbeddingEngine {
         //emb_model_dir: &str, token_cutoff_limit: usize
         let emb_model_dir = &emb_config.dir_path;
 
-        let device = if emb_config.use_cpu {
-            candle_core::Device::Cpu
+        // use the configured gpu if possible, then the first one and then the cpu if there's no
+        // working CUDA device, such as on a laptop built with the feature but without a gpu
+        let (device, device_name) = if emb_config.use_cpu {
+            (candle_core::Device::Cpu, "CPU".to_owned())
         } else {
-            candle_core::Device::new_cuda(0).unwrap()
+            let device_index = emb_config.gpu_device_index.unwrap_or(0);
+            match candle_core::Device::new_cuda(device_index) {
+                Ok(device) => (device, format!("CUDA device {}", device_index)),
+                Err(err) => {
+                    log::warn!(
+                        "Unable to use CUDA device {} for the embedding model: {}",
+                        device_index,
+                        err
+                    );
+                    let first_device = if device_index != 0 {
+                        candle_core::Device::new_cuda(0).ok()
+                    } else {
+                        None
+                    };
+                    match first_device {
+                        Some(device) => {
+                            log::warn!("Falling back to CUDA device 0 for the embedding model.");
+                            (device, "CUDA device 0".to_owned())
+                        }
+                        None => {
+                            log::warn!("Falling back to the CPU for the embedding model.");
+                            (candle_core::Device::Cpu, "CPU".to_owned())
+                        }
+                    }
+                }
+            }
         };
 
         let config_filename = format!("{}/config.json", emb_model_dir);
@@ -76,18 +118,29 @@ impl VectorEmbeddingEngine {
             model,
             tokenizer,
             config: emb_config.clone(),
+            device_name,
         })
     }
 
+    // returns a description of the device the embedding model is running on, which can be
+    // the CPU even when a gpu was configured if CUDA couldn't be used.
+    pub fn get_device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    // builds the embeddings for the chatlog items and returns the number of items that
+    // had embeddings built.
     pub fn build_all_vector_embeddings(
         &self,
         // the chatlog to build embeddings for
         chatlog: &mut ChatLog,
         // if false it will skip chatlogitems with non-empty embedding vectors
         force_recalculation: bool,
-    ) {
-        // let mut chatlog_embeddings: Vec<Tensor> = Vec::new();
+    ) -> usize {
+        // gather up the chunks of every item that needs embeddings so they can be built in batches
         let device = &self.model.device;
+        let mut built_count = 0;
+        let mut pending_chunks: Vec<(usize, String)> = Vec::new();
         for i in 0..chatlog.len() {
             let chatlogitem: &mut ChatLogItem = chatlog.get_mut(i).unwrap();
             // if we're not forcing recalculation and we already have embeddings, move on...
@@ -126,36 +179,139 @@ impl VectorEmbeddingEngine {
             // any remaining buffer gets turned into a chunk
             chunked_line.push(buffer);
 
-            // now we go through and make embeddings for each chunk
-            let embedding_encode_pretext = match &self.config.encode_pretext {
-                Some(s) => s.as_str(),
-                None => "",
-            };
             chatlogitem.embeddings.clear();
-            for line in &chunked_line {
-                match generate_vector_embedding(
-                    device,
-                    &self.model,
-                    &self.tokenizer,
-                    embedding_encode_pretext,
-                    line,
-                ) {
-                    Ok(embedding) => {
+            built_count += 1;
+            pending_chunks.extend(chunked_line.into_iter().map(|line| (i, line)));
+        }
+
+        // now make the embeddings for the chunks, a batch at a time
+        let embedding_encode_pretext = match &self.config.encode_pretext {
+            Some(s) => s.as_str(),
+            None => "",
+        };
+        for batch in pending_chunks.chunks(EMBEDDING_BATCH_SIZE) {
+            let lines: Vec<&str> = batch.iter().map(|(_, line)| line.as_str()).collect();
+            match generate_vector_embeddings_batch(
+                device,
+                &self.model,
+                &self.tokenizer,
+                embedding_encode_pretext,
+                &lines,
+            ) {
+                Ok(embeddings) => {
+                    for ((i, _), embedding) in batch.iter().zip(embeddings) {
                         log::trace!(
                             "Loaded and encoded sentence {i} (shape {:?})...",
                             embedding.shape()
                         );
-                        chatlogitem.embeddings.push(embedding);
-                    }
-                    Err(err) => {
-                        log::error!(
-                            "Failed to encode vector embeddings for sentence {i}: {}",
-                            err
-                        );
+                        chatlog.get_mut(*i).unwrap().embeddings.push(embedding);
                     }
                 }
+                Err(err) => {
+                    log::error!(
+                        "Failed to encode vector embeddings for a batch of {} sentences: {}",
+                        batch.len(),
+                        err
+                    );
+                }
             }
         }
+
+        built_count
+    }
+
+    // fills in the embeddings of chatlog items that don't have any from the cache file next
+    // to the chatlog, for the items whose text hasn't changed since the cache was saved.
+    // returns the number of items that got their embeddings from the cache.
+    pub fn load_cached_embeddings(&self, chatlog: &mut ChatLog) -> usize {
+        let Some(cache_path) = self.get_embeddings_cache_path(chatlog) else {
+            return 0;
+        };
+        if !cache_path.exists() {
+            return 0;
+        }
+        let cached = match candle_core::safetensors::load(&cache_path, &self.model.device) {
+            Ok(cached) => cached,
+            Err(err) => {
+                log::warn!(
+                    "Unable to read the cached embeddings from {:?}, so they'll be built again: {}",
+                    cache_path,
+                    err
+                );
+                return 0;
+            }
+        };
+
+        let mut restored_count = 0;
+        for i in 0..chatlog.len() {
+            let chatlogitem: &mut ChatLogItem = chatlog.get_mut(i).unwrap();
+            if !chatlogitem.embeddings.is_empty() {
+                continue;
+            }
+            let text_hash = self.hash_text_for_cache(&chatlogitem.get_name_and_items_as_string());
+            let mut chunk = 0;
+            while let Some(embedding) = cached.get(&get_cache_key(i, chunk, text_hash)) {
+                chatlogitem.embeddings.push(embedding.clone());
+                chunk += 1;
+            }
+            if chunk > 0 {
+                restored_count += 1;
+            }
+        }
+        log::debug!(
+            "Loaded the cached embeddings for {} chatlog items from {:?}",
+            restored_count,
+            cache_path
+        );
+
+        restored_count
+    }
+
+    // writes the embeddings of every chatlog item to the cache file next to the chatlog,
+    // replacing what was there.
+    pub fn save_cached_embeddings(&self, chatlog: &ChatLog) {
+        let Some(cache_path) = self.get_embeddings_cache_path(chatlog) else {
+            return;
+        };
+        let mut tensors = HashMap::new();
+        for (i, chatlogitem) in chatlog.iter().enumerate() {
+            let text_hash = self.hash_text_for_cache(&chatlogitem.get_name_and_items_as_string());
+            for (chunk, embedding) in chatlogitem.embeddings.iter().enumerate() {
+                tensors.insert(get_cache_key(i, chunk, text_hash), embedding.clone());
+            }
+        }
+        if tensors.is_empty() {
+            return;
+        }
+        if let Err(err) = candle_core::safetensors::save(&tensors, &cache_path) {
+            log::error!(
+                "Failed to save the cached embeddings to {:?}: {}",
+                cache_path,
+                err
+            );
+        }
+    }
+
+    // returns the path of the embeddings cache file for the chatlog, or None if it hasn't
+    // been saved to a file yet or caching is turned off.
+    fn get_embeddings_cache_path(&self, chatlog: &ChatLog) -> Option<PathBuf> {
+        if !self.config.cache_embeddings.unwrap_or(true) {
+            return None;
+        }
+        chatlog
+            .get_last_used_filepath()
+            .map(|fp| fp.with_extension(EMBEDDINGS_CACHE_EXTENSION))
+    }
+
+    // hashes the text of a chatlog item along with the embedding settings that change what
+    // gets built for it, so cached embeddings are only used if they'd be built the same way.
+    fn hash_text_for_cache(&self, text: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.config.dir_path.hash(&mut hasher);
+        self.config.token_cutoff_limit.hash(&mut hasher);
+        self.config.encode_pretext.hash(&mut hasher);
+        text.hash(&mut hasher);
+        hasher.finish()
     }
 
     // returns the number of requested similarities, if possible, as a vector of tuples
@@ -233,6 +389,12 @@ impl VectorEmbeddingEngine {
     }
 }
 
+// builds the name an embedding is stored under in the cache file: the chatlog item's index,
+// which of its chunks it is and the hash of its text.
+fn get_cache_key(index: usize, chunk: usize, text_hash: u64) -> String {
+    format!("{}.{}.{:016x}", index, chunk, text_hash)
+}
+
 // generates a vector embedding Tensor with the device, model and tokenizer passed in for the text specified.
 fn generate_vector_embedding(
     device: &candle_core::Device,
@@ -263,6 +425,50 @@ fn generate_vector_embedding(
     Ok(embedding)
 }
 
+// generates a vector embedding Tensor for each of the texts specified, running them through the
+// model together. the tokenizer pads the batch to the longest text and the padding is left out
+// of the mean so that each embedding comes out close to what `generate_vector_embedding` makes.
+fn generate_vector_embeddings_batch(
+    device: &candle_core::Device,
+    model: &BertModel,
+    tokenizer: &Tokenizer,
+    embedding_pretext: &str,
+    texts: &[&str],
+) -> Result<Vec<Tensor>> {
+    // prepend a directive, if appropriate for the embedding model
+    let embedding_texts: Vec<String> = texts
+        .iter()
+        .map(|text| [embedding_pretext, text].concat())
+        .collect();
+
+    let encodings = tokenizer
+        .encode_batch(embedding_texts, true)
+        .map_err(E::msg)?;
+    let token_ids = encodings
+        .iter()
+        .map(|encoding| Tensor::new(encoding.get_ids(), device))
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let attention_mask = encodings
+        .iter()
+        .map(|encoding| Tensor::new(encoding.get_attention_mask(), device))
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let token_ids = Tensor::stack(&token_ids, 0)?;
+    let attention_mask = Tensor::stack(&attention_mask, 0)?
+        .to_dtype(DTYPE)?
+        .unsqueeze(2)?;
+    let token_type_ids = token_ids.zeros_like()?;
+    let ys = model.forward(&token_ids, &token_type_ids)?;
+
+    // avg-pool each sentence by taking the mean embedding value of its tokens, without the padding
+    let summed = ys.broadcast_mul(&attention_mask)?.sum(1)?;
+    let token_counts = attention_mask.sum(1)?;
+    let embeddings = summed.broadcast_div(&token_counts)?;
+
+    (0..texts.len())
+        .map(|i| embeddings.get(i).map_err(E::from))
+        .collect()
+}
+
 // calculates the cosine similarity between two vector embedding Tensors
 fn vector_embedding_cosine_similarity(first: &Tensor, second: &Tensor) -> Result<f32> {
     let sum_ij = (second * first)?.sum_all()?.to_scalar::<f32>()?;
@@ -271,3 +477,29 @@ fn vector_embedding_cosine_similarity(first: &Tensor, second: &Tensor) -> Result
 
     Ok(sum_ij / (sum_i2 * sum_j2).sqrt())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_similarity(first: &[f32], second: &[f32], expected: f32) {
+        let device = candle_core::Device::Cpu;
+        let first = Tensor::new(first, &device).unwrap();
+        let second = Tensor::new(second, &device).unwrap();
+        let similarity = vector_embedding_cosine_similarity(&first, &second).unwrap();
+        assert!(
+            (similarity - expected).abs() < 1e-6,
+            "expected {} but got {}",
+            expected,
+            similarity
+        );
+    }
+
+    #[test]
+    fn cosine_similarity_matches_hand_computed_values() {
+        assert_similarity(&[1.0, 0.0], &[1.0, 1.0], 1.0 / 2.0f32.sqrt());
+        assert_similarity(&[3.0, 4.0], &[4.0, 3.0], 0.96);
+        assert_similarity(&[1.0, 2.0], &[2.0, 4.0], 1.0);
+        assert_similarity(&[1.0, 0.0], &[0.0, 1.0], 0.0);
+    }
+}